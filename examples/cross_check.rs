@@ -0,0 +1,60 @@
+//! Runs an RLE pattern with two unbounded engines, brute force and HashLife,
+//! and checks that they agree.
+//!
+//! ```sh
+//! cargo run --example cross_check -- pattern.rle 1000
+//! ```
+
+extern crate failure;
+use failure::{err_msg, Error};
+
+extern crate game_of_life_cluster;
+use game_of_life_cluster::{Backend, Pattern, Rule};
+
+use std::env;
+use std::process;
+
+/// The R-pentomino, which stabilizes after 1103 generations.
+const R_PENTOMINO: &str = "x = 3, y = 3\nb2o$2ob$bo!";
+
+fn run() -> Result<(), Error> {
+  let mut args = env::args().skip(1);
+  let pattern = match args.next() {
+    Some(path) => Pattern::open(path)?,
+    None => R_PENTOMINO.parse()?,
+  };
+  let generations = match args.next() {
+    Some(generations) => generations.parse()?,
+    None => 1103,
+  };
+  let rule = pattern.rule.unwrap_or_else(Rule::default);
+
+  let mut results = Vec::new();
+  for &backend in &[Backend::Unbounded, Backend::Hashlife] {
+    let mut engine = backend.create(rule)?;
+    pattern.load_into(&mut *engine)?;
+    engine.step(generations)?;
+
+    let (hash, population) = (engine.hash()?, engine.population()?);
+    println!(
+      "{}: generation #{}, population {}, hash {:016x}",
+      backend,
+      engine.generation(),
+      population,
+      hash
+    );
+    results.push((hash, population));
+  }
+
+  if results[0] != results[1] {
+    return Err(err_msg("the engines disagree"));
+  }
+  Ok(())
+}
+
+fn main() {
+  if let Err(error) = run() {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
+}
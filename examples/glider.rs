@@ -0,0 +1,32 @@
+//! Flies a glider around a torus with the threaded engine and prints where it
+//! is every few generations.
+
+extern crate game_of_life_cluster;
+use game_of_life_cluster::threaded::{scheduler::Scheduling, ThreadedEngine};
+use game_of_life_cluster::{Engine, Pattern, Rule};
+
+const SIZE: usize = 16;
+const GLIDER: &str = "x = 3, y = 3\nbob$2bo$3o!";
+
+fn main() {
+  let rule: Rule = format!("B3/S23:T{},{}", SIZE, SIZE).parse().unwrap();
+  let glider: Pattern = GLIDER.parse().unwrap();
+
+  let mut world = Pattern::new(SIZE, SIZE);
+  world.paste(&glider, 1, 1);
+  world.rule = Some(rule);
+
+  let mut engine = ThreadedEngine::new(rule, 2, Scheduling::Dynamic);
+  world.load_into(&mut engine).unwrap();
+
+  // a glider moves by one cell diagonally every 4 generations, so it's back
+  // where it started after crossing the whole torus
+  while engine.generation() < 4 * SIZE as u64 {
+    engine.step(16).unwrap();
+    let current = Pattern::read(&engine, 0, 0, SIZE, SIZE).unwrap();
+    println!("generation #{}:\n{}", engine.generation(), current);
+  }
+
+  let last = Pattern::read(&engine, 0, 0, SIZE, SIZE).unwrap();
+  assert_eq!(last.cells, world.cells);
+}
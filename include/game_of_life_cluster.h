@@ -0,0 +1,140 @@
+#ifndef GAME_OF_LIFE_CLUSTER_H
+#define GAME_OF_LIFE_CLUSTER_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Result of functions which can fail.
+ */
+typedef enum GolStatus {
+  GOL_STATUS_OK = 0,
+  /*
+   See `gol_last_error`.
+   */
+  GOL_STATUS_ERROR = -1,
+} GolStatus;
+
+/*
+ A world computed by one of the engines.
+ */
+typedef struct GolWorld GolWorld;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Returns the message of the last error on this thread, or `NULL` if nothing
+ has failed yet. The message is valid until the next error.
+ */
+const char *gol_last_error(void);
+
+/*
+ Creates an empty world computed by `backend` (`"threaded"`, `"gpu"`,
+ `"unbounded"`, `"hashlife"` or `"cluster"`) with `rule`, e.g. `"B3/S23"`.
+ `NULL` arguments select the threaded backend and Conway's Game of Life.
+
+ Returns `NULL` on errors. The world must be freed with `gol_world_free`.
+
+ # Safety
+
+ Non-`NULL` arguments must be NUL-terminated strings.
+ */
+struct GolWorld *gol_world_new(const char *backend, const char *rule);
+
+/*
+ Frees a world, `NULL` is ignored.
+
+ # Safety
+
+ `world` must have been created by `gol_world_new` and not freed yet.
+ */
+void gol_world_free(struct GolWorld *world);
+
+/*
+ Replaces the rule, which clears the world.
+
+ # Safety
+
+ `world` must be a live world and `rule` a NUL-terminated string.
+ */
+enum GolStatus gol_world_set_rule(struct GolWorld *world, const char *rule);
+
+/*
+ Replaces the world with a pattern in the RLE format and resets the
+ generation to 0. The rule of the pattern, if it has one, replaces the
+ rule of the world. Patterns are centered in bounded grids, otherwise
+ their top left corner is at `(0, 0)`.
+
+ # Safety
+
+ `world` must be a live world and `rle` a NUL-terminated string.
+ */
+enum GolStatus gol_world_load_rle(struct GolWorld *world, const char *rle);
+
+/*
+ Computes the next `generations` generations.
+
+ # Safety
+
+ `world` must be a live world.
+ */
+enum GolStatus gol_world_step(struct GolWorld *world, uint64_t generations);
+
+/*
+ Returns the number of generations computed since the last load, or 0 if
+ `world` is `NULL`.
+
+ # Safety
+
+ `world` must be a live world or `NULL`.
+ */
+uint64_t gol_world_generation(const struct GolWorld *world);
+
+/*
+ Copies the states of the cells of a region into `buffer` row by row, which
+ must have room for `width * height` cells. Dead cells are 0, alive ones 1
+ and dying cells of "Generations" rules 2 and higher.
+
+ # Safety
+
+ `world` must be a live world and `buffer` must point to `width * height`
+ writable bytes.
+ */
+enum GolStatus gol_world_read_region(const struct GolWorld *world,
+                                     int64_t x,
+                                     int64_t y,
+                                     size_t width,
+                                     size_t height,
+                                     uint8_t *buffer);
+
+/*
+ Stores the number of alive cells in `population`.
+
+ # Safety
+
+ `world` must be a live world and `population` writable.
+ */
+enum GolStatus gol_world_population(const struct GolWorld *world, uint64_t *population);
+
+/*
+ Stores the hash of the world in `hash`, which is the same for every
+ backend and the `verify` subcommand.
+
+ # Safety
+
+ `world` must be a live world and `hash` writable.
+ */
+enum GolStatus gol_world_hash(const struct GolWorld *world, uint64_t *hash);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* GAME_OF_LIFE_CLUSTER_H */
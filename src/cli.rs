@@ -1,4 +1,12 @@
 use clap;
+use std::str::FromStr;
+
+use game_of_life_cluster::engine::Backend;
+use game_of_life_cluster::gpu::device::{DeviceSelection, Selector};
+use game_of_life_cluster::hashlife::universe::MAX_STEP_LOG2;
+use game_of_life_cluster::rule::{self, Rule};
+use game_of_life_cluster::slave;
+use game_of_life_cluster::threaded::{self, scheduler::Scheduling};
 
 const APP_NAME: &str = env!("CARGO_PKG_NAME");
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -7,58 +15,211 @@ const APP_DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 
 const VERBOSE_OPT: &str = "verbose";
 const VERBOSE_OPT_SHORT: &str = "v";
+const RULE_OPT: &str = "rule";
 
 const MASTER_COMMAND: &str = "master";
 const SLAVE_COMMAND: &str = "slave";
 const GPU_COMMAND: &str = "gpu";
 const THREADED_COMMAND: &str = "threaded";
+const UNBOUNDED_COMMAND: &str = "unbounded";
+const HASHLIFE_COMMAND: &str = "hashlife";
+const VERIFY_COMMAND: &str = "verify";
 
 const PORT_ARG: &str = "PORT";
 const HOSTNAME_ARG: &str = "HOSTNAME";
+const BACKEND_A_ARG: &str = "BACKEND_A";
+const BACKEND_B_ARG: &str = "BACKEND_B";
+
+const SEED_OPT: &str = "seed";
+const GENERATIONS_OPT: &str = "generations";
+const WIDTH_OPT: &str = "width";
+const HEIGHT_OPT: &str = "height";
+const THREADS_OPT: &str = "threads";
+const SLAVES_OPT: &str = "slaves";
+const STEP_OPT: &str = "step";
+const MEMORY_LIMIT_OPT: &str = "memory-limit";
+const SCHEDULING_OPT: &str = "scheduling";
+const NO_RENDER_OPT: &str = "no-render";
+const RENDER_OPT: &str = "render";
+const LIST_DEVICES_OPT: &str = "list-devices";
+const PLATFORM_OPT: &str = "platform";
+const DEVICE_OPT: &str = "device";
+const ALL_DEVICES_OPT: &str = "all-devices";
+const EXCHANGE_OPT: &str = "exchange";
+const BACKEND_OPT: &str = "backend";
+
+const THREADED_BACKEND: &str = "threaded";
+const GPU_BACKEND: &str = "gpu";
+const UNBOUNDED_BACKEND: &str = "unbounded";
+const HASHLIFE_BACKEND: &str = "hashlife";
+const CLUSTER_BACKEND: &str = "cluster";
+const CPU_BACKEND: &str = "cpu";
+
+const STATIC_SCHEDULING: &str = "static";
+const DYNAMIC_SCHEDULING: &str = "dynamic";
 
 pub struct Options {
   pub verbosity: u64,
+  pub rule: Rule,
   pub command: Command,
 }
 
 pub enum Command {
-  Master { port: u16 },
-  Slave { hostname: String, port: u16 },
-  Gpu,
-  Threaded,
+  Master {
+    port: u16,
+    slaves: usize,
+    render: bool,
+  },
+  Slave {
+    hostname: String,
+    port: u16,
+    backend: slave::Backend,
+  },
+  Gpu {
+    step: u64,
+    render: bool,
+    selection: DeviceSelection,
+    all_devices: bool,
+    exchange: u64,
+  },
+  ListDevices,
+  Threaded {
+    threads: usize,
+    scheduling: Scheduling,
+    render: bool,
+  },
+  Unbounded {
+    render: bool,
+  },
+  Hashlife {
+    step_log2: u8,
+    memory_limit: usize,
+    render: bool,
+  },
+  Verify {
+    backends: (Backend, Backend),
+    seed: u64,
+    generations: u64,
+    width: usize,
+    height: usize,
+  },
 }
 
 pub fn get_options() -> clap::Result<Options> {
   let matches = create_parser().get_matches_safe()?;
 
   let verbosity = matches.occurrences_of(VERBOSE_OPT);
+  let rule = parse_rule(matches.value_of(RULE_OPT).unwrap())?;
   let command = match matches.subcommand() {
     (MASTER_COMMAND, Some(master_matches)) => {
       let port_str = master_matches.value_of(PORT_ARG).unwrap();
       let port = parse_port(port_str)?;
+      let slaves = parse_slaves(master_matches.value_of(SLAVES_OPT).unwrap())?;
 
-      Command::Master { port }
+      Command::Master {
+        port,
+        slaves,
+        render: master_matches.is_present(RENDER_OPT),
+      }
     }
 
     (SLAVE_COMMAND, Some(slave_matches)) => {
       let hostname = slave_matches.value_of(HOSTNAME_ARG).unwrap();
       let port_str = slave_matches.value_of(PORT_ARG).unwrap();
       let port = parse_port(port_str)?;
+      let backend = match slave_matches.value_of(BACKEND_OPT).unwrap() {
+        GPU_BACKEND => slave::Backend::Gpu(parse_selection(slave_matches)?),
+        _ => slave::Backend::Cpu,
+      };
 
       Command::Slave {
         hostname: hostname.to_owned(),
         port,
+        backend,
+      }
+    }
+
+    (GPU_COMMAND, Some(gpu_matches)) => {
+      if gpu_matches.is_present(LIST_DEVICES_OPT) {
+        Command::ListDevices
+      } else {
+        let step = parse_generations(gpu_matches.value_of(STEP_OPT).unwrap())?;
+        let render = !gpu_matches.is_present(NO_RENDER_OPT);
+        let selection = parse_selection(gpu_matches)?;
+        let all_devices = gpu_matches.is_present(ALL_DEVICES_OPT);
+        let exchange =
+          parse_generations(gpu_matches.value_of(EXCHANGE_OPT).unwrap())?;
+
+        Command::Gpu {
+          step,
+          render,
+          selection,
+          all_devices,
+          exchange,
+        }
       }
     }
 
-    (GPU_COMMAND, Some(_gpu_matches)) => Command::Gpu,
+    (THREADED_COMMAND, Some(threaded_matches)) => {
+      let threads = match threaded_matches.value_of(THREADS_OPT) {
+        Some(threads_str) => parse_threads(threads_str)?,
+        None => threaded::default_threads(),
+      };
+
+      let scheduling =
+        parse_scheduling(threaded_matches.value_of(SCHEDULING_OPT).unwrap());
+
+      Command::Threaded {
+        threads,
+        scheduling,
+        render: threaded_matches.is_present(RENDER_OPT),
+      }
+    }
 
-    (THREADED_COMMAND, Some(_threaded_matches)) => Command::Threaded,
+    (UNBOUNDED_COMMAND, Some(unbounded_matches)) => Command::Unbounded {
+      render: unbounded_matches.is_present(RENDER_OPT),
+    },
+
+    (HASHLIFE_COMMAND, Some(hashlife_matches)) => {
+      let step_log2 = parse_step(hashlife_matches.value_of(STEP_OPT).unwrap())?;
+      let memory_limit: usize =
+        parse_number(hashlife_matches.value_of(MEMORY_LIMIT_OPT).unwrap())?;
+
+      Command::Hashlife {
+        step_log2,
+        memory_limit: memory_limit * 1024 * 1024,
+        render: hashlife_matches.is_present(RENDER_OPT),
+      }
+    }
+
+    (VERIFY_COMMAND, Some(verify_matches)) => {
+      let backend_a =
+        parse_backend(verify_matches.value_of(BACKEND_A_ARG).unwrap());
+      let backend_b =
+        parse_backend(verify_matches.value_of(BACKEND_B_ARG).unwrap());
+      let seed = parse_number(verify_matches.value_of(SEED_OPT).unwrap())?;
+      let generations =
+        parse_number(verify_matches.value_of(GENERATIONS_OPT).unwrap())?;
+      let width = parse_number(verify_matches.value_of(WIDTH_OPT).unwrap())?;
+      let height = parse_number(verify_matches.value_of(HEIGHT_OPT).unwrap())?;
+
+      Command::Verify {
+        backends: (backend_a, backend_b),
+        seed,
+        generations,
+        width,
+        height,
+      }
+    }
 
     _ => unreachable!(),
   };
 
-  Ok(Options { verbosity, command })
+  Ok(Options {
+    verbosity,
+    rule,
+    command,
+  })
 }
 
 fn create_parser<'a, 'b>() -> clap::App<'a, 'b> {
@@ -75,17 +236,225 @@ fn create_parser<'a, 'b>() -> clap::App<'a, 'b> {
         .global(true)
         .help("Sets the level of verbosity"),
     )
+    .arg(
+      clap::Arg::with_name(RULE_OPT)
+        .long(RULE_OPT)
+        .takes_value(true)
+        .global(true)
+        .default_value(rule::CONWAY)
+        .help(
+          "Sets the rule, e.g. B36/S23, B2/S/C3 or R5,C0,M1,S34..58,B34..45",
+        ),
+    )
     .subcommand(
       clap::SubCommand::with_name(MASTER_COMMAND)
-        .arg(clap::Arg::with_name(PORT_ARG).required(true)),
+        .arg(clap::Arg::with_name(PORT_ARG).required(true))
+        .arg(
+          clap::Arg::with_name(SLAVES_OPT)
+            .long(SLAVES_OPT)
+            .takes_value(true)
+            .default_value("1")
+            .help("Number of slaves the simulation waits for"),
+        )
+        .arg(render_arg()),
     )
     .subcommand(
       clap::SubCommand::with_name(SLAVE_COMMAND)
         .arg(clap::Arg::with_name(HOSTNAME_ARG).required(true))
-        .arg(clap::Arg::with_name(PORT_ARG).required(true)),
+        .arg(clap::Arg::with_name(PORT_ARG).required(true))
+        .arg(
+          clap::Arg::with_name(BACKEND_OPT)
+            .long(BACKEND_OPT)
+            .takes_value(true)
+            .possible_values(&[CPU_BACKEND, GPU_BACKEND])
+            .default_value(CPU_BACKEND)
+            .help(
+              "Keeps the tiles in memory (cpu) or on an OpenCL device (gpu)",
+            ),
+        )
+        .arg(
+          clap::Arg::with_name(PLATFORM_OPT)
+            .long(PLATFORM_OPT)
+            .takes_value(true)
+            .help("OpenCL platform of the gpu backend"),
+        )
+        .arg(
+          clap::Arg::with_name(DEVICE_OPT)
+            .long(DEVICE_OPT)
+            .takes_value(true)
+            .help("OpenCL device of the gpu backend"),
+        ),
     )
-    .subcommand(clap::SubCommand::with_name(GPU_COMMAND))
-    .subcommand(clap::SubCommand::with_name(THREADED_COMMAND))
+    .subcommand(
+      clap::SubCommand::with_name(GPU_COMMAND)
+        .arg(
+          clap::Arg::with_name(STEP_OPT)
+            .long(STEP_OPT)
+            .takes_value(true)
+            .default_value("1")
+            .help("Generations computed on the device between reads"),
+        )
+        .arg(
+          clap::Arg::with_name(NO_RENDER_OPT)
+            .long(NO_RENDER_OPT)
+            .help(
+              "Doesn't draw the world, so only its population is read from \
+               the device",
+            ),
+        )
+        .arg(
+          clap::Arg::with_name(LIST_DEVICES_OPT)
+            .long(LIST_DEVICES_OPT)
+            .help("Lists the OpenCL platforms and their devices"),
+        )
+        .arg(
+          clap::Arg::with_name(PLATFORM_OPT)
+            .long(PLATFORM_OPT)
+            .takes_value(true)
+            .help("OpenCL platform, its index or a part of its name"),
+        )
+        .arg(
+          clap::Arg::with_name(DEVICE_OPT)
+            .long(DEVICE_OPT)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help(
+              "OpenCL device, its index on the platform or a part of its \
+               name. Several devices split the world between them",
+            ),
+        )
+        .arg(
+          clap::Arg::with_name(ALL_DEVICES_OPT)
+            .long(ALL_DEVICES_OPT)
+            .conflicts_with(DEVICE_OPT)
+            .help("Splits the world between every device of the platform"),
+        )
+        .arg(
+          clap::Arg::with_name(EXCHANGE_OPT)
+            .long(EXCHANGE_OPT)
+            .takes_value(true)
+            .default_value("1")
+            .help(
+              "Generations between exchanges of halos between devices, \
+               wider halos are exchanged less often",
+            ),
+        ),
+    )
+    .subcommand(
+      clap::SubCommand::with_name(THREADED_COMMAND)
+        .arg(
+          clap::Arg::with_name(THREADS_OPT)
+            .long(THREADS_OPT)
+            .takes_value(true)
+            .help("Number of threads, the number of cores by default"),
+        )
+        .arg(
+          clap::Arg::with_name(SCHEDULING_OPT)
+            .long(SCHEDULING_OPT)
+            .takes_value(true)
+            .possible_values(&[STATIC_SCHEDULING, DYNAMIC_SCHEDULING])
+            .default_value(DYNAMIC_SCHEDULING)
+            .help(
+              "Computes a fixed sector per thread (static) or single tiles \
+               which idle threads steal from busy ones (dynamic)",
+            ),
+        )
+        .arg(render_arg()),
+    )
+    .subcommand(
+      clap::SubCommand::with_name(UNBOUNDED_COMMAND)
+        .about("Runs a world which grows as far as the pattern reaches")
+        .arg(render_arg()),
+    )
+    .subcommand(
+      clap::SubCommand::with_name(HASHLIFE_COMMAND)
+        .about("Runs an unbounded world with HashLife")
+        .arg(
+          clap::Arg::with_name(STEP_OPT)
+            .long(STEP_OPT)
+            .takes_value(true)
+            .default_value("1")
+            .help("Generations per step, a power of two like 1024 or 2^10"),
+        )
+        .arg(
+          clap::Arg::with_name(MEMORY_LIMIT_OPT)
+            .long(MEMORY_LIMIT_OPT)
+            .takes_value(true)
+            .default_value("1024")
+            .help("Memory for the nodes in MiB"),
+        )
+        .arg(render_arg()),
+    )
+    .subcommand(
+      clap::SubCommand::with_name(VERIFY_COMMAND)
+        .about("Runs two backends side by side and compares their results")
+        .arg(backend_arg(BACKEND_A_ARG))
+        .arg(backend_arg(BACKEND_B_ARG))
+        .arg(
+          clap::Arg::with_name(SEED_OPT)
+            .long(SEED_OPT)
+            .takes_value(true)
+            .default_value("0")
+            .help("Seed of the random initial world"),
+        )
+        .arg(
+          clap::Arg::with_name(GENERATIONS_OPT)
+            .long(GENERATIONS_OPT)
+            .takes_value(true)
+            .default_value("100")
+            .help("Number of generations to compare"),
+        )
+        .arg(
+          clap::Arg::with_name(WIDTH_OPT)
+            .long(WIDTH_OPT)
+            .takes_value(true)
+            .default_value("200"),
+        )
+        .arg(
+          clap::Arg::with_name(HEIGHT_OPT)
+            .long(HEIGHT_OPT)
+            .takes_value(true)
+            .default_value("50"),
+        ),
+    )
+}
+
+fn render_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+  clap::Arg::with_name(RENDER_OPT)
+    .long(RENDER_OPT)
+    .help("Shows the world in an interactive terminal UI")
+}
+
+fn backend_arg<'a, 'b>(name: &'a str) -> clap::Arg<'a, 'b> {
+  clap::Arg::with_name(name).required(true).possible_values(&[
+    THREADED_BACKEND,
+    GPU_BACKEND,
+    UNBOUNDED_BACKEND,
+    HASHLIFE_BACKEND,
+    CLUSTER_BACKEND,
+  ])
+}
+
+fn parse_scheduling(scheduling_str: &str) -> Scheduling {
+  match scheduling_str {
+    STATIC_SCHEDULING => Scheduling::Static,
+    DYNAMIC_SCHEDULING => Scheduling::Dynamic,
+    // other values are rejected by clap
+    _ => unreachable!(),
+  }
+}
+
+fn parse_backend(backend_str: &str) -> Backend {
+  match backend_str {
+    THREADED_BACKEND => Backend::Threaded,
+    GPU_BACKEND => Backend::Gpu,
+    UNBOUNDED_BACKEND => Backend::Unbounded,
+    HASHLIFE_BACKEND => Backend::Hashlife,
+    CLUSTER_BACKEND => Backend::Cluster,
+    // other values are rejected by clap
+    _ => unreachable!(),
+  }
 }
 
 fn parse_port(port_str: &str) -> clap::Result<u16> {
@@ -96,3 +465,93 @@ fn parse_port(port_str: &str) -> clap::Result<u16> {
     ))
   })
 }
+
+/// Parses a step of HashLife, which is either `2^k` or a power of two, into
+/// `k`.
+fn parse_step(step_str: &str) -> clap::Result<u8> {
+  let step_log2 = if step_str.starts_with("2^") {
+    step_str[2..].parse::<u8>().ok()
+  } else {
+    match step_str.parse::<u64>() {
+      Ok(step) if step.is_power_of_two() => Some(step.trailing_zeros() as u8),
+      _ => None,
+    }
+  };
+
+  match step_log2 {
+    Some(step_log2) if step_log2 <= MAX_STEP_LOG2 => Ok(step_log2),
+    _ => Err(clap::Error::value_validation_auto(format!(
+      "'{}' isn't a power of two up to 2^{}",
+      step_str, MAX_STEP_LOG2
+    ))),
+  }
+}
+
+fn parse_generations(generations_str: &str) -> clap::Result<u64> {
+  match generations_str.parse::<u64>() {
+    Ok(generations) if generations > 0 => Ok(generations),
+    _ => Err(clap::Error::value_validation_auto(format!(
+      "'{}' isn't a valid number of generations",
+      generations_str
+    ))),
+  }
+}
+
+fn parse_threads(threads_str: &str) -> clap::Result<usize> {
+  match threads_str.parse::<usize>() {
+    Ok(threads) if threads > 0 => Ok(threads),
+    _ => Err(clap::Error::value_validation_auto(format!(
+      "'{}' isn't a valid number of threads",
+      threads_str
+    ))),
+  }
+}
+
+fn parse_slaves(slaves_str: &str) -> clap::Result<usize> {
+  match slaves_str.parse::<usize>() {
+    Ok(slaves) if slaves > 0 => Ok(slaves),
+    _ => Err(clap::Error::value_validation_auto(format!(
+      "'{}' isn't a valid number of slaves",
+      slaves_str
+    ))),
+  }
+}
+
+/// Parses the platform and devices of the `gpu` command or of a GPU slave.
+fn parse_selection(
+  matches: &clap::ArgMatches,
+) -> clap::Result<DeviceSelection> {
+  Ok(DeviceSelection {
+    platform: match matches.value_of(PLATFORM_OPT) {
+      Some(platform_str) => Some(parse_selector(platform_str)?),
+      None => None,
+    },
+    devices: matches
+      .values_of(DEVICE_OPT)
+      .into_iter()
+      .flatten()
+      .map(parse_selector)
+      .collect::<clap::Result<_>>()?,
+  })
+}
+
+fn parse_selector(selector_str: &str) -> clap::Result<Selector> {
+  selector_str
+    .parse()
+    .map_err(|error| clap::Error::value_validation_auto(format!("{}", error)))
+}
+
+fn parse_rule(rule_str: &str) -> clap::Result<Rule> {
+  rule_str
+    .parse::<Rule>()
+    .map_err(|error| clap::Error::value_validation_auto(error.to_string()))
+}
+
+fn parse_number<T: FromStr>(number_str: &str) -> clap::Result<T> {
+  number_str.parse::<T>().map_err(|_| {
+    clap::Error::value_validation_auto(format!(
+      "'{}' isn't a valid number",
+      number_str
+    ))
+  })
+}
@@ -0,0 +1,142 @@
+extern crate rand;
+use self::rand::Rng;
+
+use failure::{err_msg, Error};
+use std::fmt;
+use std::str::FromStr;
+
+use gpu::GpuEngine;
+use hashlife::HashlifeEngine;
+use master;
+use rule::{Rule, State};
+use slave;
+use threaded::{self, scheduler::Scheduling, ThreadedEngine};
+use unbounded::UnboundedEngine;
+
+/// A backend which computes generations of a world.
+///
+/// Bounded engines have the size of the last loaded pattern and cells outside
+/// of it are always dead, unbounded engines grow with the pattern.
+pub trait Engine {
+  /// Replaces the world with a pattern of `width` x `height` cells (given row
+  /// by row) at the origin and resets the generation to 0.
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error>;
+
+  /// Computes the next `generations` generations.
+  fn step(&mut self, generations: u64) -> Result<(), Error>;
+
+  /// Returns the number of generations computed since the last load.
+  fn generation(&self) -> u64;
+
+  /// Returns the cells of a region, row by row.
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error>;
+
+  /// Returns the number of alive cells.
+  fn population(&self) -> Result<u64, Error>;
+
+  /// Computes a stable hash of the world, see [`WorldHasher`]. Bounded engines
+  /// hash the whole world, unbounded ones the cells within the bounds of the
+  /// pattern.
+  ///
+  /// [`WorldHasher`]: ../utils/hash/struct.WorldHasher.html
+  fn hash(&self) -> Result<u64, Error>;
+}
+
+/// Memory for the nodes of HashLife engines created by [`Backend::create`].
+///
+/// [`Backend::create`]: enum.Backend.html#method.create
+pub const HASHLIFE_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
+
+/// The kinds of engines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+  Threaded,
+  Gpu,
+  Unbounded,
+  Hashlife,
+  Cluster,
+}
+
+impl Backend {
+  /// Returns `true` if the backend computes an unbounded world with `rule`,
+  /// which can only be compared with other unbounded backends. The cluster
+  /// is unbounded unless the rule has a bounded grid.
+  pub fn is_unbounded(self, rule: Rule) -> bool {
+    match self {
+      Backend::Threaded | Backend::Gpu => false,
+      Backend::Unbounded | Backend::Hashlife => true,
+      Backend::Cluster => rule.grid().is_none(),
+    }
+  }
+
+  /// Creates an engine of this kind with default settings: as many threads
+  /// (or local CPU slaves) as there are cores, dynamic scheduling and
+  /// [`HASHLIFE_MEMORY_LIMIT`].
+  ///
+  /// [`HASHLIFE_MEMORY_LIMIT`]: constant.HASHLIFE_MEMORY_LIMIT.html
+  pub fn create(self, rule: Rule) -> Result<Box<dyn Engine>, Error> {
+    Ok(match self {
+      Backend::Threaded => Box::new(ThreadedEngine::new(
+        rule,
+        threaded::default_threads(),
+        Scheduling::Dynamic,
+      )),
+      Backend::Gpu => Box::new(GpuEngine::new(rule)?),
+      Backend::Unbounded => Box::new(UnboundedEngine::new(rule)?),
+      Backend::Hashlife => {
+        Box::new(HashlifeEngine::new(rule, HASHLIFE_MEMORY_LIMIT)?)
+      }
+      Backend::Cluster => Box::new(master::spawn_local(
+        rule,
+        threaded::default_threads(),
+        &slave::Backend::Cpu,
+      )?),
+    })
+  }
+}
+
+impl fmt::Display for Backend {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Backend::Threaded => "threaded",
+      Backend::Gpu => "gpu",
+      Backend::Unbounded => "unbounded",
+      Backend::Hashlife => "hashlife",
+      Backend::Cluster => "cluster",
+    })
+  }
+}
+
+impl FromStr for Backend {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "threaded" => Ok(Backend::Threaded),
+      "gpu" => Ok(Backend::Gpu),
+      "unbounded" => Ok(Backend::Unbounded),
+      "hashlife" => Ok(Backend::Hashlife),
+      "cluster" => Ok(Backend::Cluster),
+      _ => Err(err_msg(format!("unknown backend '{}'", s))),
+    }
+  }
+}
+
+/// Creates a random soup of cells which are alive with a probability of 50%.
+pub fn random_soup(width: usize, height: usize) -> Vec<State> {
+  let mut rng = rand::thread_rng();
+  (0..width * height)
+    .map(|_| rng.gen_bool(0.5) as State)
+    .collect()
+}
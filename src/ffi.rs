@@ -0,0 +1,312 @@
+//! The C API, declared in `include/game_of_life_cluster.h` which is generated
+//! with cbindgen. The `c_api` test checks that the header is up to date and
+//! rewrites it when `GOL_GENERATE_HEADER` is set.
+//!
+//! Functions which can fail return a [`GolStatus`], if it's an error
+//! [`gol_last_error`] describes what went wrong. Panics never cross the API,
+//! they are reported as errors too.
+//!
+//! [`GolStatus`]: enum.GolStatus.html
+//! [`gol_last_error`]: fn.gol_last_error.html
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use failure::{err_msg, Error};
+
+use engine::{Backend, Engine};
+use pattern::Pattern;
+use rule::{self, Rule, State};
+
+/// Result of functions which can fail.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GolStatus {
+  Ok = 0,
+  /// See `gol_last_error`.
+  Error = -1,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// A world computed by one of the engines.
+pub struct GolWorld {
+  backend: Backend,
+  rule: Rule,
+  engine: Box<dyn Engine>,
+}
+
+impl GolWorld {
+  fn set_rule(&mut self, rule: Rule) -> Result<(), Error> {
+    self.engine = self.backend.create(rule)?;
+    self.rule = rule;
+    Ok(())
+  }
+
+  fn load(&mut self, pattern: &Pattern) -> Result<(), Error> {
+    if let Some(rule) = pattern.rule {
+      if rule != self.rule {
+        self.set_rule(rule)?;
+      }
+    }
+
+    match self.rule.grid() {
+      // bounded grids define the size of the world, patterns are centered
+      Some(grid) => {
+        if pattern.width > grid.width || pattern.height > grid.height {
+          return Err(err_msg(format!(
+            "a pattern of {}x{} cells doesn't fit into the grid of {}",
+            pattern.width, pattern.height, self.rule
+          )));
+        }
+
+        let mut world = Pattern::new(grid.width, grid.height);
+        world.paste(
+          pattern,
+          (grid.width - pattern.width) / 2,
+          (grid.height - pattern.height) / 2,
+        );
+        world.load_into(&mut *self.engine)
+      }
+      None => pattern.load_into(&mut *self.engine),
+    }
+  }
+}
+
+fn set_last_error(message: String) {
+  // messages can't contain NUL bytes, but errors of the library don't have
+  // them anyway
+  let message = CString::new(message.replace('\0', "")).unwrap();
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, storing its error or panic as the last error.
+fn catch<T, F>(f: F) -> Option<T>
+where
+  F: FnOnce() -> Result<T, Error>,
+{
+  match panic::catch_unwind(AssertUnwindSafe(f)) {
+    Ok(Ok(value)) => Some(value),
+    Ok(Err(error)) => {
+      set_last_error(error.to_string());
+      None
+    }
+    Err(_) => {
+      set_last_error("the simulator panicked".to_owned());
+      None
+    }
+  }
+}
+
+fn status(result: Option<()>) -> GolStatus {
+  result.map_or(GolStatus::Error, |()| GolStatus::Ok)
+}
+
+/// Converts a C string, returning `None` if it's `NULL`.
+unsafe fn optional_str<'a>(s: *const c_char) -> Result<Option<&'a str>, Error> {
+  if s.is_null() {
+    return Ok(None);
+  }
+
+  CStr::from_ptr(s)
+    .to_str()
+    .map(Some)
+    .map_err(|_| err_msg("strings must be valid UTF-8"))
+}
+
+unsafe fn world_mut<'a>(
+  world: *mut GolWorld,
+) -> Result<&'a mut GolWorld, Error> {
+  world.as_mut().ok_or_else(|| err_msg("the world is NULL"))
+}
+
+unsafe fn world_ref<'a>(world: *const GolWorld) -> Result<&'a GolWorld, Error> {
+  world.as_ref().ok_or_else(|| err_msg("the world is NULL"))
+}
+
+/// Returns the message of the last error on this thread, or `NULL` if nothing
+/// has failed yet. The message is valid until the next error.
+#[no_mangle]
+pub extern "C" fn gol_last_error() -> *const c_char {
+  LAST_ERROR.with(|last_error| {
+    last_error
+      .borrow()
+      .as_ref()
+      .map_or(ptr::null(), |message| message.as_ptr())
+  })
+}
+
+/// Creates an empty world computed by `backend` (`"threaded"`, `"gpu"`,
+/// `"unbounded"`, `"hashlife"` or `"cluster"`) with `rule`, e.g. `"B3/S23"`.
+/// `NULL` arguments select the threaded backend and Conway's Game of Life.
+///
+/// Returns `NULL` on errors. The world must be freed with `gol_world_free`.
+///
+/// # Safety
+///
+/// Non-`NULL` arguments must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_new(
+  backend: *const c_char,
+  rule: *const c_char,
+) -> *mut GolWorld {
+  catch(|| {
+    let backend = optional_str(backend)?.unwrap_or("threaded").parse()?;
+    let rule: Rule = optional_str(rule)?.unwrap_or(rule::CONWAY).parse()?;
+    let engine = Backend::create(backend, rule)?;
+    Ok(Box::into_raw(Box::new(GolWorld {
+      backend,
+      rule,
+      engine,
+    })))
+  })
+  .unwrap_or(ptr::null_mut())
+}
+
+/// Frees a world, `NULL` is ignored.
+///
+/// # Safety
+///
+/// `world` must have been created by `gol_world_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_free(world: *mut GolWorld) {
+  if !world.is_null() {
+    drop(Box::from_raw(world));
+  }
+}
+
+/// Replaces the rule, which clears the world.
+///
+/// # Safety
+///
+/// `world` must be a live world and `rule` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_set_rule(
+  world: *mut GolWorld,
+  rule: *const c_char,
+) -> GolStatus {
+  status(catch(|| {
+    let world = world_mut(world)?;
+    let rule =
+      optional_str(rule)?.ok_or_else(|| err_msg("the rule is NULL"))?;
+    world.set_rule(rule.parse()?)
+  }))
+}
+
+/// Replaces the world with a pattern in the RLE format and resets the
+/// generation to 0. The rule of the pattern, if it has one, replaces the
+/// rule of the world. Patterns are centered in bounded grids, otherwise
+/// their top left corner is at `(0, 0)`.
+///
+/// # Safety
+///
+/// `world` must be a live world and `rle` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_load_rle(
+  world: *mut GolWorld,
+  rle: *const c_char,
+) -> GolStatus {
+  status(catch(|| {
+    let world = world_mut(world)?;
+    let rle =
+      optional_str(rle)?.ok_or_else(|| err_msg("the pattern is NULL"))?;
+    world.load(&rle.parse()?)
+  }))
+}
+
+/// Computes the next `generations` generations.
+///
+/// # Safety
+///
+/// `world` must be a live world.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_step(
+  world: *mut GolWorld,
+  generations: u64,
+) -> GolStatus {
+  status(catch(|| world_mut(world)?.engine.step(generations)))
+}
+
+/// Returns the number of generations computed since the last load, or 0 if
+/// `world` is `NULL`.
+///
+/// # Safety
+///
+/// `world` must be a live world or `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_generation(world: *const GolWorld) -> u64 {
+  world.as_ref().map_or(0, |world| world.engine.generation())
+}
+
+/// Copies the states of the cells of a region into `buffer` row by row, which
+/// must have room for `width * height` cells. Dead cells are 0, alive ones 1
+/// and dying cells of "Generations" rules 2 and higher.
+///
+/// # Safety
+///
+/// `world` must be a live world and `buffer` must point to `width * height`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_read_region(
+  world: *const GolWorld,
+  x: i64,
+  y: i64,
+  width: usize,
+  height: usize,
+  buffer: *mut u8,
+) -> GolStatus {
+  status(catch(|| {
+    let world = world_ref(world)?;
+    if buffer.is_null() {
+      return Err(err_msg("the buffer is NULL"));
+    }
+
+    let cells = world.engine.read_region(x, y, width, height)?;
+    slice::from_raw_parts_mut(buffer as *mut State, cells.len())
+      .copy_from_slice(&cells);
+    Ok(())
+  }))
+}
+
+/// Stores the number of alive cells in `population`.
+///
+/// # Safety
+///
+/// `world` must be a live world and `population` writable.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_population(
+  world: *const GolWorld,
+  population: *mut u64,
+) -> GolStatus {
+  status(catch(|| {
+    let value = world_ref(world)?.engine.population()?;
+    *population
+      .as_mut()
+      .ok_or_else(|| err_msg("the output is NULL"))? = value;
+    Ok(())
+  }))
+}
+
+/// Stores the hash of the world in `hash`, which is the same for every
+/// backend and the `verify` subcommand.
+///
+/// # Safety
+///
+/// `world` must be a live world and `hash` writable.
+#[no_mangle]
+pub unsafe extern "C" fn gol_world_hash(
+  world: *const GolWorld,
+  hash: *mut u64,
+) -> GolStatus {
+  status(catch(|| {
+    let value = world_ref(world)?.engine.hash()?;
+    *hash.as_mut().ok_or_else(|| err_msg("the output is NULL"))? = value;
+    Ok(())
+  }))
+}
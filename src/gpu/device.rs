@@ -0,0 +1,251 @@
+use std::fmt::{self, Write};
+use std::ptr;
+use std::str::FromStr;
+
+use failure::{err_msg, Error};
+
+use super::ocl::{core, ffi, Device, Platform};
+
+/// Selects a platform or a device by its index in the list printed by
+/// [`list_devices`] or by a case-insensitive part of its name.
+///
+/// [`list_devices`]: fn.list_devices.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+  Index(usize),
+  Name(String),
+}
+
+impl Selector {
+  fn matches(&self, index: usize, name: &str) -> bool {
+    match *self {
+      Selector::Index(selected) => index == selected,
+      Selector::Name(ref part) => {
+        name.to_lowercase().contains(&part.to_lowercase())
+      }
+    }
+  }
+}
+
+impl FromStr for Selector {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.is_empty() {
+      return Err(err_msg("the selector is empty"));
+    }
+
+    Ok(
+      s.parse()
+        .map(Selector::Index)
+        .unwrap_or_else(|_| Selector::Name(s.to_owned())),
+    )
+  }
+}
+
+impl fmt::Display for Selector {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Selector::Index(index) => write!(f, "#{}", index),
+      Selector::Name(ref name) => write!(f, "'{}'", name),
+    }
+  }
+}
+
+/// The platform and the devices GPU engines run on, the first ones if they
+/// aren't given. Device indices count the devices of the platform, names are
+/// searched for on every platform unless the platform is given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceSelection {
+  pub platform: Option<Selector>,
+  pub devices: Vec<Selector>,
+}
+
+impl DeviceSelection {
+  /// Returns the first selected device.
+  pub fn select(&self) -> Result<(Platform, Device), Error> {
+    let selector = self.devices.first();
+    for platform in self.platforms(selector)? {
+      let device = Device::list_all(platform)?.into_iter().enumerate().find(
+        |&(index, device)| {
+          selector.map_or(true, |selector| {
+            selector.matches(index, &device_name(device))
+          })
+        },
+      );
+
+      if let Some((_, device)) = device {
+        return Ok((platform, device));
+      }
+    }
+
+    Err(err_msg(match selector {
+      Some(selector) => format!("there's no OpenCL device {}", selector),
+      None => "the OpenCL platform has no devices".to_owned(),
+    }))
+  }
+
+  /// Returns every selected device in the order they are given, or every
+  /// device of the platform if none is given. A device may be given twice.
+  pub fn select_all(&self) -> Result<Vec<(Platform, Device)>, Error> {
+    if self.devices.is_empty() {
+      let platform = self.platforms(None)?[0];
+      let devices = Device::list_all(platform)?;
+      if devices.is_empty() {
+        return Err(err_msg("the OpenCL platform has no devices"));
+      }
+      return Ok(
+        devices
+          .into_iter()
+          .map(|device| (platform, device))
+          .collect(),
+      );
+    }
+
+    self
+      .devices
+      .iter()
+      .map(|selector| {
+        DeviceSelection {
+          platform: self.platform.clone(),
+          devices: vec![selector.clone()],
+        }
+        .select()
+      })
+      .collect()
+  }
+
+  /// Returns the platforms on which devices selected by `device` are
+  /// searched for.
+  fn platforms(
+    &self,
+    device: Option<&Selector>,
+  ) -> Result<Vec<Platform>, Error> {
+    let platforms = platforms();
+    if platforms.is_empty() {
+      return Err(err_msg("there's no OpenCL platform"));
+    }
+
+    let candidates = platforms
+      .into_iter()
+      .enumerate()
+      .filter(|&(index, platform)| match self.platform {
+        Some(ref selector) => selector.matches(index, &platform_name(platform)),
+        // indices of devices refer to the first platform
+        None => match device {
+          Some(&Selector::Name(_)) => true,
+          _ => index == 0,
+        },
+      })
+      .map(|(_, platform)| platform)
+      .collect::<Vec<_>>();
+
+    match self.platform {
+      Some(ref selector) if candidates.is_empty() => {
+        Err(err_msg(format!("there's no OpenCL platform {}", selector)))
+      }
+      _ => Ok(candidates),
+    }
+  }
+}
+
+/// Returns the OpenCL platforms, none if there's no OpenCL implementation
+/// installed.
+pub fn platforms() -> Vec<Platform> {
+  if platform_count() == 0 {
+    return Vec::new();
+  }
+
+  core::get_platform_ids()
+    .map(|ids| ids.into_iter().map(Platform::new).collect())
+    .unwrap_or_default()
+}
+
+/// Returns the number of OpenCL platforms. Without an implementation the
+/// loader fails instead of returning 0, which ocl retries for 10 seconds, so
+/// the platforms are counted directly first.
+fn platform_count() -> u32 {
+  let mut count = 0;
+  let status = unsafe { ffi::clGetPlatformIDs(0, ptr::null_mut(), &mut count) };
+  if status == ffi::CL_SUCCESS {
+    count
+  } else {
+    0
+  }
+}
+
+/// Returns `true` if there's at least one OpenCL platform.
+pub fn is_available() -> bool {
+  !platforms().is_empty()
+}
+
+/// Lists the platforms and their devices with the indices used by
+/// [`Selector`](enum.Selector.html).
+pub fn list_devices() -> Result<String, Error> {
+  let platforms = platforms();
+  if platforms.is_empty() {
+    return Ok("there's no OpenCL platform\n".to_owned());
+  }
+
+  let mut list = String::new();
+  for (index, platform) in platforms.into_iter().enumerate() {
+    writeln!(
+      list,
+      "platform {}: {} ({})",
+      index,
+      platform_name(platform),
+      platform.version()?
+    )?;
+
+    for (index, device) in Device::list_all(platform)?.into_iter().enumerate() {
+      writeln!(
+        list,
+        "  device {}: {} ({})",
+        index,
+        device_name(device),
+        device.vendor()?
+      )?;
+    }
+  }
+  Ok(list)
+}
+
+fn platform_name(platform: Platform) -> String {
+  platform.name().unwrap_or_else(|_| "unknown".to_owned())
+}
+
+fn device_name(device: Device) -> String {
+  device.name().unwrap_or_else(|_| "unknown".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn selectors() {
+    assert_eq!("1".parse::<Selector>().unwrap(), Selector::Index(1));
+    assert_eq!(
+      "GeForce".parse::<Selector>().unwrap(),
+      Selector::Name("GeForce".to_owned())
+    );
+    assert!("".parse::<Selector>().is_err());
+
+    let selector = Selector::Name("gtx 10".to_owned());
+    assert!(selector.matches(3, "GeForce GTX 1080"));
+    assert!(!selector.matches(3, "Radeon RX 580"));
+    assert!(Selector::Index(2).matches(2, "Radeon RX 580"));
+    assert!(!Selector::Index(2).matches(0, "Radeon RX 580"));
+  }
+
+  #[test]
+  fn no_platforms() {
+    if is_available() {
+      return;
+    }
+
+    assert!(DeviceSelection::default().select().is_err());
+    assert!(DeviceSelection::default().select_all().is_err());
+    assert_eq!(list_devices().unwrap(), "there's no OpenCL platform\n");
+  }
+}
@@ -1,79 +1,561 @@
 extern crate ocl;
 use self::ocl::{
-  Buffer, Context, Device, Kernel, Platform, Program, Queue,
+  Buffer, Context, Device, Kernel, OclPrm, Platform, Program, Queue,
   Result as OclResult, SpatialDims,
 };
 
-extern crate rand;
-use self::rand::Rng;
+use failure::{err_msg, Error};
+use std::mem;
 
-use std::time::Instant;
+use engine::Engine;
+use rule::{Rule, State, ALIVE, DEAD};
+use threaded::{self, scheduler::Scheduling, ThreadedEngine};
+use utils::hash::hash_world;
+use utils::time::measure_time;
 
-type Cell = u8;
-type World = Buffer<Cell>;
+pub type Cell = State;
+/// A word of 32 cells of a bit-packed world.
+pub type Word = u32;
 
-const PROGRAM_SOURCE: &str = include_str!("program.cl");
-const KERNEL_NAME: &str = "next_generation";
+pub mod device;
+pub mod multi;
+pub mod program;
+pub mod tiles;
+use self::device::DeviceSelection;
+use self::multi::MultiGpuEngine;
+use self::program::{REDUCTION_SIZE, TILE_HEIGHT, TILE_WIDTH};
 
-pub fn run() -> OclResult<()> {
-  let width = 200;
-  let height = 50;
-  let dimensions = SpatialDims::Two(width, height);
+/// Number of cells in a [`Word`](type.Word.html).
+const WORD_BITS: usize = 32;
 
-  let platform = time("get_platform", get_platform);
-  let device = time("get_device", || get_device(&platform))?;
-  let context = time("create_context", || create_context(platform, &device))?;
+/// Maximum number of work-groups of the population kernels, whose sums are
+/// read from the device.
+const MAX_REDUCTION_GROUPS: usize = 64;
 
-  let program = time("compile_program", || compile_program(&context, &device))?;
+/// Creates an engine on the selected devices (or every device of the
+/// platform if `all_devices` is set).
+///
+/// With several devices, halos are exchanged between them every `exchange`
+/// generations, see [`MultiGpuEngine`]. Without any OpenCL platform a
+/// threaded engine is created instead.
+///
+/// [`MultiGpuEngine`]: multi/struct.MultiGpuEngine.html
+pub fn create(
+  rule: Rule,
+  selection: &DeviceSelection,
+  all_devices: bool,
+  exchange: u64,
+) -> Result<Box<dyn Engine>, Error> {
+  if !device::is_available() {
+    warn!(
+      target: "gpu",
+      "there's no OpenCL platform, falling back to the threaded backend"
+    );
+    return Ok(Box::new(ThreadedEngine::new(
+      rule,
+      threaded::default_threads(),
+      Scheduling::Dynamic,
+    )));
+  }
 
-  let queue = time("create_queue", || create_queue(&context, device))?;
-  let world_a = time("create_world", || create_world(&queue, &dimensions))?;
-  let world_b = time("create_world", || create_world(&queue, &dimensions))?;
+  let layout = Layout::best(&rule);
+  let devices = if all_devices || selection.devices.len() > 1 {
+    selection.select_all()?
+  } else {
+    vec![selection.select()?]
+  };
+  Ok(match devices.as_slice() {
+    &[(platform, device)] => {
+      Box::new(GpuEngine::on_device(rule, layout, platform, device)?)
+    }
+    devices => Box::new(MultiGpuEngine::new(rule, layout, devices, exchange)?),
+  })
+}
 
-  let kernel = time("create_kernel", || {
-    create_kernel(&program, &queue, &dimensions)
-  })?;
+/// How the world is stored on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+  /// A byte per cell, computed by the `next_generation` kernel. Supports
+  /// every rule.
+  Bytes,
+  /// 32 cells per word, computed a word per work-item by the
+  /// `next_generation_packed` kernel. Supports two-state rules on range 1
+  /// neighborhoods.
+  Packed,
+}
 
-  let mut tmp_data = vec![0; dimensions.to_len()];
-  time("fill_world", || {
-    generate_world_data(&dimensions, &mut tmp_data);
-    world_a.write(&tmp_data).enq()
-  })?;
+impl Layout {
+  /// Returns the fastest layout which supports `rule`.
+  pub fn best(rule: &Rule) -> Self {
+    if Layout::Packed.supports(rule) {
+      Layout::Packed
+    } else {
+      Layout::Bytes
+    }
+  }
+
+  pub fn supports(self, rule: &Rule) -> bool {
+    match self {
+      Layout::Bytes => true,
+      Layout::Packed => rule.states() == 2 && !rule.is_larger_than_life(),
+    }
+  }
+
+  /// Returns the names of the gather and the scatter kernel of the layout,
+  /// see [`Transfer`](struct.Transfer.html).
+  fn transfer_kernel_names(self) -> (&'static str, &'static str) {
+    match self {
+      Layout::Bytes => {
+        (program::GATHER_KERNEL_NAME, program::SCATTER_KERNEL_NAME)
+      }
+      Layout::Packed => (
+        program::PACKED_GATHER_KERNEL_NAME,
+        program::PACKED_SCATTER_KERNEL_NAME,
+      ),
+    }
+  }
+}
+
+/// The GPU backend: a bounded world in two buffers on an OpenCL device,
+/// which a kernel computes into each other in turns.
+pub struct GpuEngine {
+  layout: Layout,
+  program: Program,
+  queue: Queue,
+  /// The loaded world.
+  world: Option<DeviceWorld>,
+  generation: u64,
+}
+
+struct DeviceWorld {
+  width: usize,
+  height: usize,
+  buffers: Buffers,
+}
+
+enum Buffers {
+  Bytes(DoubleBuffer<Cell>),
+  Packed(DoubleBuffer<Word>),
+}
 
-  println!();
+impl Buffers {
+  /// Uploads a world of `width` x `height` cells in `layout`.
+  fn new(
+    layout: Layout,
+    program: &Program,
+    queue: &Queue,
+    width: usize,
+    height: usize,
+    cells: &[Cell],
+  ) -> OclResult<Self> {
+    Ok(match layout {
+      Layout::Bytes => {
+        let kernel = measure_time("create_kernel", || {
+          create_kernel(program, queue, width, height)
+        })?;
+        Buffers::Bytes(DoubleBuffer::new(
+          program,
+          queue,
+          kernel,
+          program::POPULATION_KERNEL_NAME,
+          cells,
+        )?)
+      }
+      Layout::Packed => {
+        let kernel = measure_time("create_kernel", || {
+          create_packed_kernel(program, queue, width, height)
+        })?;
+        let words = pack(cells, width, height);
+        Buffers::Packed(DoubleBuffer::new(
+          program,
+          queue,
+          kernel,
+          program::PACKED_POPULATION_KERNEL_NAME,
+          &words,
+        )?)
+      }
+    })
+  }
+
+  /// Enqueues the computation of the next generation.
+  fn next_generation(&mut self) -> OclResult<()> {
+    match *self {
+      Buffers::Bytes(ref mut buffers) => buffers.next_generation(),
+      Buffers::Packed(ref mut buffers) => buffers.next_generation(),
+    }
+  }
 
-  for n in 0.. {
-    let world = if n % 2 == 0 { &world_a } else { &world_b };
-    let next_world = if n % 2 == 0 { &world_b } else { &world_a };
+  /// Reads the cells of the current generation of a `width` x `height`
+  /// world.
+  fn read_cells(&self, width: usize, height: usize) -> OclResult<Vec<Cell>> {
+    match *self {
+      Buffers::Bytes(ref buffers) => buffers.read(),
+      Buffers::Packed(ref buffers) => {
+        Ok(unpack(&buffers.read()?, width, height))
+      }
+    }
+  }
 
-    time(format!("generation #{}", n).as_str(), || {
-      next_generation(&kernel, world, next_world)
+  fn population(&self) -> OclResult<u64> {
+    match *self {
+      Buffers::Bytes(ref buffers) => buffers.population(),
+      Buffers::Packed(ref buffers) => buffers.population(),
+    }
+  }
+
+  /// Sets the argument `index` of `kernel` to the current generation.
+  fn set_world_arg(&self, kernel: &Kernel, index: usize) -> OclResult<()> {
+    match *self {
+      Buffers::Bytes(ref buffers) => kernel.set_arg(index, &buffers.world),
+      Buffers::Packed(ref buffers) => kernel.set_arg(index, &buffers.world),
+    }
+  }
+}
+
+/// The current and the next generation of a world, the kernel which
+/// computes one from the other and the kernel which counts the population.
+struct DoubleBuffer<T: OclPrm> {
+  world: Buffer<T>,
+  next_world: Buffer<T>,
+  kernel: Kernel,
+  population: Population,
+}
+
+impl<T: OclPrm> DoubleBuffer<T> {
+  fn new(
+    program: &Program,
+    queue: &Queue,
+    kernel: Kernel,
+    population_kernel: &str,
+    data: &[T],
+  ) -> OclResult<Self> {
+    let world =
+      measure_time("create_world", || create_buffer(queue, data.len()))?;
+    let next_world =
+      measure_time("create_world", || create_buffer(queue, data.len()))?;
+    measure_time("fill_world", || world.write(data).enq())?;
+
+    let population = measure_time("create_kernel", || {
+      Population::new::<T>(program, queue, population_kernel, data.len())
     })?;
 
-    next_world.read(&mut tmp_data).enq()?;
-    print_world_data(&tmp_data, &dimensions);
-    move_cursor_up(height as u16 + 1);
+    Ok(DoubleBuffer {
+      world,
+      next_world,
+      kernel,
+      population,
+    })
+  }
+
+  fn next_generation(&mut self) -> OclResult<()> {
+    self.kernel.set_arg(0, &self.world)?;
+    self.kernel.set_arg(1, &self.next_world)?;
+    unsafe { self.kernel.enq()? };
+    mem::swap(&mut self.world, &mut self.next_world);
+    Ok(())
+  }
+
+  fn read(&self) -> OclResult<Vec<T>> {
+    let mut data = vec![T::default(); self.world.len()];
+    if !data.is_empty() {
+      self.world.read(&mut data).enq()?;
+    }
+    Ok(data)
+  }
+
+  fn population(&self) -> OclResult<u64> {
+    self.population.count(&self.world)
+  }
+}
+
+/// Cells at a list of indices of a padded world (a strip of a world split
+/// across devices or a tile of a slave) and a gather or scatter kernel which
+/// copies them from or to `values`.
+struct Transfer {
+  kernel: Kernel,
+  /// The indices, which must live as long as the kernel.
+  _indices: Buffer<u32>,
+  values: Buffer<Cell>,
+}
+
+impl Transfer {
+  fn new(
+    layout: Layout,
+    (program, queue): (&Program, &Queue),
+    name: &str,
+    padded_width: usize,
+    indices: &[u32],
+  ) -> OclResult<Self> {
+    let values = create_buffer(queue, indices.len())?;
+    let indices_buffer = create_buffer(queue, indices.len())?;
+    indices_buffer.write(indices).enq()?;
+
+    let mut builder = Kernel::builder();
+    builder
+      .program(program)
+      .name(name)
+      .queue(queue.clone())
+      .global_work_size(indices.len());
+    match layout {
+      Layout::Bytes => builder.arg(None::<&Buffer<Cell>>),
+      Layout::Packed => builder.arg(None::<&Buffer<Word>>),
+    };
+    let kernel = builder
+      .arg(padded_width as u32)
+      .arg(&indices_buffer)
+      .arg(&values)
+      .build()?;
+
+    Ok(Transfer {
+      kernel,
+      _indices: indices_buffer,
+      values,
+    })
+  }
+
+  /// Enqueues the kernel on the current generation of `buffers`.
+  fn enq(&self, buffers: &Buffers) -> OclResult<()> {
+    buffers.set_world_arg(&self.kernel, 0)?;
+    unsafe { self.kernel.enq() }
+  }
+}
+
+/// A population kernel and the buffer of the sums of its work-groups.
+struct Population {
+  kernel: Kernel,
+  counts: Buffer<u32>,
+}
+
+impl Population {
+  fn new<T: OclPrm>(
+    program: &Program,
+    queue: &Queue,
+    name: &str,
+    len: usize,
+  ) -> OclResult<Self> {
+    let groups = ((len + REDUCTION_SIZE - 1) / REDUCTION_SIZE)
+      .max(1)
+      .min(MAX_REDUCTION_GROUPS);
+    let counts = create_buffer(queue, groups)?;
+
+    let kernel = Kernel::builder()
+      .program(program)
+      .name(name)
+      .queue(queue.clone())
+      .global_work_size(groups * REDUCTION_SIZE)
+      .local_work_size(REDUCTION_SIZE)
+      .arg(None::<&Buffer<T>>)
+      .arg(len as u64)
+      .arg(&counts)
+      .build()?;
+
+    Ok(Population { kernel, counts })
+  }
+
+  /// Counts the alive cells of `world`, only the sums of the work-groups are
+  /// read from the device.
+  fn count<T: OclPrm>(&self, world: &Buffer<T>) -> OclResult<u64> {
+    self.kernel.set_arg(0, world)?;
+    unsafe { self.kernel.enq()? };
+
+    let mut counts = vec![0; self.counts.len()];
+    self.counts.read(&mut counts).enq()?;
+    Ok(counts.iter().map(|&count| u64::from(count)).sum())
+  }
+}
+
+impl GpuEngine {
+  /// Creates an engine with the best layout for `rule`, see
+  /// [`Layout::best`](enum.Layout.html#method.best), on the first device.
+  pub fn new(rule: Rule) -> Result<Self, Error> {
+    GpuEngine::with_layout(rule, Layout::best(&rule))
+  }
+
+  pub fn with_layout(rule: Rule, layout: Layout) -> Result<Self, Error> {
+    GpuEngine::with_device(rule, layout, &DeviceSelection::default())
+  }
+
+  /// Creates an engine on the first selected device.
+  pub fn with_device(
+    rule: Rule,
+    layout: Layout,
+    selection: &DeviceSelection,
+  ) -> Result<Self, Error> {
+    let (platform, device) =
+      measure_time("select_device", || selection.select())?;
+    GpuEngine::on_device(rule, layout, platform, device)
+  }
+
+  pub fn on_device(
+    rule: Rule,
+    layout: Layout,
+    platform: Platform,
+    device: Device,
+  ) -> Result<Self, Error> {
+    if !layout.supports(&rule) {
+      return Err(err_msg(format!(
+        "the {:?} layout doesn't support {}",
+        layout, rule
+      )));
+    }
+
+    let (program, queue) =
+      open_device(platform, device, program::source(&rule))?;
+
+    Ok(GpuEngine {
+      layout,
+      program,
+      queue,
+      world: None,
+      generation: 0,
+    })
+  }
+
+  pub fn layout(&self) -> Layout {
+    self.layout
+  }
+
+  /// Reads the cells of the world from the device.
+  fn read_cells(&self) -> OclResult<(usize, usize, Vec<Cell>)> {
+    let world = match self.world {
+      Some(ref world) => world,
+      None => return Ok((0, 0, Vec::new())),
+    };
+
+    let cells = world.buffers.read_cells(world.width, world.height)?;
+    Ok((world.width, world.height, cells))
+  }
+}
+
+impl Engine for GpuEngine {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    let buffers = Buffers::new(
+      self.layout,
+      &self.program,
+      &self.queue,
+      width,
+      height,
+      cells,
+    )?;
+
+    self.world = Some(DeviceWorld {
+      width,
+      height,
+      buffers,
+    });
+    self.generation = 0;
+    Ok(())
+  }
+
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    if let Some(ref mut world) = self.world {
+      for _ in 0..generations {
+        world.buffers.next_generation()?;
+      }
+    }
+
+    // the kernels are only enqueued, waiting for them once per step keeps
+    // the host in step with the device
+    self.queue.finish()?;
+    self.generation += generations;
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let (world_width, world_height, data) = self.read_cells()?;
+    Ok(region(
+      &data,
+      world_width,
+      world_height,
+      (x, y, width, height),
+    ))
+  }
+
+  fn population(&self) -> Result<u64, Error> {
+    Ok(match self.world {
+      Some(ref world) => world.buffers.population()?,
+      None => 0,
+    })
   }
 
-  Ok(())
+  fn hash(&self) -> Result<u64, Error> {
+    let (width, height, data) = self.read_cells()?;
+    Ok(hash_world_data(&data, width, height))
+  }
+}
+
+/// Computes a stable hash of the world, see [`WorldHasher`] for details.
+///
+/// [`WorldHasher`]: ../utils/hash/struct.WorldHasher.html
+pub fn hash_world_data(data: &[Cell], width: usize, height: usize) -> u64 {
+  hash_world(width, height, |x, y| data[x + y * width])
 }
 
-pub fn time<F, R>(name: &str, f: F) -> R
-where
-  F: FnOnce() -> R,
-{
-  let start_time = Instant::now();
-  let ret = f();
-  println!("{} - {} µs", name, start_time.elapsed().as_micros());
-  ret
+/// Copies a region of a world of `world_width` x `world_height` cells, cells
+/// outside of the world are dead.
+fn region(
+  data: &[Cell],
+  world_width: usize,
+  world_height: usize,
+  (x, y, width, height): (i64, i64, usize, usize),
+) -> Vec<State> {
+  let in_world = |x: i64, y: i64| {
+    x >= 0 && y >= 0 && x < world_width as i64 && y < world_height as i64
+  };
+
+  let mut cells = Vec::with_capacity(width * height);
+  for y in y..y + height as i64 {
+    for x in x..x + width as i64 {
+      cells.push(if in_world(x, y) {
+        data[x as usize + y as usize * world_width]
+      } else {
+        DEAD
+      });
+    }
+  }
+  cells
 }
 
-fn get_platform() -> Platform {
-  Platform::default()
+/// Packs cells into words of 32 cells, every row starts with a new word.
+pub fn pack(cells: &[Cell], width: usize, height: usize) -> Vec<Word> {
+  let row_words = (width + WORD_BITS - 1) / WORD_BITS;
+  let mut words = vec![0; row_words * height];
+  for y in 0..height {
+    for x in 0..width {
+      if cells[x + y * width] == ALIVE {
+        words[x / WORD_BITS + y * row_words] |= 1 << (x % WORD_BITS);
+      }
+    }
+  }
+  words
 }
 
-fn get_device(platform: &Platform) -> OclResult<Device> {
-  Device::first(platform)
+/// Unpacks cells packed by [`pack`](fn.pack.html).
+pub fn unpack(words: &[Word], width: usize, height: usize) -> Vec<Cell> {
+  let row_words = (width + WORD_BITS - 1) / WORD_BITS;
+  let mut cells = Vec::with_capacity(width * height);
+  for y in 0..height {
+    for x in 0..width {
+      let word = words[x / WORD_BITS + y * row_words];
+      cells.push((word >> (x % WORD_BITS)) as Cell & 1);
+    }
+  }
+  cells
 }
 
 fn create_context(platform: Platform, device: &Device) -> OclResult<Context> {
@@ -83,10 +565,36 @@ fn create_context(platform: Platform, device: &Device) -> OclResult<Context> {
     .build()
 }
 
-fn compile_program(context: &Context, device: &Device) -> OclResult<Program> {
+/// Compiles `source` for a device and creates a queue on it.
+fn open_device(
+  platform: Platform,
+  device: Device,
+  source: String,
+) -> OclResult<(Program, Queue)> {
+  info!(
+    target: "gpu",
+    "running on {}",
+    device.name().unwrap_or_else(|_| "an unknown device".to_owned())
+  );
+  let context =
+    measure_time("create_context", || create_context(platform, &device))?;
+
+  let program = measure_time("compile_program", || {
+    compile_program(&context, &device, source)
+  })?;
+
+  let queue = measure_time("create_queue", || create_queue(&context, device))?;
+  Ok((program, queue))
+}
+
+fn compile_program(
+  context: &Context,
+  device: &Device,
+  source: String,
+) -> OclResult<Program> {
   Program::builder()
     .devices(device)
-    .src(PROGRAM_SOURCE)
+    .src(source)
     .build(&context)
 }
 
@@ -94,64 +602,250 @@ fn create_queue(context: &Context, device: Device) -> OclResult<Queue> {
   Queue::new(context, device, None)
 }
 
-fn create_world(queue: &Queue, dimensions: &SpatialDims) -> OclResult<World> {
-  World::builder()
+/// Creates a buffer of `len` zeros.
+fn create_buffer<T: OclPrm>(queue: &Queue, len: usize) -> OclResult<Buffer<T>> {
+  Buffer::builder()
     .queue(queue.clone())
     .flags(ocl::flags::MEM_READ_WRITE)
-    .len(dimensions)
-    .fill_val(0)
+    .len(len)
+    .fill_val(T::default())
     .build()
 }
 
 fn create_kernel(
   program: &Program,
   queue: &Queue,
-  dimensions: &SpatialDims,
+  width: usize,
+  height: usize,
 ) -> OclResult<Kernel> {
   Kernel::builder()
     .program(program)
-    .name(KERNEL_NAME)
+    .name(program::KERNEL_NAME)
     .queue(queue.clone())
-    .global_work_size(dimensions)
-    .arg(None::<&World>)
-    .arg(None::<&World>)
+    .global_work_size(SpatialDims::Two(width, height))
+    .arg(None::<&Buffer<Cell>>)
+    .arg(None::<&Buffer<Cell>>)
     .build()
 }
 
-fn generate_world_data(dimensions: &SpatialDims, dest: &mut Vec<Cell>) {
-  let width = dimensions[0];
-  let height = dimensions[1];
+fn create_packed_kernel(
+  program: &Program,
+  queue: &Queue,
+  width: usize,
+  height: usize,
+) -> OclResult<Kernel> {
+  // work-groups must be whole, the kernel skips words outside of the world
+  let round_up =
+    |n: usize, multiple: usize| (n + multiple - 1) / multiple * multiple;
+  let row_words = (width + WORD_BITS - 1) / WORD_BITS;
 
-  let mut rng = rand::thread_rng();
-  for y in 0..height {
-    for x in 0..width {
-      dest[x + y * width] = rng.gen_bool(0.5) as Cell;
+  Kernel::builder()
+    .program(program)
+    .name(program::PACKED_KERNEL_NAME)
+    .queue(queue.clone())
+    .global_work_size(SpatialDims::Two(
+      round_up(row_words, TILE_WIDTH),
+      round_up(height, TILE_HEIGHT),
+    ))
+    .local_work_size(SpatialDims::Two(TILE_WIDTH, TILE_HEIGHT))
+    .arg(None::<&Buffer<Word>>)
+    .arg(None::<&Buffer<Word>>)
+    .arg(width as u32)
+    .arg(height as u32)
+    .build()
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate rand;
+  extern crate test;
+  use self::rand::prng::XorShiftRng;
+  use self::rand::{Rng, SeedableRng};
+  use self::test::Bencher;
+
+  use std::env;
+
+  use super::*;
+  use threaded::{scheduler::Scheduling, ThreadedEngine};
+
+  /// Returns `false` if there's no OpenCL platform, so the tests can run on
+  /// machines without a GPU. CPU implementations such as POCL are enough,
+  /// set `GOL_REQUIRE_OPENCL` to make sure the tests don't skip.
+  pub fn opencl_available() -> bool {
+    if device::is_available() {
+      return true;
     }
+
+    assert!(
+      env::var_os("GOL_REQUIRE_OPENCL").is_none(),
+      "no OpenCL platform"
+    );
+    false
   }
-}
 
-fn next_generation(
-  kernel: &Kernel,
-  world: &World,
-  next_world: &World,
-) -> OclResult<()> {
-  kernel.set_arg(0, world)?;
-  kernel.set_arg(1, next_world)?;
-  unsafe { kernel.enq() }
-}
+  pub fn random_cells(rng: &mut XorShiftRng, len: usize) -> Vec<Cell> {
+    (0..len).map(|_| rng.gen_bool(0.4) as Cell).collect()
+  }
 
-fn print_world_data(data: &[Cell], dimensions: &SpatialDims) {
-  let width = dimensions[0];
-  let height = dimensions[1];
+  /// Runs every rule for 20 generations on the GPU with every layout which
+  /// supports it and compares the worlds with the threaded engine.
+  fn assert_rules_match_threaded(rules: &[&str]) {
+    let mut rng = XorShiftRng::from_seed([7; 16]);
+    for rule in rules {
+      let rule: Rule = rule.parse().unwrap();
+      let (width, height) = rule
+        .grid()
+        .map_or((40, 30), |grid| (grid.width, grid.height));
+      let cells = random_cells(&mut rng, width * height);
 
-  for y in 0..height {
-    for x in 0..width {
-      print!("{}", if data[x + y * width] > 0 { 'x' } else { ' ' });
+      for &layout in &[Layout::Bytes, Layout::Packed] {
+        if !layout.supports(&rule) {
+          continue;
+        }
+
+        let mut gpu = GpuEngine::with_layout(rule, layout).unwrap();
+        let mut threaded = ThreadedEngine::new(rule, 2, Scheduling::Dynamic);
+        gpu.load(width, height, &cells).unwrap();
+        threaded.load(width, height, &cells).unwrap();
+
+        for generation in 1..=20 {
+          gpu.step(1).unwrap();
+          threaded.step(1).unwrap();
+          assert_eq!(
+            gpu.hash().unwrap(),
+            threaded.hash().unwrap(),
+            "{} diverged in generation #{} with the {:?} layout",
+            rule,
+            generation,
+            layout
+          );
+          assert_eq!(gpu.population().unwrap(), threaded.population().unwrap());
+        }
+      }
     }
-    println!();
   }
-}
 
-fn move_cursor_up(lines: u16) {
-  print!("\x1b[{}A", lines);
+  #[test]
+  fn pack_round_trip() {
+    let mut rng = XorShiftRng::from_seed([3; 16]);
+    for &(width, height) in &[(1, 1), (31, 2), (32, 3), (33, 4), (100, 7)] {
+      let cells = random_cells(&mut rng, width * height);
+      let words = pack(&cells, width, height);
+      assert_eq!(words.len(), (width + 31) / 32 * height);
+      assert_eq!(unpack(&words, width, height), cells);
+    }
+
+    let mut cells = vec![DEAD; 40];
+    cells[0] = ALIVE;
+    cells[33] = ALIVE;
+    assert_eq!(pack(&cells, 40, 1), vec![1, 2]);
+  }
+
+  #[test]
+  fn layouts() {
+    let conway = Rule::default();
+    assert_eq!(Layout::best(&conway), Layout::Packed);
+    assert!(Layout::Bytes.supports(&conway));
+
+    let brain: Rule = "B2/S/C3".parse().unwrap();
+    let bosco: Rule = "R5,C0,M1,S34..58,B34..45".parse().unwrap();
+    assert_eq!(Layout::best(&brain), Layout::Bytes);
+    assert_eq!(Layout::best(&bosco), Layout::Bytes);
+  }
+
+  #[test]
+  fn rules_match_threaded() {
+    if !opencl_available() {
+      return;
+    }
+
+    assert_rules_match_threaded(&[
+      "B3/S23",
+      "B3/S23:T40,30",
+      "B2-a/S12:K40*,30",
+      "B2/S/C3:K40,30*",
+      "B2/S34H:C40,30",
+      "B2/S3V:S40",
+      "R2,C0,M1,S5..8,B6..7:T40,30",
+      "R3,C4,M0,S6..12,B7..9,NN:P40,30",
+    ]);
+  }
+
+  // widths which aren't multiples of 32 leave cells of the edge and wrapped
+  // cells in the padding of the last word of every row, and worlds larger
+  // than a work-group read their halo from other tiles
+  #[test]
+  fn packed_matches_threaded() {
+    if !opencl_available() {
+      return;
+    }
+
+    assert_rules_match_threaded(&[
+      "B3/S23:P45,30",
+      "B3/S23:T70,33",
+      "B3/S23:T600,20",
+      "B36/S23:K64*,20",
+      "B36/S23:K33,20*",
+      "B2-a/S12:C33,40",
+      "B2/S34H:T96,17",
+      "B3/S23:S37",
+      "B3/S23:T2,2",
+    ]);
+  }
+
+  // worlds larger than all work-groups of the population kernels together
+  // are counted in several elements per work-item
+  #[test]
+  fn population_is_counted_on_device() {
+    if !opencl_available() {
+      return;
+    }
+
+    let mut rng = XorShiftRng::from_seed([5; 16]);
+    for &(width, height) in &[(1, 1), (33, 7), (300, 300), (1000, 77)] {
+      let cells = random_cells(&mut rng, width * height);
+      let population = cells.iter().filter(|&&cell| cell == ALIVE).count();
+
+      for &layout in &[Layout::Bytes, Layout::Packed] {
+        let mut engine =
+          GpuEngine::with_layout(Rule::default(), layout).unwrap();
+        assert_eq!(engine.population().unwrap(), 0);
+        engine.load(width, height, &cells).unwrap();
+        assert_eq!(engine.population().unwrap(), population as u64);
+      }
+    }
+  }
+
+  const BENCH_SIZE: usize = 1024;
+  const BENCH_GENERATIONS: u64 = 16;
+
+  /// Computes generations of a random torus, the engine is created once so
+  /// compiling the program isn't measured.
+  fn bench_layout(bencher: &mut Bencher, layout: Layout) {
+    if !opencl_available() {
+      return;
+    }
+
+    let rule = format!("B3/S23:T{},{}", BENCH_SIZE, BENCH_SIZE);
+    let mut engine =
+      GpuEngine::with_layout(rule.parse().unwrap(), layout).unwrap();
+    let mut rng = XorShiftRng::from_seed([1; 16]);
+    let cells = random_cells(&mut rng, BENCH_SIZE * BENCH_SIZE);
+    engine.load(BENCH_SIZE, BENCH_SIZE, &cells).unwrap();
+
+    bencher.iter(|| {
+      engine.step(BENCH_GENERATIONS).unwrap();
+      engine.queue.finish().unwrap();
+    });
+  }
+
+  #[bench]
+  fn bytes(bencher: &mut Bencher) {
+    bench_layout(bencher, Layout::Bytes);
+  }
+
+  #[bench]
+  fn packed(bencher: &mut Bencher) {
+    bench_layout(bencher, Layout::Packed);
+  }
 }
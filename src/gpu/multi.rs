@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+
+use failure::{err_msg, Error};
+
+use super::ocl::{Device, Platform, Program, Queue, Result as OclResult};
+use super::{
+  hash_world_data, open_device, program, region, Buffers, Cell, Layout,
+  Transfer,
+};
+use engine::Engine;
+use rule::{Rule, State, DEAD};
+use threaded::{partition, world::Sector};
+use topology::Topology;
+
+/// The GPU backend on several devices. The world is split into sectors by
+/// [`partition`], which are assigned to the devices in turns. Every device
+/// computes its sectors as planes padded with halos, copies of the cells
+/// around the sector. The halos are exchanged through the host before every
+/// `exchange` generations, so they are `exchange` times the range of the
+/// rule wide.
+///
+/// [`partition`]: ../../threaded/fn.partition.html
+pub struct MultiGpuEngine {
+  rule: Rule,
+  layout: Layout,
+  /// Generations between exchanges of halos.
+  exchange: u64,
+  devices: Vec<(Program, Queue)>,
+  world: Option<SplitWorld>,
+  generation: u64,
+}
+
+struct SplitWorld {
+  width: usize,
+  height: usize,
+  strips: Vec<Strip>,
+}
+
+/// A sector of the world and its halos on a device.
+struct Strip {
+  sector: Sector,
+  /// Width of the halos on every side of the sector.
+  halo_width: usize,
+  buffers: Buffers,
+  /// Cells of the sector which are copied into halos, if there are any.
+  border: Option<Transfer>,
+  /// The cells of the halos.
+  halo: Transfer,
+  /// Where the cells of the halos are copied from: a strip and an index of
+  /// its border, or `None` if the cell is always dead.
+  sources: Vec<Option<(usize, usize)>>,
+  /// Cells of the halos which are always dead, which are cleared after every
+  /// generation if there are several generations between exchanges.
+  dead: Option<Transfer>,
+  /// Clears every cell of the halos, see `population`.
+  clear: Transfer,
+}
+
+impl MultiGpuEngine {
+  pub fn new(
+    rule: Rule,
+    layout: Layout,
+    devices: &[(Platform, Device)],
+    exchange: u64,
+  ) -> Result<Self, Error> {
+    if !layout.supports(&rule) {
+      return Err(err_msg(format!(
+        "the {:?} layout doesn't support {}",
+        layout, rule
+      )));
+    }
+    if devices.is_empty() {
+      return Err(err_msg("there are no devices to run on"));
+    }
+    if exchange == 0 {
+      return Err(err_msg(
+        "halos must be exchanged after 1 generation at least",
+      ));
+    }
+
+    // cells of halos evolve like the cells they are copies of only if the
+    // halo isn't mirrored or rotated relative to the sector
+    let untwisted = match rule.topology() {
+      Topology::Plane | Topology::Torus => true,
+      _ => false,
+    };
+    if exchange > 1 && !untwisted {
+      return Err(err_msg(format!(
+        "halos of {} must be exchanged every generation",
+        rule
+      )));
+    }
+
+    let source = program::source_with_topology(&rule, Topology::Plane);
+    let devices = devices
+      .iter()
+      .map(|&(platform, device)| open_device(platform, device, source.clone()))
+      .collect::<OclResult<Vec<_>>>()?;
+
+    Ok(MultiGpuEngine {
+      rule,
+      layout,
+      exchange,
+      devices,
+      world: None,
+      generation: 0,
+    })
+  }
+
+  /// Reads the cells of the world from the devices.
+  fn read_cells(&self) -> OclResult<(usize, usize, Vec<Cell>)> {
+    let world = match self.world {
+      Some(ref world) => world,
+      None => return Ok((0, 0, Vec::new())),
+    };
+
+    let mut cells = vec![DEAD; world.width * world.height];
+    for strip in &world.strips {
+      let (padded_width, padded_height) = strip.padded_size();
+      let padded = strip.buffers.read_cells(padded_width, padded_height)?;
+      let sector = &strip.sector;
+      for y in 0..sector.height {
+        let start = strip.halo_width + (y + strip.halo_width) * padded_width;
+        let world_start = sector.x + (sector.y + y) * world.width;
+        cells[world_start..world_start + sector.width]
+          .copy_from_slice(&padded[start..start + sector.width]);
+      }
+    }
+    Ok((world.width, world.height, cells))
+  }
+}
+
+impl SplitWorld {
+  /// Copies the borders of every strip into the halos of the others.
+  fn exchange(&mut self) -> OclResult<()> {
+    // the devices gather their borders in parallel
+    for strip in &self.strips {
+      if let Some(ref border) = strip.border {
+        border.enq(&strip.buffers)?;
+      }
+    }
+
+    let mut borders = Vec::with_capacity(self.strips.len());
+    for strip in &self.strips {
+      borders.push(match strip.border {
+        Some(ref border) => {
+          let mut values = vec![DEAD; border.values.len()];
+          border.values.read(&mut values).enq()?;
+          values
+        }
+        None => Vec::new(),
+      });
+    }
+
+    for strip in &self.strips {
+      let values = strip
+        .sources
+        .iter()
+        .map(|source| {
+          source.map_or(DEAD, |(strip, index)| borders[strip][index])
+        })
+        .collect::<Vec<_>>();
+      strip.halo.values.write(&values).enq()?;
+      strip.halo.enq(&strip.buffers)?;
+    }
+    Ok(())
+  }
+}
+
+impl Strip {
+  fn padded_size(&self) -> (usize, usize) {
+    (
+      self.sector.width + 2 * self.halo_width,
+      self.sector.height + 2 * self.halo_width,
+    )
+  }
+
+  fn next_generation(&mut self) -> OclResult<()> {
+    self.buffers.next_generation()?;
+    if let Some(ref dead) = self.dead {
+      dead.enq(&self.buffers)?;
+    }
+    Ok(())
+  }
+
+  /// Counts the alive cells of the sector. The halos are cleared, which
+  /// doesn't matter as they are exchanged before the next generation anyway.
+  fn population(&self) -> OclResult<u64> {
+    self.clear.enq(&self.buffers)?;
+    self.buffers.population()
+  }
+}
+
+/// The indices of the cells of a strip which are copied in halo exchanges.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct HaloPlan {
+  /// Cells copied into halos of strips.
+  border: Vec<u32>,
+  /// The cells of the halos of the strip.
+  halo: Vec<u32>,
+  /// See [`Strip::sources`](struct.Strip.html#structfield.sources).
+  sources: Vec<Option<(usize, usize)>>,
+}
+
+/// Finds where every cell of the halos of `sectors` of a `width` x `height`
+/// world comes from. Indices are relative to the sectors padded with
+/// `halo_width` cells on every side.
+fn plan_halos(
+  sectors: &[Sector],
+  (width, height): (usize, usize),
+  topology: Topology,
+  halo_width: usize,
+) -> Vec<HaloPlan> {
+  let mut plans = vec![HaloPlan::default(); sectors.len()];
+  // indices of cells in the borders of every strip
+  let mut borders = vec![HashMap::new(); sectors.len()];
+
+  for (strip, sector) in sectors.iter().enumerate() {
+    let padded_width = sector.width + 2 * halo_width;
+    let padded_height = sector.height + 2 * halo_width;
+    for y in 0..padded_height {
+      for x in 0..padded_width {
+        let inside = x >= halo_width
+          && x < halo_width + sector.width
+          && y >= halo_width
+          && y < halo_width + sector.height;
+        if inside {
+          continue;
+        }
+
+        let world_x = (sector.x + x) as isize - halo_width as isize;
+        let world_y = (sector.y + y) as isize - halo_width as isize;
+        let source =
+          topology
+            .wrap(world_x, world_y, width, height)
+            .map(|(x, y)| {
+              let owner = sectors
+                .iter()
+                .position(|sector| {
+                  x >= sector.x
+                    && x < sector.x + sector.width
+                    && y >= sector.y
+                    && y < sector.y + sector.height
+                })
+                .expect("sectors must cover the world");
+              let sector = &sectors[owner];
+              let index = (x - sector.x + halo_width)
+                + (y - sector.y + halo_width) * (sector.width + 2 * halo_width);
+
+              let border = &mut borders[owner];
+              let next = border.len();
+              (owner, *border.entry(index as u32).or_insert(next))
+            });
+
+        plans[strip].halo.push((x + y * padded_width) as u32);
+        plans[strip].sources.push(source);
+      }
+    }
+  }
+
+  for (plan, border) in plans.iter_mut().zip(borders) {
+    plan.border = vec![0; border.len()];
+    for (index, position) in border {
+      plan.border[position] = index;
+    }
+  }
+  plans
+}
+
+impl Engine for MultiGpuEngine {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    let sectors = partition(width, height, self.devices.len());
+    let halo_width = self.exchange as usize * self.rule.range();
+    let plans =
+      plan_halos(&sectors, (width, height), self.rule.topology(), halo_width);
+
+    let mut strips = Vec::with_capacity(sectors.len());
+    let devices = self.devices.iter().cycle();
+    for ((sector, plan), (program, queue)) in
+      sectors.into_iter().zip(plans).zip(devices)
+    {
+      let padded_width = sector.width + 2 * halo_width;
+      let padded_height = sector.height + 2 * halo_width;
+      let mut padded = vec![DEAD; padded_width * padded_height];
+      for y in 0..sector.height {
+        let start = halo_width + (y + halo_width) * padded_width;
+        let world_start = sector.x + (sector.y + y) * width;
+        padded[start..start + sector.width]
+          .copy_from_slice(&cells[world_start..world_start + sector.width]);
+      }
+
+      let (gather, scatter) = self.layout.transfer_kernel_names();
+      let device = (program, queue);
+      let transfer = |name, indices: &[u32]| {
+        Transfer::new(self.layout, device, name, padded_width, indices)
+      };
+
+      let dead = plan
+        .halo
+        .iter()
+        .zip(&plan.sources)
+        .filter(|&(_, source)| source.is_none())
+        .map(|(&index, _)| index)
+        .collect::<Vec<_>>();
+
+      strips.push(Strip {
+        halo_width,
+        buffers: Buffers::new(
+          self.layout,
+          program,
+          queue,
+          padded_width,
+          padded_height,
+          &padded,
+        )?,
+        border: if plan.border.is_empty() {
+          None
+        } else {
+          Some(transfer(gather, &plan.border)?)
+        },
+        halo: transfer(scatter, &plan.halo)?,
+        dead: if self.exchange > 1 && !dead.is_empty() {
+          Some(transfer(scatter, &dead)?)
+        } else {
+          None
+        },
+        clear: transfer(scatter, &plan.halo)?,
+        sources: plan.sources,
+        sector,
+      });
+    }
+
+    self.world = Some(SplitWorld {
+      width,
+      height,
+      strips,
+    });
+    self.generation = 0;
+    Ok(())
+  }
+
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    if let Some(ref mut world) = self.world {
+      let mut remaining = generations;
+      while remaining > 0 {
+        world.exchange()?;
+        let window = remaining.min(self.exchange);
+        for _ in 0..window {
+          for strip in &mut world.strips {
+            strip.next_generation()?;
+          }
+        }
+        remaining -= window;
+      }
+    }
+
+    for (_, queue) in &self.devices {
+      queue.finish()?;
+    }
+    self.generation += generations;
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let (world_width, world_height, data) = self.read_cells()?;
+    Ok(region(
+      &data,
+      world_width,
+      world_height,
+      (x, y, width, height),
+    ))
+  }
+
+  fn population(&self) -> Result<u64, Error> {
+    let mut population = 0;
+    if let Some(ref world) = self.world {
+      for strip in &world.strips {
+        population += strip.population()?;
+      }
+    }
+    Ok(population)
+  }
+
+  fn hash(&self) -> Result<u64, Error> {
+    let (width, height, data) = self.read_cells()?;
+    Ok(hash_world_data(&data, width, height))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate rand;
+  use self::rand::prng::XorShiftRng;
+  use self::rand::SeedableRng;
+
+  use std::ptr;
+
+  use super::super::ocl::{core::DeviceId, ffi, flags};
+  use super::*;
+  use gpu::device::{self, DeviceSelection};
+  use gpu::tests::{opencl_available, random_cells};
+  use threaded::{scheduler::Scheduling, ThreadedEngine};
+
+  /// Partitions the first CPU device which supports it into sub-devices of
+  /// one compute unit each, or returns an empty list. The sub-devices have to
+  /// be released with `clReleaseDevice`.
+  fn cpu_sub_devices() -> Vec<(Platform, Device)> {
+    let properties = [
+      ffi::CL_DEVICE_PARTITION_EQUALLY as ffi::cl_device_partition_property,
+      1,
+      0,
+    ];
+
+    for platform in device::platforms() {
+      let devices = Device::list(platform, Some(flags::DEVICE_TYPE_CPU))
+        .unwrap_or_default();
+      for device in devices {
+        let device = device.as_core().as_raw();
+        let mut count = 0;
+        let status = unsafe {
+          ffi::clCreateSubDevices(
+            device,
+            properties.as_ptr(),
+            0,
+            ptr::null_mut(),
+            &mut count,
+          )
+        };
+        if status != ffi::CL_SUCCESS || count < 2 {
+          continue;
+        }
+
+        let mut sub_devices = vec![ptr::null_mut(); count as usize];
+        let status = unsafe {
+          ffi::clCreateSubDevices(
+            device,
+            properties.as_ptr(),
+            count,
+            sub_devices.as_mut_ptr(),
+            ptr::null_mut(),
+          )
+        };
+        if status != ffi::CL_SUCCESS {
+          continue;
+        }
+
+        return sub_devices
+          .into_iter()
+          .map(|id| (platform, Device::from(unsafe { DeviceId::from_raw(id) })))
+          .collect();
+      }
+    }
+    Vec::new()
+  }
+
+  // copies the borders into the halos on the host and checks that every cell
+  // of the halos is the cell of the world it's a copy of
+  #[test]
+  fn halos_are_copies_of_their_cells() {
+    let mut rng = XorShiftRng::from_seed([9; 16]);
+    let cases = [
+      ("B3/S23:P150,140", 3),
+      ("B3/S23:T150,140", 3),
+      ("B3/S23:K150*,140", 1),
+      ("B3/S23:K150,140*", 1),
+      ("B3/S23:C150,140", 1),
+      ("B3/S23:S140", 1),
+    ];
+
+    for &(rule, halo_width) in cases.iter() {
+      let rule: Rule = rule.parse().unwrap();
+      let grid = rule.grid().unwrap();
+      let (width, height) = (grid.width, grid.height);
+      let world = random_cells(&mut rng, width * height);
+
+      for &parts in &[1, 2, 3, 5] {
+        let sectors = partition(width, height, parts);
+        let plans =
+          plan_halos(&sectors, (width, height), rule.topology(), halo_width);
+
+        let padded = sectors
+          .iter()
+          .map(|sector| {
+            let padded_width = sector.width + 2 * halo_width;
+            let mut padded =
+              vec![DEAD; padded_width * (sector.height + 2 * halo_width)];
+            for y in 0..sector.height {
+              for x in 0..sector.width {
+                padded[x + halo_width + (y + halo_width) * padded_width] =
+                  world[sector.x + x + (sector.y + y) * width];
+              }
+            }
+            padded
+          })
+          .collect::<Vec<_>>();
+        let borders = plans
+          .iter()
+          .zip(&padded)
+          .map(|(plan, padded)| {
+            plan
+              .border
+              .iter()
+              .map(|&index| padded[index as usize])
+              .collect::<Vec<_>>()
+          })
+          .collect::<Vec<_>>();
+
+        for (strip, (sector, plan)) in sectors.iter().zip(&plans).enumerate() {
+          let padded_width = sector.width + 2 * halo_width;
+          assert_eq!(plan.halo.len(), plan.sources.len());
+          for (&index, source) in plan.halo.iter().zip(&plan.sources) {
+            let index = index as usize;
+            let x =
+              (sector.x + index % padded_width) as isize - halo_width as isize;
+            let y =
+              (sector.y + index / padded_width) as isize - halo_width as isize;
+            let expected = rule
+              .topology()
+              .wrap(x, y, width, height)
+              .map_or(DEAD, |(x, y)| world[x + y * width]);
+            let copied =
+              source.map_or(DEAD, |(strip, index)| borders[strip][index]);
+            assert_eq!(
+              copied, expected,
+              "cell ({}, {}) of the halo of strip #{} of {} with {} parts",
+              x, y, strip, rule, parts
+            );
+          }
+        }
+      }
+    }
+  }
+
+  // several strips on the same device exchange halos just like strips on
+  // different devices
+  #[test]
+  fn matches_threaded() {
+    if !opencl_available() {
+      return;
+    }
+
+    let device = DeviceSelection::default().select().unwrap();
+    let cases = [
+      ("B3/S23:P130,70", 1),
+      ("B3/S23:P130,70", 3),
+      ("B3/S23:T200,150", 2),
+      ("B36/S23:K140*,130", 1),
+      ("B2-a/S12:C130,140", 1),
+      ("B2/S34H:S140", 1),
+      ("B2/S/C3:T130,70", 4),
+      ("R2,C0,M1,S5..8,B6..7:T140,70", 2),
+      ("R2,C0,M1,S5..8,B6..7:K140,70*", 1),
+    ];
+
+    let mut rng = XorShiftRng::from_seed([11; 16]);
+    for &(rule, exchange) in cases.iter() {
+      let rule: Rule = rule.parse().unwrap();
+      let grid = rule.grid().unwrap();
+      let cells = random_cells(&mut rng, grid.width * grid.height);
+
+      for &layout in &[Layout::Bytes, Layout::Packed] {
+        if !layout.supports(&rule) {
+          continue;
+        }
+
+        for &devices in &[2, 3] {
+          let mut gpu =
+            MultiGpuEngine::new(rule, layout, &vec![device; devices], exchange)
+              .unwrap();
+          let mut threaded = ThreadedEngine::new(rule, 2, Scheduling::Dynamic);
+          gpu.load(grid.width, grid.height, &cells).unwrap();
+          threaded.load(grid.width, grid.height, &cells).unwrap();
+
+          // steps which aren't multiples of the exchange too
+          for &generations in &[1, 2, 5, 1, 7] {
+            gpu.step(generations).unwrap();
+            threaded.step(generations).unwrap();
+            assert_eq!(
+              gpu.hash().unwrap(),
+              threaded.hash().unwrap(),
+              "{} on {} devices with the {:?} layout diverged by \
+               generation #{}",
+              rule,
+              devices,
+              layout,
+              gpu.generation()
+            );
+            assert_eq!(
+              gpu.population().unwrap(),
+              threaded.population().unwrap()
+            );
+          }
+        }
+      }
+    }
+  }
+
+  // sub-devices (e.g. of POCL) have their own contexts and queues, so halos
+  // are really exchanged between devices
+  #[test]
+  fn matches_threaded_on_sub_devices() {
+    if !opencl_available() {
+      return;
+    }
+    let sub_devices = cpu_sub_devices();
+    if sub_devices.is_empty() {
+      // no CPU device can be partitioned
+      return;
+    }
+
+    let devices = &sub_devices[..sub_devices.len().min(4)];
+    let cases = [
+      ("B3/S23:T200,150", 1),
+      ("B3/S23:P130,70", 3),
+      ("B36/S23:K140*,130", 1),
+      ("R2,C0,M1,S5..8,B6..7:T140,70", 2),
+    ];
+
+    let mut rng = XorShiftRng::from_seed([13; 16]);
+    for &(rule, exchange) in cases.iter() {
+      let rule: Rule = rule.parse().unwrap();
+      let grid = rule.grid().unwrap();
+      let cells = random_cells(&mut rng, grid.width * grid.height);
+
+      let layout = Layout::best(&rule);
+      let mut gpu =
+        MultiGpuEngine::new(rule, layout, devices, exchange).unwrap();
+      let mut threaded = ThreadedEngine::new(rule, 2, Scheduling::Dynamic);
+      gpu.load(grid.width, grid.height, &cells).unwrap();
+      threaded.load(grid.width, grid.height, &cells).unwrap();
+
+      for &generations in &[1, 4, 7] {
+        gpu.step(generations).unwrap();
+        threaded.step(generations).unwrap();
+        assert_eq!(
+          gpu.hash().unwrap(),
+          threaded.hash().unwrap(),
+          "{} on {} sub-devices diverged by generation #{}",
+          rule,
+          devices.len(),
+          gpu.generation()
+        );
+      }
+      assert_eq!(gpu.population().unwrap(), threaded.population().unwrap());
+    }
+
+    for &(_, sub_device) in &sub_devices {
+      unsafe { ffi::clReleaseDevice(sub_device.as_core().as_raw()) };
+    }
+  }
+
+  #[test]
+  fn twisted_halos_are_exchanged_every_generation() {
+    if !opencl_available() {
+      return;
+    }
+
+    let device = DeviceSelection::default().select().unwrap();
+    let rule = "B3/S23:K100*,100".parse().unwrap();
+    assert!(MultiGpuEngine::new(rule, Layout::Bytes, &[device], 2).is_err());
+    assert!(MultiGpuEngine::new(rule, Layout::Bytes, &[device], 1).is_ok());
+  }
+}
@@ -1,25 +1,318 @@
-__kernel void next_generation(__global const uchar *world, __global uchar *next_world) {
-  size_t x = get_global_id(0);
-  size_t y = get_global_id(1);
-  size_t w = get_global_size(0);
-  size_t h = get_global_size(1);
-
-  bool is_left   = x > 0  ;
-  bool is_right  = x < w-1;
-  bool is_top    = y > 0  ;
-  bool is_bottom = y < h-1;
-
-  size_t n
-    = (is_top                ? world[ x      + (y - 1)*w] : 0) // top
-    + (is_right && is_top    ? world[(x + 1) + (y - 1)*w] : 0) // top right
-    + (is_right              ? world[(x + 1) +  y     *w] : 0) // right
-    + (is_right && is_bottom ? world[(x + 1) + (y + 1)*w] : 0) // bottom right
-    + (is_bottom             ? world[ x      + (y + 1)*w] : 0) // bottom
-    + (is_left  && is_bottom ? world[(x - 1) + (y + 1)*w] : 0) // bottom left
-    + (is_left               ? world[(x - 1) +  y     *w] : 0) // left
-    + (is_left  && is_top    ? world[(x - 1) + (y - 1)*w] : 0) // top left
-    ;
+// This is a template: the definitions of the rule and the topology are
+// generated by `program::source` in program.rs and prepended to it:
+//
+// - TOPOLOGY is one of the topologies below;
+// - STATES is the number of states of the rule;
+// - NEXT_STATE[cell][index] is the next state of a dead (0) or alive (1)
+//   cell, indexed by the configuration of alive neighbors (see `neighbors`
+//   in rule.rs) or, if LARGER_THAN_LIFE is defined, by the number of alive
+//   cells in the neighborhood including the cell itself;
+// - for Larger than Life rules RECTANGLES lists RECTANGLES_LEN rectangles
+//   (x0, y0, x1, y1) with inclusive offsets which cover the neighborhood;
+// - TILE_WIDTH x TILE_HEIGHT is the size of work-groups of the bit-packed
+//   kernel in words;
+// - REDUCTION_SIZE is the size of work-groups of the population kernels, a
+//   power of two.
+
+// Topologies of the world, see `topology_code` in program.rs.
+#define PLANE                   0
+#define TORUS                   1
+#define KLEIN_BOTTLE_TOP_BOTTOM 2
+#define KLEIN_BOTTLE_LEFT_RIGHT 3
+#define CROSS_SURFACE           4
+#define SPHERE                  5
+
+// Returns how many times `value` has crossed the edges of `0..size` and
+// wraps it into them.
+long wrap_laps(long *value, long size) {
+  long laps = *value < 0 ? (*value + 1) / size - 1 : *value / size;
+  *value -= laps * size;
+  return laps;
+}
+
+// Maps a cell at (x, y), which may be outside of the world (but not further
+// than the size of the world), into the world the same way as
+// `Topology::wrap` does it. Returns false if the cell is always dead.
+bool wrap(long *cell_x, long *cell_y, long w, long h) {
+  long x = *cell_x;
+  long y = *cell_y;
+  bool x_outside = x < 0 || x >= w;
+  bool y_outside = y < 0 || y >= h;
+
+  if (x_outside || y_outside) {
+#if TOPOLOGY == PLANE
+    return false;
+#elif TOPOLOGY == SPHERE
+    // cells beyond a corner don't correspond to any cell
+    if (x_outside && y_outside) {
+      return false;
+    }
+
+    long sphere_x, sphere_y;
+    if      (y < 0 ) { sphere_x = -y - 1;          sphere_y = x;               }
+    else if (y >= h) { sphere_x = w - 1 - (y - h); sphere_y = x;               }
+    else if (x < 0 ) { sphere_x = y;               sphere_y = -x - 1;          }
+    else             { sphere_x = y;               sphere_y = h - 1 - (x - w); }
+    x = sphere_x;
+    y = sphere_y;
+    if (x < 0 || x >= w || y < 0 || y >= h) {
+      return false;
+    }
+#else
+    long x_laps = wrap_laps(&x, w);
+    long y_laps = wrap_laps(&y, h);
+    // crossing a twisted edge mirrors the other coordinate
+#if TOPOLOGY == KLEIN_BOTTLE_TOP_BOTTOM || TOPOLOGY == CROSS_SURFACE
+    if (y_laps % 2 != 0) x = w - 1 - x;
+#endif
+#if TOPOLOGY == KLEIN_BOTTLE_LEFT_RIGHT || TOPOLOGY == CROSS_SURFACE
+    if (x_laps % 2 != 0) y = h - 1 - y;
+#endif
+#endif
+  }
+
+  *cell_x = x;
+  *cell_y = y;
+  return true;
+}
+
+// Returns 1 if the cell at (x, y) is alive, see `wrap`.
+uint is_alive(__global const uchar *world, long x, long y, long w, long h) {
+  return wrap(&x, &y, w, h) && world[x + y*w] == 1;
+}
+
+// Cells in states from 2 to STATES - 1 are dying cells of "Generations"
+// rules, they aren't counted as neighbors and just advance to the next state.
+__kernel void next_generation(__global const uchar *world,
+                              __global uchar *next_world) {
+  long x = get_global_id(0);
+  long y = get_global_id(1);
+  long w = get_global_size(0);
+  long h = get_global_size(1);
 
   size_t index = x + y*w;
-  next_world[index] = world[index] ? (n == 2 || n == 3) : (n == 3);
+  uchar cell = world[index];
+  if (cell > 1) {
+    next_world[index] = cell + 1 < STATES ? cell + 1 : 0;
+    return;
+  }
+
+#ifdef LARGER_THAN_LIFE
+  uint count = 0;
+  for (int i = 0; i < RECTANGLES_LEN; i++) {
+    for (long dy = RECTANGLES[i][1]; dy <= RECTANGLES[i][3]; dy++) {
+      for (long dx = RECTANGLES[i][0]; dx <= RECTANGLES[i][2]; dx++) {
+        count += is_alive(world, x + dx, y + dy, w, h);
+      }
+    }
+  }
+  next_world[index] = NEXT_STATE[cell][count];
+#else
+  uint neighbors
+    = is_alive(world, x,     y - 1, w, h) << 0 // top
+    | is_alive(world, x + 1, y - 1, w, h) << 1 // top right
+    | is_alive(world, x + 1, y,     w, h) << 2 // right
+    | is_alive(world, x + 1, y + 1, w, h) << 3 // bottom right
+    | is_alive(world, x,     y + 1, w, h) << 4 // bottom
+    | is_alive(world, x - 1, y + 1, w, h) << 5 // bottom left
+    | is_alive(world, x - 1, y,     w, h) << 6 // left
+    | is_alive(world, x - 1, y - 1, w, h) << 7 // top left
+    ;
+  next_world[index] = NEXT_STATE[cell][neighbors];
+#endif
+}
+
+#ifndef LARGER_THAN_LIFE
+
+// Bit-packed worlds store every row in (w + 31) / 32 words, the cell at x is
+// the bit x % 32 of the word x / 32. Bits beyond the right edge are 0.
+
+// Returns 1 if the cell at (x, y) of a bit-packed world is alive, see `wrap`.
+uint is_alive_packed(__global const uint *world, long x, long y, long w,
+                     long h) {
+  long words = (w + 31) / 32;
+  return wrap(&x, &y, w, h) && (world[x / 32 + y*words] >> (x % 32)) & 1;
+}
+
+// Returns the word at (word_x, y) as the neighbors of the cells next to it
+// see it. Words within the world are read as they are, others (including
+// the last word of a row, whose bits beyond the edge are wrapped cells) are
+// assembled cell by cell.
+uint load_word(__global const uint *world, long word_x, long y, long w,
+               long h) {
+  long words = (w + 31) / 32;
+  if (word_x >= 0 && (word_x + 1) * 32 <= w && y >= 0 && y < h) {
+    return world[word_x + y*words];
+  }
+
+  uint word = 0;
+  for (int i = 0; i < 32; i++) {
+    word |= is_alive_packed(world, word_x * 32 + i, y, w, h) << i;
+  }
+  return word;
+}
+
+// Computes a word of 32 cells of a bit-packed world per work-item. A
+// work-group loads its tile of words and the halo of words around it into
+// local memory first, so every word is read from global memory once per
+// work-group instead of nine times. Only two-state rules are supported.
+__kernel __attribute__((reqd_work_group_size(TILE_WIDTH, TILE_HEIGHT, 1)))
+void next_generation_packed(__global const uint *world,
+                            __global uint *next_world,
+                            uint width, uint height) {
+  __local uint tile[TILE_HEIGHT + 2][TILE_WIDTH + 2];
+
+  long w = width;
+  long h = height;
+  long words = (w + 31) / 32;
+  long local_x = get_local_id(0);
+  long local_y = get_local_id(1);
+
+  long first_word = get_group_id(0) * TILE_WIDTH - 1;
+  long first_row = get_group_id(1) * TILE_HEIGHT - 1;
+  for (long i = local_x + local_y * TILE_WIDTH;
+       i < (TILE_WIDTH + 2) * (TILE_HEIGHT + 2);
+       i += TILE_WIDTH * TILE_HEIGHT) {
+    long tile_x = i % (TILE_WIDTH + 2);
+    long tile_y = i / (TILE_WIDTH + 2);
+    tile[tile_y][tile_x] =
+      load_word(world, first_word + tile_x, first_row + tile_y, w, h);
+  }
+  barrier(CLK_LOCAL_MEM_FENCE);
+
+  // the global size is rounded up to whole work-groups
+  long word_x = get_global_id(0);
+  long y = get_global_id(1);
+  if (word_x >= words || y >= h) {
+    return;
+  }
+
+  // rows of the cells above, next to and below the word, shifted so that
+  // the bits i, i + 1 and i + 2 are the left neighbor of the cell i, the
+  // cell itself and its right neighbor
+  ulong rows[3];
+  for (int row = 0; row < 3; row++) {
+    rows[row] = (ulong)tile[local_y + row][local_x] >> 31
+              | (ulong)tile[local_y + row][local_x + 1] << 1
+              | (ulong)tile[local_y + row][local_x + 2] << 33;
+  }
+
+  uint next = 0;
+  for (int i = 0; i < 32; i++) {
+    uint above = rows[0] >> i;
+    uint middle = rows[1] >> i;
+    uint below = rows[2] >> i;
+    uint neighbors
+      = (above  >> 1 & 1) << 0 // top
+      | (above  >> 2 & 1) << 1 // top right
+      | (middle >> 2 & 1) << 2 // right
+      | (below  >> 2 & 1) << 3 // bottom right
+      | (below  >> 1 & 1) << 4 // bottom
+      | (below       & 1) << 5 // bottom left
+      | (middle      & 1) << 6 // left
+      | (above       & 1) << 7 // top left
+      ;
+    next |= (uint)NEXT_STATE[middle >> 1 & 1][neighbors] << i;
+  }
+
+  // cells beyond the right edge stay dead
+  long cells = w - word_x * 32;
+  if (cells < 32) {
+    next &= (1u << cells) - 1;
+  }
+  next_world[word_x + y*words] = next;
+}
+
+#endif
+
+// Population counts: every work-item counts the alive cells in every
+// (global size)-th element of the world, then every work-group adds up the
+// counts of its work-items in local memory and writes the sum to
+// counts[group]. The host adds up the sums of the work-groups.
+
+void reduce(uint count, __local uint *sums, __global uint *counts) {
+  size_t id = get_local_id(0);
+  sums[id] = count;
+  barrier(CLK_LOCAL_MEM_FENCE);
+
+  for (size_t half = REDUCTION_SIZE / 2; half > 0; half /= 2) {
+    if (id < half) {
+      sums[id] += sums[id + half];
+    }
+    barrier(CLK_LOCAL_MEM_FENCE);
+  }
+
+  if (id == 0) {
+    counts[get_group_id(0)] = sums[0];
+  }
+}
+
+__kernel __attribute__((reqd_work_group_size(REDUCTION_SIZE, 1, 1)))
+void population(__global const uchar *world, ulong len,
+                __global uint *counts) {
+  __local uint sums[REDUCTION_SIZE];
+  uint count = 0;
+  for (size_t i = get_global_id(0); i < len; i += get_global_size(0)) {
+    count += world[i] == 1;
+  }
+  reduce(count, sums, counts);
+}
+
+// Bits beyond the right edge of bit-packed worlds are 0, so every bit is a
+// cell.
+__kernel __attribute__((reqd_work_group_size(REDUCTION_SIZE, 1, 1)))
+void population_packed(__global const uint *world, ulong len,
+                       __global uint *counts) {
+  __local uint sums[REDUCTION_SIZE];
+  uint count = 0;
+  for (size_t i = get_global_id(0); i < len; i += get_global_size(0)) {
+    count += popcount(world[i]);
+  }
+  reduce(count, sums, counts);
+}
+
+// Halo exchange of worlds split across devices: every device computes a
+// strip of the world padded with halos, which are copies of cells of other
+// strips. gather copies the cells at indices (x + y * width of the padded
+// strip) into values, scatter copies values into the cells at indices. The
+// width is only needed by the bit-packed variants.
+
+__kernel void gather(__global const uchar *world, uint width,
+                     __global const uint *indices, __global uchar *values) {
+  size_t i = get_global_id(0);
+  values[i] = world[indices[i]];
+}
+
+__kernel void scatter(__global uchar *world, uint width,
+                      __global const uint *indices,
+                      __global const uchar *values) {
+  size_t i = get_global_id(0);
+  world[indices[i]] = values[i];
+}
+
+// Index of the word of the cell at index in a bit-packed world.
+size_t word_index(uint index, uint width) {
+  return index / width * ((width + 31) / 32) + index % width / 32;
+}
+
+__kernel void gather_packed(__global const uint *world, uint width,
+                            __global const uint *indices,
+                            __global uchar *values) {
+  size_t i = get_global_id(0);
+  uint index = indices[i];
+  values[i] = world[word_index(index, width)] >> (index % width % 32) & 1;
+}
+
+// Cells of the same word may be written by several work-items at once.
+__kernel void scatter_packed(__global uint *world, uint width,
+                             __global const uint *indices,
+                             __global const uchar *values) {
+  size_t i = get_global_id(0);
+  uint index = indices[i];
+  uint bit = 1u << (index % width % 32);
+  if (values[i]) {
+    atomic_or(&world[word_index(index, width)], bit);
+  } else {
+    atomic_and(&world[word_index(index, width)], ~bit);
+  }
 }
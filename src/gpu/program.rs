@@ -0,0 +1,149 @@
+use std::fmt::Write;
+
+use rule::{Rule, State, ALIVE, DEAD};
+use topology::{Topology, Twist};
+
+/// The kernel, which is completed by the definitions generated by [`source`].
+///
+/// [`source`]: fn.source.html
+const TEMPLATE: &str = include_str!("program.cl");
+
+pub const KERNEL_NAME: &str = "next_generation";
+pub const PACKED_KERNEL_NAME: &str = "next_generation_packed";
+pub const POPULATION_KERNEL_NAME: &str = "population";
+pub const PACKED_POPULATION_KERNEL_NAME: &str = "population_packed";
+pub const GATHER_KERNEL_NAME: &str = "gather";
+pub const PACKED_GATHER_KERNEL_NAME: &str = "gather_packed";
+pub const SCATTER_KERNEL_NAME: &str = "scatter";
+pub const PACKED_SCATTER_KERNEL_NAME: &str = "scatter_packed";
+
+/// Size of work-groups of the bit-packed kernel: every work-item computes a
+/// word of 32 cells, so a work-group computes 512 x 8 cells.
+pub const TILE_WIDTH: usize = 16;
+pub const TILE_HEIGHT: usize = 8;
+
+/// Size of work-groups of the population kernels, each of them sums up the
+/// counts of its work-items in local memory.
+pub const REDUCTION_SIZE: usize = 256;
+
+/// Generates the source of the program which computes generations of `rule`.
+/// The rule is compiled into tables of next states, which are filled in by
+/// the rule itself, so the kernel supports every rule the CPU engines do. The
+/// bit-packed kernel is included for rules on range 1 neighborhoods.
+pub fn source(rule: &Rule) -> String {
+  source_with_topology(rule, rule.topology())
+}
+
+/// Generates the source of the program for `rule` on a world with another
+/// topology, such as the strips of worlds split across devices, which are
+/// planes padded with halos.
+pub fn source_with_topology(rule: &Rule, topology: Topology) -> String {
+  let mut source = String::new();
+  writeln!(source, "#define TOPOLOGY {}", topology_code(topology)).unwrap();
+  writeln!(source, "#define STATES {}", rule.states()).unwrap();
+  writeln!(source, "#define TILE_WIDTH {}", TILE_WIDTH).unwrap();
+  writeln!(source, "#define TILE_HEIGHT {}", TILE_HEIGHT).unwrap();
+  writeln!(source, "#define REDUCTION_SIZE {}", REDUCTION_SIZE).unwrap();
+
+  if rule.is_larger_than_life() {
+    let range = rule.range();
+    let rectangles = rule.neighborhood().rectangles(range);
+    writeln!(source, "#define LARGER_THAN_LIFE").unwrap();
+    writeln!(source, "#define RECTANGLES_LEN {}", rectangles.len()).unwrap();
+    let rectangles = rectangles
+      .iter()
+      .map(|&(x0, y0, x1, y1)| format!("{{{}, {}, {}, {}}}", x0, y0, x1, y1))
+      .collect::<Vec<_>>();
+    write_array(&mut source, "int", "RECTANGLES", 4, &rectangles);
+
+    // the count includes the cell itself, so an alive cell never has 0
+    let size = rule.neighborhood().size(range);
+    let next_states = [DEAD, ALIVE]
+      .iter()
+      .map(|&state| {
+        let next_states = (0..=size as u32).map(|count| match (state, count) {
+          (ALIVE, 0) => DEAD,
+          _ => rule.next_state_by_count(state, count),
+        });
+        states_row(next_states)
+      })
+      .collect::<Vec<_>>();
+    write_array(&mut source, "uchar", "NEXT_STATE", size + 1, &next_states);
+  } else {
+    let next_states = [DEAD, ALIVE]
+      .iter()
+      .map(|&state| {
+        states_row((0..=255).map(|neighbors| rule.next_state(state, neighbors)))
+      })
+      .collect::<Vec<_>>();
+    write_array(&mut source, "uchar", "NEXT_STATE", 256, &next_states);
+  }
+
+  source.push('\n');
+  source.push_str(TEMPLATE);
+  source
+}
+
+/// Returns the code of a topology used by the kernel.
+fn topology_code(topology: Topology) -> u32 {
+  match topology {
+    Topology::Plane => 0,
+    Topology::Torus => 1,
+    Topology::KleinBottle(Twist::TopBottom) => 2,
+    Topology::KleinBottle(Twist::LeftRight) => 3,
+    Topology::CrossSurface => 4,
+    Topology::Sphere => 5,
+  }
+}
+
+fn states_row<I: Iterator<Item = State>>(states: I) -> String {
+  let states = states.map(|state| state.to_string()).collect::<Vec<_>>();
+  format!("{{{}}}", states.join(", "))
+}
+
+/// Writes a `__constant` two-dimensional array of `rows`, which are already
+/// written in OpenCL C and have `length` elements each.
+fn write_array(
+  source: &mut String,
+  element_type: &str,
+  name: &str,
+  length: usize,
+  rows: &[String],
+) {
+  writeln!(
+    source,
+    "__constant {} {}[{}][{}] = {{\n  {}\n}};",
+    element_type,
+    name,
+    rows.len(),
+    length,
+    rows.join(",\n  ")
+  )
+  .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn definitions() {
+    let conway = source(&Rule::default());
+    assert!(conway.starts_with("#define TOPOLOGY 0\n#define STATES 2\n"));
+    assert!(!conway.contains("#define LARGER_THAN_LIFE"));
+    // a dead cell with the top, top right and right neighbors is born
+    assert!(conway.contains("__constant uchar NEXT_STATE[2][256] = {\n  {0, "));
+    let dead_row = conway.split('{').nth(2).unwrap();
+    assert_eq!(dead_row.split(", ").nth(0b111), Some("1"));
+
+    let brain = source(&"B2/S/C3:T10,10".parse().unwrap());
+    assert!(brain.starts_with("#define TOPOLOGY 1\n#define STATES 3\n"));
+
+    let bosco = source(&"R5,C0,M1,S34..58,B34..45:K20*,20".parse().unwrap());
+    assert!(bosco.starts_with("#define TOPOLOGY 2\n#define STATES 2\n"));
+    assert!(bosco.contains("#define RECTANGLES_LEN 1\n"));
+    assert!(bosco
+      .contains("__constant int RECTANGLES[1][4] = {\n  {-5, -5, 5, 5}\n};"));
+    assert!(bosco.contains("__constant uchar NEXT_STATE[2][122] = {"));
+  }
+}
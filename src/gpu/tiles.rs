@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use failure::{err_msg, Error};
+
+use super::device::DeviceSelection;
+use super::ocl::{Program, Queue, Result as OclResult};
+use super::{open_device, program, Buffers, Cell, Layout, Transfer};
+use protocol::{Stats, TileCells};
+use rule::{Rule, State, DEAD};
+use slave::Tiles;
+use tiling::{check_halos, copy_region, not_assigned, union, TileRect};
+use topology::Topology;
+use unbounded::world::TileCoord;
+
+/// The tiles assigned to a slave, kept in buffers of an OpenCL device. Every
+/// tile is computed as a plane padded with its halo, so only halos, borders
+/// and the regions which are asked for are copied between the host and the
+/// device.
+pub struct DeviceTiles {
+  rule: Rule,
+  layout: Layout,
+  program: Program,
+  queue: Queue,
+  /// The size of a bounded world.
+  size: Option<(usize, usize)>,
+  tiles: HashMap<TileCoord, DeviceTile>,
+}
+
+/// A tile padded with its halo, which is `range` cells wide.
+struct DeviceTile {
+  rect: TileRect,
+  range: usize,
+  buffers: Buffers,
+  /// Copies the border of the tile from the buffers.
+  border: Transfer,
+  /// Copies the halo into the buffers.
+  halo: Transfer,
+  /// Clears the halo, which is computed along with the tile but isn't part
+  /// of it, see `population`.
+  clear: Transfer,
+}
+
+impl DeviceTiles {
+  /// Opens the selected device. The program of `rule` is compiled right
+  /// away, so that devices which can't run it are rejected before any tile
+  /// is assigned.
+  pub fn new(rule: Rule, selection: &DeviceSelection) -> Result<Self, Error> {
+    let (platform, device) = selection.select()?;
+    // the master wraps the halos around the edges of bounded worlds
+    let source = program::source_with_topology(&rule, Topology::Plane);
+    let (program, queue) = open_device(platform, device, source)?;
+
+    Ok(DeviceTiles {
+      rule,
+      layout: Layout::best(&rule),
+      program,
+      queue,
+      size: None,
+      tiles: HashMap::new(),
+    })
+  }
+
+  /// Uploads a tile, row by row.
+  fn upload(&self, rect: TileRect, cells: &[Cell]) -> OclResult<DeviceTile> {
+    let range = self.rule.range();
+    let (padded_width, padded_height) =
+      (rect.width + 2 * range, rect.height + 2 * range);
+    let mut padded = vec![DEAD; padded_width * padded_height];
+    for (y, row) in cells.chunks(rect.width).enumerate() {
+      let start = range + (y + range) * padded_width;
+      padded[start..start + rect.width].copy_from_slice(row);
+    }
+
+    let index = |x: isize, y: isize| {
+      let r = range as isize;
+      ((x + r) + (y + r) * padded_width as isize) as u32
+    };
+    let border = rect
+      .border(range)
+      .into_iter()
+      .map(|(x, y)| index(x as isize, y as isize))
+      .collect::<Vec<_>>();
+    let halo = rect
+      .halo(range)
+      .into_iter()
+      .map(|(x, y)| index(x, y))
+      .collect::<Vec<_>>();
+
+    let (gather, scatter) = self.layout.transfer_kernel_names();
+    let device = (&self.program, &self.queue);
+    let transfer = |name, indices: &[u32]| {
+      Transfer::new(self.layout, device, name, padded_width, indices)
+    };
+
+    Ok(DeviceTile {
+      rect,
+      range,
+      buffers: Buffers::new(
+        self.layout,
+        &self.program,
+        &self.queue,
+        padded_width,
+        padded_height,
+        &padded,
+      )?,
+      border: transfer(gather, &border)?,
+      halo: transfer(scatter, &halo)?,
+      clear: transfer(scatter, &halo)?,
+    })
+  }
+}
+
+impl DeviceTile {
+  /// Enqueues the computation of the next generation from the `halo` and the
+  /// copy of the border into `border.values`.
+  fn step(&mut self, halo: &[State]) -> OclResult<()> {
+    self.halo.values.write(halo).enq()?;
+    self.halo.enq(&self.buffers)?;
+    self.buffers.next_generation()?;
+    self.border.enq(&self.buffers)
+  }
+
+  fn read_border(&self) -> OclResult<Vec<State>> {
+    let mut border = vec![DEAD; self.border.values.len()];
+    self.border.values.read(&mut border).enq()?;
+    Ok(border)
+  }
+
+  /// Downloads the cells of the tile, row by row.
+  fn read(&self) -> OclResult<Vec<State>> {
+    let (width, range) = (self.rect.width, self.range);
+    let padded_width = width + 2 * range;
+    let padded_height = self.rect.height + 2 * range;
+    let padded = self.buffers.read_cells(padded_width, padded_height)?;
+
+    let mut cells = Vec::with_capacity(width * self.rect.height);
+    for y in 0..self.rect.height {
+      let start = range + (y + range) * padded_width;
+      cells.extend_from_slice(&padded[start..start + width]);
+    }
+    Ok(cells)
+  }
+
+  /// Counts the alive cells of the tile. The halo is cleared, which doesn't
+  /// matter as it's copied in again before the next generation.
+  fn population(&self) -> OclResult<u64> {
+    self.clear.enq(&self.buffers)?;
+    self.buffers.population()
+  }
+
+  /// Returns `true` if every cell of the tile is dead.
+  fn is_dead(&self, states: u8) -> OclResult<bool> {
+    if self.population()? > 0 {
+      return Ok(false);
+    }
+    // dying cells aren't counted
+    Ok(states == 2 || self.read()?.iter().all(|&cell| cell == DEAD))
+  }
+}
+
+impl Tiles for DeviceTiles {
+  fn assign(&mut self, tiles: &[TileCoord]) -> Result<(), Error> {
+    for &tile in tiles {
+      if !self.tiles.contains_key(&tile) {
+        let rect = TileRect::of(tile, self.size);
+        let device_tile =
+          self.upload(rect, &vec![DEAD; rect.width * rect.height])?;
+        self.tiles.insert(tile, device_tile);
+      }
+    }
+    Ok(())
+  }
+
+  fn clear(&mut self, size: Option<(usize, usize)>) {
+    self.size = size;
+    self.tiles.clear();
+  }
+
+  fn count(&self) -> usize {
+    self.tiles.len()
+  }
+
+  fn write(&mut self, tile: TileCoord, cells: &[State]) -> Result<(), Error> {
+    let rect = self
+      .tiles
+      .get(&tile)
+      .ok_or_else(|| not_assigned(tile))?
+      .rect;
+    if cells.len() != rect.width * rect.height {
+      return Err(err_msg(format!(
+        "a tile has {} cells, not {}",
+        rect.width * rect.height,
+        cells.len()
+      )));
+    }
+
+    let device_tile = self.upload(rect, cells)?;
+    self.tiles.insert(tile, device_tile);
+    Ok(())
+  }
+
+  fn step(
+    &mut self,
+    halos: TileCells,
+  ) -> Result<(TileCells, Vec<TileCoord>), Error> {
+    let halos = check_halos(
+      halos,
+      self
+        .tiles
+        .iter()
+        .map(|(&coord, tile)| (coord, tile.halo.values.len())),
+    )?;
+
+    // every tile is enqueued before the first border is waited for
+    for (coord, tile) in &mut self.tiles {
+      tile.step(&halos[coord])?;
+    }
+
+    let mut borders = Vec::with_capacity(self.tiles.len());
+    let mut freed = Vec::new();
+    for (&coord, tile) in &self.tiles {
+      let border = tile.read_border()?;
+      // a tile with cells in its border isn't dead
+      let dead = self.size.is_none()
+        && border.iter().all(|&cell| cell == DEAD)
+        && tile.is_dead(self.rule.states())?;
+      if dead {
+        freed.push(coord);
+      } else {
+        borders.push((coord, border));
+      }
+    }
+    for coord in &freed {
+      self.tiles.remove(coord);
+    }
+    Ok((borders, freed))
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let mut cells = vec![DEAD; width * height];
+    for tile in self.tiles.values() {
+      // only tiles in the region are downloaded
+      let rect = tile.rect;
+      let overlaps = x < rect.x + rect.width as i64
+        && rect.x < x + width as i64
+        && y < rect.y + rect.height as i64
+        && rect.y < y + height as i64;
+      if overlaps {
+        let tile_cells = tile.read()?;
+        copy_region(&mut cells, (x, y, width, height), rect, |x, y| {
+          tile_cells[x + y * rect.width]
+        });
+      }
+    }
+    Ok(cells)
+  }
+
+  fn stats(&self, with_bounds: bool) -> Result<Stats, Error> {
+    let mut population = 0;
+    let mut bounds = None;
+    for tile in self.tiles.values() {
+      population += tile.population()?;
+      if with_bounds {
+        let rect = tile.rect;
+        let cells = tile.read()?;
+        let alive = cells
+          .iter()
+          .enumerate()
+          .filter(|&(_, &cell)| cell != DEAD)
+          .map(|(index, _)| {
+            let (x, y) = (index % rect.width, index / rect.width);
+            (rect.x + x as i64, rect.y + y as i64)
+          });
+        for (x, y) in alive {
+          bounds = union(bounds, Some((x, y, x, y)));
+        }
+      }
+    }
+    Ok((population, bounds))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate rand;
+  use self::rand::prng::XorShiftRng;
+  use self::rand::SeedableRng;
+
+  use super::*;
+  use engine::Engine;
+  use gpu::tests::{opencl_available, random_cells};
+  use master;
+  use slave::{self, host::HostTiles};
+  use unbounded::UnboundedEngine;
+
+  #[test]
+  fn tiles_match_host_tiles() {
+    if !opencl_available() {
+      return;
+    }
+
+    let mut rng = XorShiftRng::from_seed([5; 16]);
+    for &(rule_str, size) in &[
+      ("B3/S23", None),
+      ("B2/S/C3", None),
+      ("R2,C0,M1,S5..8,B6..7", None),
+      ("B36/S23:T100,70", Some((100, 70))),
+    ] {
+      let rule: Rule = rule_str.parse().unwrap();
+      let mut device =
+        DeviceTiles::new(rule, &DeviceSelection::default()).unwrap();
+      let mut host = HostTiles::new(rule);
+      let coords = [(0, 0), (1, 0), (0, 1)];
+      for tiles in &mut [&mut device as &mut dyn Tiles, &mut host] {
+        tiles.clear(size);
+        tiles.assign(&coords).unwrap();
+      }
+      assert_eq!(device.count(), 3);
+
+      let rect = TileRect::of((0, 0), size);
+      let cells = random_cells(&mut rng, rect.width * rect.height);
+      device.write((0, 0), &cells).unwrap();
+      host.write((0, 0), &cells).unwrap();
+      assert!(device.write((0, 0), &cells[1..]).is_err());
+      assert!(device.write((5, 5), &cells).is_err());
+
+      for _ in 0..10 {
+        // halos of freed tiles are ignored
+        let halos: TileCells = coords
+          .iter()
+          .map(|&coord| {
+            let halo = TileRect::of(coord, size).halo(rule.range());
+            (coord, random_cells(&mut rng, halo.len()))
+          })
+          .collect();
+
+        let mut device_step = device.step(halos.clone()).unwrap();
+        let mut host_step = host.step(halos).unwrap();
+        for step in &mut [&mut device_step, &mut host_step] {
+          step.0.sort();
+          step.1.sort();
+        }
+        assert_eq!(device_step, host_step, "{}", rule);
+        assert_eq!(device.stats(true).unwrap(), host.stats(true).unwrap());
+        assert_eq!(
+          device.read_region(-3, 40, 80, 50).unwrap(),
+          host.read_region(-3, 40, 80, 50).unwrap()
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn gpu_slaves_match_unbounded_engine() {
+    if !opencl_available() {
+      return;
+    }
+
+    let rule = "B36/S23".parse().unwrap();
+    let backend = slave::Backend::Gpu(DeviceSelection::default());
+    let mut cluster = master::spawn_local(rule, 2, &backend).unwrap();
+    let mut unbounded = UnboundedEngine::new(rule).unwrap();
+
+    let mut rng = XorShiftRng::from_seed([9; 16]);
+    let soup = random_cells(&mut rng, 150 * 70);
+    cluster.load(150, 70, &soup).unwrap();
+    unbounded.load(150, 70, &soup).unwrap();
+
+    for _ in 0..4 {
+      cluster.step(5).unwrap();
+      unbounded.step(5).unwrap();
+      assert_eq!(cluster.hash().unwrap(), unbounded.hash().unwrap());
+      assert_eq!(
+        cluster.population().unwrap(),
+        unbounded.population().unwrap()
+      );
+    }
+  }
+}
@@ -0,0 +1,89 @@
+use failure::Error;
+
+use engine::Engine;
+use rule::{Rule, State};
+
+pub mod universe;
+use self::universe::Universe;
+
+/// The HashLife backend, see [`Universe`].
+///
+/// [`Universe`]: universe/struct.Universe.html
+pub struct HashlifeEngine {
+  rule: Rule,
+  memory_limit: usize,
+  universe: Universe,
+}
+
+impl HashlifeEngine {
+  pub fn new(rule: Rule, memory_limit: usize) -> Result<Self, Error> {
+    Ok(HashlifeEngine {
+      rule,
+      memory_limit,
+      universe: Universe::new(rule, memory_limit)?,
+    })
+  }
+}
+
+impl Engine for HashlifeEngine {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    self.universe = Universe::new(self.rule, self.memory_limit)?;
+    for y in 0..height {
+      for x in 0..width {
+        self.universe.set(x as i64, y as i64, cells[x + y * width]);
+      }
+    }
+    Ok(())
+  }
+
+  /// Advances the universe by the powers of two which sum up to
+  /// `generations`.
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    for log2 in 0..64 {
+      if (generations >> log2) & 1 != 0 {
+        self.universe.step(log2)?;
+      }
+    }
+
+    debug!(
+      target: "hashlife",
+      "generation #{}: {} nodes",
+      self.universe.generation(),
+      self.universe.node_count()
+    );
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.universe.generation()
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let mut cells = Vec::with_capacity(width * height);
+    for y in y..y + height as i64 {
+      for x in x..x + width as i64 {
+        cells.push(self.universe.get(x, y));
+      }
+    }
+    Ok(cells)
+  }
+
+  fn population(&self) -> Result<u64, Error> {
+    Ok(self.universe.population())
+  }
+
+  fn hash(&self) -> Result<u64, Error> {
+    Ok(self.universe.hash())
+  }
+}
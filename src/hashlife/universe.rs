@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+
+use failure::{err_msg, Error};
+
+use rule::neighbors::*;
+use rule::{Rule, State, ALIVE, DEAD};
+use utils::hash::hash_world;
+
+type NodeId = u32;
+
+const DEAD_LEAF: NodeId = 0;
+const ALIVE_LEAF: NodeId = 1;
+
+/// Level of the root of an empty universe, the root never gets smaller.
+const MIN_ROOT_LEVEL: u8 = 3;
+/// Level of the largest root, so coordinates of its cells fit into `i64`.
+const MAX_ROOT_LEVEL: u8 = 62;
+/// Largest step is `2^MAX_STEP_LOG2` generations, which leaves room for
+/// padding the root.
+pub const MAX_STEP_LOG2: u8 = MAX_ROOT_LEVEL - 3;
+
+/// Estimated number of bytes used by a node, including its entries in the
+/// hash tables, which turns a memory limit into a limit of nodes.
+const BYTES_PER_NODE: usize = 96;
+
+/// Offsets of the neighbors of a cell in the order of their bits in a
+/// configuration (see [`neighbors`]).
+///
+/// [`neighbors`]: ../../rule/neighbors/index.html
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const OFFSETS: [(u8, isize, isize); 8] = [
+  (N, 0, -1), (NE, 1, -1), (E, 1, 0), (SE, 1, 1),
+  (S, 0, 1), (SW, -1, 1), (W, -1, 0), (NW, -1, -1),
+];
+
+/// A square of `2^level` x `2^level` cells. Nodes of level 0 are cells, the
+/// others are made of four children of the level below: the top left, the
+/// top right, the bottom left and the bottom right quadrant.
+#[derive(Clone, Copy)]
+struct Node {
+  level: u8,
+  population: u64,
+  children: [NodeId; 4],
+}
+
+/// An unbounded two-state world computed with HashLife: the world is a
+/// quadtree whose identical nodes are shared, and the future of every node
+/// is memoized, so regular patterns can be advanced by huge steps.
+pub struct Universe {
+  rule: Rule,
+  nodes: Vec<Node>,
+  /// Nodes by their children, which makes every node canonical.
+  canonical: HashMap<[NodeId; 4], NodeId>,
+  /// Centers of nodes advanced by `2^j` generations, by the node and `j`.
+  results: HashMap<(NodeId, u8), NodeId>,
+  /// Empty nodes by their level.
+  empty: Vec<NodeId>,
+  max_nodes: usize,
+  root: NodeId,
+  /// Coordinates of the top left cell of the root.
+  x: i64,
+  y: i64,
+  generation: u64,
+}
+
+impl Universe {
+  /// Creates an empty universe which uses about `memory_limit` bytes at most.
+  /// Only two-state rules of range 1 without births on 0 neighbors can be
+  /// computed with HashLife, and the universe is always unbounded.
+  pub fn new(rule: Rule, memory_limit: usize) -> Result<Self, Error> {
+    if rule.states() != 2 || rule.is_larger_than_life() {
+      return Err(err_msg(format!(
+        "HashLife supports only two-state range 1 rules, but {} isn't",
+        rule
+      )));
+    }
+    if rule.grid().is_some() {
+      return Err(err_msg(format!(
+        "HashLife universes are unbounded, but {} has a bounded grid",
+        rule
+      )));
+    }
+    if rule.next_state(DEAD, 0) == ALIVE {
+      return Err(err_msg(format!(
+        "HashLife doesn't support births on 0 neighbors of {}",
+        rule
+      )));
+    }
+
+    let leaf = |population| Node {
+      level: 0,
+      population,
+      children: [DEAD_LEAF; 4],
+    };
+
+    let mut universe = Universe {
+      rule,
+      nodes: vec![leaf(0), leaf(1)],
+      canonical: HashMap::new(),
+      results: HashMap::new(),
+      empty: vec![DEAD_LEAF],
+      max_nodes: memory_limit / BYTES_PER_NODE,
+      root: DEAD_LEAF,
+      x: 0,
+      y: 0,
+      generation: 0,
+    };
+    universe.root = universe.empty(MIN_ROOT_LEVEL);
+    Ok(universe)
+  }
+
+  /// Returns the number of computed generations.
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  pub fn population(&self) -> u64 {
+    self.nodes[self.root as usize].population
+  }
+
+  /// Returns the number of nodes, including the ones which will be
+  /// collected as garbage.
+  pub fn node_count(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn get(&self, x: i64, y: i64) -> State {
+    let size = self.size();
+    let (dx, dy) = (x.wrapping_sub(self.x), y.wrapping_sub(self.y));
+    if dx < 0 || dy < 0 || dx >= size || dy >= size {
+      return DEAD;
+    }
+
+    let (mut node, mut dx, mut dy) = (self.root, dx as u64, dy as u64);
+    for level in (1..=self.level()).rev() {
+      let half = 1 << (level - 1);
+      node = self.node(node).children[quadrant(dx, dy, half)];
+      dx %= half;
+      dy %= half;
+    }
+
+    if node == ALIVE_LEAF {
+      ALIVE
+    } else {
+      DEAD
+    }
+  }
+
+  pub fn set(&mut self, x: i64, y: i64, cell: State) {
+    loop {
+      let size = self.size();
+      let (dx, dy) = (x - self.x, y - self.y);
+      if dx >= 0 && dy >= 0 && dx < size && dy < size {
+        let leaf = if cell == ALIVE { ALIVE_LEAF } else { DEAD_LEAF };
+        self.root = self.set_in(self.root, dx as u64, dy as u64, leaf);
+        return;
+      }
+
+      assert!(self.level() < MAX_ROOT_LEVEL, "cell is too far away");
+      self.expand();
+    }
+  }
+
+  /// Returns the smallest rectangle `(x0, y0, x1, y1)` (inclusive) which
+  /// contains all alive cells, or `None` if the universe is empty.
+  pub fn bounds(&self) -> Option<(i64, i64, i64, i64)> {
+    self.bounds_in(self.root, self.x, self.y)
+  }
+
+  /// Computes a stable hash of the cells within [`bounds`], the same way as
+  /// [`TiledWorld::hash`] does, so both can be cross-checked.
+  ///
+  /// [`bounds`]: #method.bounds
+  /// [`TiledWorld::hash`]: ../../unbounded/world/struct.TiledWorld.html#method.hash
+  pub fn hash(&self) -> u64 {
+    let (x0, y0, x1, y1) = self.bounds().unwrap_or((0, 0, -1, -1));
+    let width = (x1 - x0 + 1) as usize;
+    let height = (y1 - y0 + 1) as usize;
+    hash_world(width, height, |x, y| self.get(x0 + x as i64, y0 + y as i64))
+  }
+
+  /// Advances the universe by `2^log2` generations. Garbage is collected
+  /// before the step if there are too many nodes, an error is returned if
+  /// there are too many nodes even after that. A single step may exceed the
+  /// limit, which is only checked between steps.
+  pub fn step(&mut self, log2: u8) -> Result<(), Error> {
+    if log2 > MAX_STEP_LOG2 {
+      return Err(err_msg(format!(
+        "steps can't be larger than 2^{}",
+        MAX_STEP_LOG2
+      )));
+    }
+
+    if self.nodes.len() > self.max_nodes {
+      self.collect_garbage();
+      if self.nodes.len() > self.max_nodes {
+        return Err(err_msg("the pattern doesn't fit into the memory limit"));
+      }
+    }
+
+    // the pattern must be within the center of the root, which is padded
+    // once more, so it can't grow out of the center during the step
+    while self.level() < log2 + 2 || !self.is_centered() {
+      if self.level() >= MAX_ROOT_LEVEL - 1 {
+        return Err(err_msg("the pattern has grown too large"));
+      }
+      self.expand();
+    }
+    self.expand();
+
+    let offset = 1 << (self.level() - 2);
+    self.root = self.advance(self.root, log2);
+    self.x += offset;
+    self.y += offset;
+    self.generation += 1 << log2;
+
+    while self.level() > MIN_ROOT_LEVEL && self.is_centered() {
+      let offset = 1 << (self.level() - 2);
+      self.root = self.center(self.root);
+      self.x += offset;
+      self.y += offset;
+    }
+
+    Ok(())
+  }
+
+  /// Removes all nodes which aren't a part of the root and forgets computed
+  /// results.
+  pub fn collect_garbage(&mut self) {
+    let before = self.nodes.len();
+
+    let old_nodes = ::std::mem::replace(&mut self.nodes, Vec::new());
+    self.nodes.extend_from_slice(&old_nodes[..2]);
+    self.canonical.clear();
+    self.results.clear();
+    self.empty = vec![DEAD_LEAF];
+
+    let mut copies = HashMap::new();
+    self.root = self.copy(&old_nodes, self.root, &mut copies);
+
+    debug!(
+      target: "hashlife",
+      "collected {} of {} nodes",
+      before - self.nodes.len(),
+      before
+    );
+  }
+
+  fn copy(
+    &mut self,
+    old_nodes: &[Node],
+    node: NodeId,
+    copies: &mut HashMap<NodeId, NodeId>,
+  ) -> NodeId {
+    if node == DEAD_LEAF || node == ALIVE_LEAF {
+      return node;
+    }
+    if let Some(&copy) = copies.get(&node) {
+      return copy;
+    }
+
+    let mut children = old_nodes[node as usize].children;
+    for child in &mut children {
+      *child = self.copy(old_nodes, *child, copies);
+    }
+    let copy = self.join(children);
+    copies.insert(node, copy);
+    copy
+  }
+
+  fn node(&self, node: NodeId) -> Node {
+    self.nodes[node as usize]
+  }
+
+  fn level(&self) -> u8 {
+    self.node(self.root).level
+  }
+
+  fn size(&self) -> i64 {
+    1 << self.level()
+  }
+
+  /// Returns the canonical node with the given children.
+  fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+    if let Some(&node) = self.canonical.get(&children) {
+      return node;
+    }
+
+    let population = children.iter().fold(0, |population: u64, &child| {
+      population.saturating_add(self.node(child).population)
+    });
+    let node = self.nodes.len() as NodeId;
+    self.nodes.push(Node {
+      level: self.node(children[0]).level + 1,
+      population,
+      children,
+    });
+    self.canonical.insert(children, node);
+    node
+  }
+
+  fn empty(&mut self, level: u8) -> NodeId {
+    while self.empty.len() <= level as usize {
+      let empty = *self.empty.last().unwrap();
+      let node = self.join([empty; 4]);
+      self.empty.push(node);
+    }
+    self.empty[level as usize]
+  }
+
+  fn set_in(&mut self, node: NodeId, x: u64, y: u64, leaf: NodeId) -> NodeId {
+    let level = self.node(node).level;
+    if level == 0 {
+      return leaf;
+    }
+
+    let half = 1 << (level - 1);
+    let mut children = self.node(node).children;
+    let index = quadrant(x, y, half);
+    children[index] = self.set_in(children[index], x % half, y % half, leaf);
+    self.join(children)
+  }
+
+  fn bounds_in(
+    &self,
+    node: NodeId,
+    x: i64,
+    y: i64,
+  ) -> Option<(i64, i64, i64, i64)> {
+    let node = self.node(node);
+    if node.population == 0 {
+      return None;
+    }
+    if node.level == 0 {
+      return Some((x, y, x, y));
+    }
+
+    let half = 1 << (node.level - 1);
+    let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+    node
+      .children
+      .iter()
+      .zip(&offsets)
+      .filter_map(|(&child, &(dx, dy))| self.bounds_in(child, x + dx, y + dy))
+      .fold(None, |bounds, (x0, y0, x1, y1)| {
+        Some(match bounds {
+          None => (x0, y0, x1, y1),
+          Some((bx0, by0, bx1, by1)) => {
+            (bx0.min(x0), by0.min(y0), bx1.max(x1), by1.max(y1))
+          }
+        })
+      })
+  }
+
+  /// Doubles the size of the root, keeping it in the center.
+  fn expand(&mut self) {
+    let level = self.level();
+    let empty = self.empty(level - 1);
+    let [nw, ne, sw, se] = self.node(self.root).children;
+
+    self.root = {
+      let nw = self.join([empty, empty, empty, nw]);
+      let ne = self.join([empty, empty, ne, empty]);
+      let sw = self.join([empty, sw, empty, empty]);
+      let se = self.join([se, empty, empty, empty]);
+      self.join([nw, ne, sw, se])
+    };
+
+    let offset = 1 << (level - 1);
+    self.x -= offset;
+    self.y -= offset;
+  }
+
+  /// Returns `true` if all alive cells are in the center of the root.
+  fn is_centered(&mut self) -> bool {
+    let center = self.center(self.root);
+    self.node(center).population == self.population()
+  }
+
+  /// Returns the node of half the size in the center of a node.
+  fn center(&mut self, node: NodeId) -> NodeId {
+    let [nw, ne, sw, se] = self.node(node).children;
+    self.join([
+      self.node(nw).children[3],
+      self.node(ne).children[2],
+      self.node(sw).children[1],
+      self.node(se).children[0],
+    ])
+  }
+
+  /// Returns the center of a node of level `k` advanced by `2^log2`
+  /// generations, where `log2 <= k - 2`.
+  fn advance(&mut self, node: NodeId, log2: u8) -> NodeId {
+    let level = self.node(node).level;
+    debug_assert!(level >= 2 && log2 <= level - 2);
+
+    if self.node(node).population == 0 {
+      return self.empty(level - 1);
+    }
+    if let Some(&result) = self.results.get(&(node, log2)) {
+      return result;
+    }
+
+    let result = if level == 2 {
+      self.advance_leaves(node)
+    } else {
+      // nine overlapping nodes of half the size, which are advanced either
+      // by half of the step (if the step is as large as it can be) or not at
+      // all, then combined into four nodes which are advanced by the rest
+      let parts = self.nine_parts(node);
+      let (parts, log2) = if log2 == level - 2 {
+        let mut advanced = [DEAD_LEAF; 9];
+        for (advanced, &part) in advanced.iter_mut().zip(&parts) {
+          *advanced = self.advance(part, level - 3);
+        }
+        (advanced, level - 3)
+      } else {
+        let mut centers = [DEAD_LEAF; 9];
+        for (center, &part) in centers.iter_mut().zip(&parts) {
+          *center = self.center(part);
+        }
+        (centers, log2)
+      };
+
+      let mut quadrants = [DEAD_LEAF; 4];
+      for (index, quadrant) in quadrants.iter_mut().enumerate() {
+        let (x, y) = (index % 2, index / 2);
+        let part = |dx, dy| parts[x + dx + (y + dy) * 3];
+        let joined =
+          self.join([part(0, 0), part(1, 0), part(0, 1), part(1, 1)]);
+        *quadrant = self.advance(joined, log2);
+      }
+      self.join(quadrants)
+    };
+
+    self.results.insert((node, log2), result);
+    result
+  }
+
+  /// Splits a node into 3x3 overlapping nodes of half the size, row by row.
+  fn nine_parts(&mut self, node: NodeId) -> [NodeId; 9] {
+    let children = self.node(node).children;
+    let [nw, ne, sw, se] = children;
+    let [nw, ne, sw, se] = [
+      self.node(nw).children,
+      self.node(ne).children,
+      self.node(sw).children,
+      self.node(se).children,
+    ];
+
+    let top = self.join([nw[1], ne[0], nw[3], ne[2]]);
+    let left = self.join([nw[2], nw[3], sw[0], sw[1]]);
+    let center = self.join([nw[3], ne[2], sw[1], se[0]]);
+    let right = self.join([ne[2], ne[3], se[0], se[1]]);
+    let bottom = self.join([sw[1], se[0], sw[3], se[2]]);
+
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    let parts = [
+      children[0], top,    children[1],
+      left,        center, right,
+      children[2], bottom, children[3],
+    ];
+    parts
+  }
+
+  /// Computes the center 2x2 cells of a 4x4 node after one generation.
+  fn advance_leaves(&mut self, node: NodeId) -> NodeId {
+    let mut cells = [[DEAD; 4]; 4];
+    let children = self.node(node).children;
+    for (index, &child) in children.iter().enumerate() {
+      let grandchildren = self.node(child).children;
+      for (sub_index, &leaf) in grandchildren.iter().enumerate() {
+        let x = (index % 2) * 2 + sub_index % 2;
+        let y = (index / 2) * 2 + sub_index / 2;
+        cells[y][x] = if leaf == ALIVE_LEAF { ALIVE } else { DEAD };
+      }
+    }
+
+    let mut leaves = [DEAD_LEAF; 4];
+    for (index, leaf) in leaves.iter_mut().enumerate() {
+      let (x, y) = (1 + index % 2, 1 + index / 2);
+      let neighbors = OFFSETS
+        .iter()
+        .filter(|&&(_, dx, dy)| {
+          cells[(y as isize + dy) as usize][(x as isize + dx) as usize] == ALIVE
+        })
+        .fold(0, |neighbors, &(bit, _, _)| neighbors | bit);
+
+      if self.rule.next_state(cells[y][x], neighbors) == ALIVE {
+        *leaf = ALIVE_LEAF;
+      }
+    }
+    self.join(leaves)
+  }
+}
+
+/// Returns the index of the child which contains the cell at `(x, y)` of a
+/// node whose children have the size `half`.
+fn quadrant(x: u64, y: u64, half: u64) -> usize {
+  (x >= half) as usize + 2 * (y >= half) as usize
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use unbounded::world::TiledWorld;
+
+  const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+  fn soup(size: i64) -> Vec<(i64, i64)> {
+    let mut state = 0x2545_f491_u32;
+    let mut cells = Vec::new();
+    for y in 0..size {
+      for x in 0..size {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        if state % 3 == 0 {
+          cells.push((x - size / 2, y - size / 2));
+        }
+      }
+    }
+    cells
+  }
+
+  #[test]
+  fn cells() {
+    let mut universe = Universe::new(Rule::default(), MEMORY_LIMIT).unwrap();
+    universe.set(-1000, 5, ALIVE);
+    universe.set(3, -7, ALIVE);
+    universe.set(3, -7, DEAD);
+    universe.set(4, -7, ALIVE);
+
+    assert_eq!(universe.get(-1000, 5), ALIVE);
+    assert_eq!(universe.get(3, -7), DEAD);
+    assert_eq!(universe.get(i64::max_value(), 0), DEAD);
+    assert_eq!(universe.population(), 2);
+    assert_eq!(universe.bounds(), Some((-1000, -7, 4, 5)));
+  }
+
+  #[test]
+  fn matches_brute_force() {
+    for rule in &["B3/S23", "B36/S23", "B2-a/S12", "B2/S34H", "B2/S013V"] {
+      let rule: Rule = rule.parse().unwrap();
+      let mut universe = Universe::new(rule, MEMORY_LIMIT).unwrap();
+      let mut world = TiledWorld::new();
+      for (x, y) in soup(30) {
+        universe.set(x, y, ALIVE);
+        world.set(x, y, ALIVE);
+      }
+
+      // single generations, then a jump of 2^5 generations
+      for _ in 0..16 {
+        world.next_generation(&rule);
+        universe.step(0).unwrap();
+        assert_eq!(universe.hash(), world.hash(), "{}", rule);
+      }
+      for _ in 0..32 {
+        world.next_generation(&rule);
+      }
+      universe.step(5).unwrap();
+
+      assert_eq!(universe.generation(), 48);
+      assert_eq!(universe.population(), world.population() as u64);
+      assert_eq!(universe.bounds(), world.bounds(), "{}", rule);
+      assert_eq!(universe.hash(), world.hash(), "{}", rule);
+    }
+  }
+
+  #[test]
+  fn glider_after_a_million_generations() {
+    let mut universe = Universe::new(Rule::default(), MEMORY_LIMIT).unwrap();
+    for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      universe.set(x, y, ALIVE);
+    }
+    let hash = universe.hash();
+
+    universe.step(20).unwrap();
+
+    // the glider moves by one cell diagonally every 4 generations
+    let distance = 1 << 18;
+    assert_eq!(universe.population(), 5);
+    assert_eq!(universe.hash(), hash);
+    assert_eq!(
+      universe.bounds(),
+      Some((distance, distance, distance + 2, distance + 2))
+    );
+  }
+
+  #[test]
+  fn garbage_collection() {
+    let rule = Rule::default();
+    let mut universe = Universe::new(rule, MEMORY_LIMIT).unwrap();
+    for (x, y) in soup(40) {
+      universe.set(x, y, ALIVE);
+    }
+    universe.step(4).unwrap();
+    let (hash, nodes) = (universe.hash(), universe.node_count());
+
+    universe.collect_garbage();
+    assert!(universe.node_count() < nodes);
+    assert_eq!(universe.hash(), hash);
+
+    // the results are computed again after the collection
+    let mut copy = Universe::new(rule, MEMORY_LIMIT).unwrap();
+    for (x, y) in soup(40) {
+      copy.set(x, y, ALIVE);
+    }
+    copy.step(5).unwrap();
+    universe.step(4).unwrap();
+    assert_eq!(universe.hash(), copy.hash());
+
+    // too little memory even for the pattern itself
+    let mut universe = Universe::new(rule, 1024).unwrap();
+    for (x, y) in soup(40) {
+      universe.set(x, y, ALIVE);
+    }
+    assert!(universe.step(0).is_err());
+  }
+
+  #[test]
+  fn unsupported_rules() {
+    for rule in &["B2/S/C3", "R2,C0,M1,S2..3,B3..3", "B3/S23:T10,10", "B0/S8"] {
+      assert!(Universe::new(rule.parse().unwrap(), MEMORY_LIMIT).is_err());
+    }
+  }
+}
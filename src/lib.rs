@@ -0,0 +1,49 @@
+//! Game of Life simulator for clusters of computers.
+//!
+//! Every backend implements the [`Engine`] trait, so a simulation can be run
+//! programmatically:
+//!
+//! ```
+//! extern crate game_of_life_cluster;
+//! use game_of_life_cluster::{Backend, Pattern, Rule};
+//!
+//! let rule: Rule = "B3/S23".parse().unwrap();
+//! let glider: Pattern = "x = 3, y = 3\nbob$2bo$3o!".parse().unwrap();
+//!
+//! let mut engine = Backend::Hashlife.create(rule).unwrap();
+//! glider.load_into(&mut *engine).unwrap();
+//! engine.step(1024).unwrap();
+//! assert_eq!(engine.population().unwrap(), 5);
+//! ```
+//!
+//! [`Engine`]: engine/trait.Engine.html
+
+#![feature(duration_as_u128)]
+#![cfg_attr(test, feature(test))]
+
+extern crate failure;
+
+#[macro_use]
+extern crate log;
+
+pub mod engine;
+pub mod ffi;
+pub mod gpu;
+pub mod hashlife;
+pub mod master;
+pub mod neighborhood;
+pub mod pattern;
+mod protocol;
+pub mod rule;
+pub mod slave;
+pub mod threaded;
+mod tiling;
+pub mod topology;
+pub mod tui;
+pub mod unbounded;
+pub mod utils;
+pub mod verify;
+
+pub use engine::{Backend, Engine};
+pub use pattern::Pattern;
+pub use rule::{Rule, State, ALIVE, DEAD};
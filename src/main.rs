@@ -9,12 +9,19 @@ extern crate clap;
 extern crate log;
 mod logger;
 
+extern crate game_of_life_cluster;
+use game_of_life_cluster::engine::{random_soup, Engine};
+use game_of_life_cluster::hashlife::HashlifeEngine;
+use game_of_life_cluster::threaded::ThreadedEngine;
+use game_of_life_cluster::unbounded::{
+  UnboundedEngine, SOUP_HEIGHT, SOUP_WIDTH,
+};
+use game_of_life_cluster::utils::time::measure_time;
+use game_of_life_cluster::{gpu, master, slave, tui, verify, Rule};
+
+use std::time::Instant;
+
 mod cli;
-mod gpu;
-mod master;
-mod slave;
-mod threaded;
-mod utils;
 
 fn main() {
   let options = cli::get_options().unwrap_or_else(|e| e.exit());
@@ -24,12 +31,120 @@ fn main() {
 }
 
 fn run(options: cli::Options) -> Result<(), Error> {
+  let rule = options.rule;
+  // bounded worlds have the size of the rule's grid
+  let soup_size = |default| {
+    rule
+      .grid()
+      .map_or(default, |grid| (grid.width, grid.height))
+  };
+
   match options.command {
-    cli::Command::Master { port } => master::listen(port)?,
-    cli::Command::Slave { hostname, port } => slave::connect(hostname, port)?,
-    cli::Command::Gpu => gpu::run()?,
-    cli::Command::Threaded => threaded::run(),
+    cli::Command::Master {
+      port,
+      slaves,
+      render,
+    } => {
+      let mut engine = master::listen(port, rule, slaves)?;
+      let size = soup_size((SOUP_WIDTH, SOUP_HEIGHT));
+      simulate(&mut engine, rule, size, 1, render)?
+    }
+    cli::Command::Slave {
+      hostname,
+      port,
+      backend,
+    } => slave::connect(hostname, port, backend)?,
+    cli::Command::Gpu {
+      step,
+      render,
+      selection,
+      all_devices,
+      exchange,
+    } => {
+      let mut engine = gpu::create(rule, &selection, all_devices, exchange)?;
+      let size = soup_size((SOUP_WIDTH, SOUP_HEIGHT));
+      simulate(&mut *engine, rule, size, step, render)?
+    }
+    cli::Command::ListDevices => print!("{}", gpu::device::list_devices()?),
+    cli::Command::Threaded {
+      threads,
+      scheduling,
+      render,
+    } => {
+      let mut engine = ThreadedEngine::new(rule, threads, scheduling);
+      let size = soup_size((10_000, 10_000));
+      simulate(&mut engine, rule, size, 1, render)?
+    }
+    cli::Command::Unbounded { render } => {
+      let mut engine = UnboundedEngine::new(rule)?;
+      simulate(&mut engine, rule, (SOUP_WIDTH, SOUP_HEIGHT), 1, render)?
+    }
+    cli::Command::Hashlife {
+      step_log2,
+      memory_limit,
+      render,
+    } => {
+      let mut engine = HashlifeEngine::new(rule, memory_limit)?;
+      let (size, step) = ((SOUP_WIDTH, SOUP_HEIGHT), 1 << step_log2);
+      simulate(&mut engine, rule, size, step, render)?
+    }
+    cli::Command::Verify {
+      backends,
+      seed,
+      generations,
+      width,
+      height,
+    } => {
+      let report =
+        verify::run(backends, seed, generations, width, height, rule)?;
+      print!("{}", report);
+      report.check()?
+    }
   }
 
   Ok(())
 }
+
+/// Runs `engine` with `rule` from a random soup of `width` x `height` cells
+/// forever, `generations` at a time, printing how long every step took. If
+/// `render` is set and the output is a terminal, the world is shown in the
+/// interactive [`tui`] instead.
+fn simulate(
+  engine: &mut dyn Engine,
+  rule: Rule,
+  (width, height): (usize, usize),
+  generations: u64,
+  render: bool,
+) -> Result<(), Error> {
+  let soup = measure_time("create soup", || random_soup(width, height));
+  engine.load(width, height, &soup)?;
+
+  if render {
+    if tui::is_available() {
+      return tui::run(engine, rule, (width, height), generations);
+    }
+    warn!(target: "engine", "the output isn't a terminal, nothing is drawn");
+  }
+
+  loop {
+    let step_start_time = Instant::now();
+    engine.step(generations)?;
+    let elapsed = step_start_time.elapsed();
+
+    println!(
+      "generation #{} - {} µs - population {}",
+      engine.generation(),
+      elapsed.as_micros(),
+      engine.population()?
+    );
+
+    if log_enabled!(log::Level::Debug) {
+      debug!(
+        target: "engine",
+        "generation #{} hash = {:016x}",
+        engine.generation(),
+        engine.hash()?
+      );
+    }
+  }
+}
@@ -0,0 +1,349 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use failure::{err_msg, Error};
+
+use super::tiles::TileAssignment;
+use engine::Engine;
+use protocol::{Message, Stats};
+use rule::{Rule, State, ALIVE, DEAD};
+use tiling::{union, TileRect};
+use unbounded::world::{locate, tiles_covering, TileCoord};
+use utils::hash::hash_world;
+use utils::result::DescribeErr;
+
+/// The cluster backend: a world split into tiles which are computed by slaves
+/// connected through `S` streams (TCP sockets, or the Unix sockets of
+/// [`spawn_local`]).
+///
+/// The cells stay on the slaves, the master only keeps the borders of the
+/// tiles. Every generation it sends the slaves the halos of their tiles and
+/// receives the new borders, see the [`tiling`] module. Halos and borders are
+/// as wide as the range of the rule.
+///
+/// Without a bounded grid the world is unbounded: like in [`TiledWorld`],
+/// tiles are created when alive cells of the borders of their neighbors reach
+/// them and the slaves free tiles which become empty. With a bounded grid the
+/// world has the size of the loaded pattern, like the threaded engine, and
+/// every tile of it is kept.
+///
+/// [`spawn_local`]: fn.spawn_local.html
+/// [`tiling`]: ../tiling/index.html
+/// [`TiledWorld`]: ../unbounded/world/struct.TiledWorld.html
+pub struct ClusterEngine<S> {
+  rule: Rule,
+  /// Reading the world needs to talk to the slaves too.
+  slaves: RefCell<Vec<S>>,
+  tiles: TileAssignment<usize>,
+  /// The size of the world if the rule has a bounded grid.
+  size: Option<(usize, usize)>,
+  /// The border of every tile after the last generation.
+  borders: HashMap<TileCoord, Vec<State>>,
+  generation: u64,
+}
+
+impl<S: Read + Write> ClusterEngine<S> {
+  /// Sends the rule to the slaves, which must have just connected.
+  pub fn new(rule: Rule, slaves: Vec<S>) -> Result<Self, Error> {
+    if slaves.is_empty() {
+      return Err(err_msg("a cluster needs at least one slave"));
+    }
+
+    let engine = ClusterEngine {
+      rule,
+      slaves: RefCell::new(slaves),
+      tiles: TileAssignment::new(),
+      size: None,
+      borders: HashMap::new(),
+      generation: 0,
+    };
+    engine.broadcast(&Message::Setup { rule })?;
+    Ok(engine)
+  }
+
+  /// Returns the number of tiles of the world.
+  pub fn tile_count(&self) -> usize {
+    self.tiles.assigned().len()
+  }
+
+  fn slave_count(&self) -> usize {
+    self.slaves.borrow().len()
+  }
+
+  fn send(&self, slave: usize, message: &Message) -> Result<(), Error> {
+    message
+      .write_to(&mut self.slaves.borrow_mut()[slave])
+      .describe_err(format!("can't send a message to slave #{}", slave))?;
+    Ok(())
+  }
+
+  fn receive(&self, slave: usize) -> Result<Message, Error> {
+    let message = Message::read_from(&mut self.slaves.borrow_mut()[slave])
+      .describe_err(format!("can't receive a message from slave #{}", slave))?;
+    trace!(target: "master", "slave #{} has sent {:?}", slave, message);
+    Ok(message)
+  }
+
+  fn broadcast(&self, message: &Message) -> Result<(), Error> {
+    for slave in 0..self.slave_count() {
+      self.send(slave, message)?;
+    }
+    Ok(())
+  }
+
+  /// Sends `request` to every slave and returns their replies.
+  fn request(&self, request: &Message) -> Result<Vec<Message>, Error> {
+    // the slaves handle the request at the same time
+    self.broadcast(request)?;
+    (0..self.slave_count())
+      .map(|slave| self.receive(slave))
+      .collect()
+  }
+
+  /// Returns the halo of a tile, taken from the borders of its neighbors.
+  fn halo(&self, tile: TileCoord) -> Vec<State> {
+    let rect = TileRect::of(tile, self.size);
+    rect
+      .halo(self.rule.range())
+      .into_iter()
+      .map(|(x, y)| self.border_cell(rect.x + x as i64, rect.y + y as i64))
+      .collect()
+  }
+
+  /// Returns a cell of the border of a tile, cells of missing tiles are dead.
+  /// Cells beyond the edges of a bounded world are wrapped around.
+  fn border_cell(&self, x: i64, y: i64) -> State {
+    let (x, y) = match self.size {
+      Some((width, height)) => {
+        let topology = self.rule.topology();
+        match topology.wrap(x as isize, y as isize, width, height) {
+          Some((x, y)) => (x as i64, y as i64),
+          None => return DEAD,
+        }
+      }
+      None => (x, y),
+    };
+
+    let (tile, (x, y)) = locate(x, y);
+    self.borders.get(&tile).map_or(DEAD, |border| {
+      let index = TileRect::of(tile, self.size)
+        .border_index(self.rule.range(), x, y)
+        .expect("halos are in the borders of the neighbors");
+      border[index]
+    })
+  }
+
+  /// Creates the missing tiles of an unbounded world which alive cells of the
+  /// borders of their neighbors can reach, and assigns every pending tile.
+  fn create_reached_tiles(&mut self) -> Result<(), Error> {
+    let mut reached = Vec::new();
+    for (&(tx, ty), border) in &self.borders {
+      if self.size.is_some() || !border.contains(&ALIVE) {
+        continue;
+      }
+
+      for neighbor in
+        (ty - 1..=ty + 1).flat_map(|y| (tx - 1..=tx + 1).map(move |x| (x, y)))
+      {
+        if self.tiles.owner(neighbor).is_none()
+          && !reached.contains(&neighbor)
+          && self.halo(neighbor).contains(&ALIVE)
+        {
+          reached.push(neighbor);
+        }
+      }
+    }
+
+    // sorted to assign tiles in the same way every time
+    reached.sort();
+    for tile in reached {
+      self.tiles.create_tile(tile);
+    }
+    for (slave, tiles) in self.tiles.assign_pending() {
+      debug!(target: "master", "{} tiles for slave #{}", tiles.len(), slave);
+      self.send(slave, &Message::AssignTiles { tiles })?;
+    }
+    Ok(())
+  }
+
+  fn next_generation(&mut self) -> Result<(), Error> {
+    self.create_reached_tiles()?;
+
+    let mut halos = vec![Vec::new(); self.slave_count()];
+    for (tile, slave) in self.tiles.assigned() {
+      halos[slave].push((tile, self.halo(tile)));
+    }
+
+    // every slave has its halos before the first one is waited for
+    for (slave, halos) in halos.into_iter().enumerate() {
+      self.send(slave, &Message::Step { halos })?;
+    }
+    for slave in 0..self.slave_count() {
+      match self.receive(slave)? {
+        Message::Borders { borders, freed } => {
+          self.borders.extend(borders);
+          for tile in freed {
+            self.borders.remove(&tile);
+            self.tiles.remove_tile(tile);
+          }
+        }
+        _ => return Err(unexpected_reply(slave)),
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the population of the world and its bounds, if they're asked
+  /// for.
+  fn stats(&self, with_bounds: bool) -> Result<Stats, Error> {
+    let mut population = 0;
+    let mut bounds = None;
+    let request = Message::GetStats {
+      bounds: with_bounds,
+    };
+    for (slave, reply) in self.request(&request)?.into_iter().enumerate() {
+      match reply {
+        Message::Stats {
+          population: slave_population,
+          bounds: slave_bounds,
+        } => {
+          population += slave_population;
+          bounds = union(bounds, slave_bounds);
+        }
+        _ => return Err(unexpected_reply(slave)),
+      }
+    }
+    Ok((population, bounds))
+  }
+}
+
+impl<S: Read + Write> Engine for ClusterEngine<S> {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    // a bounded world has the size of the pattern
+    self.size = self.rule.grid().map(|_| (width, height));
+    self.broadcast(&Message::Clear { size: self.size })?;
+    self.tiles = TileAssignment::new();
+    for slave in 0..self.slave_count() {
+      self.tiles.add_slave(slave);
+    }
+    self.borders.clear();
+    self.generation = 0;
+    if width == 0 || height == 0 {
+      return Ok(());
+    }
+
+    let cell = |x: i64, y: i64| {
+      if x < width as i64 && y < height as i64 {
+        cells[x as usize + y as usize * width]
+      } else {
+        DEAD
+      }
+    };
+    let mut written = Vec::new();
+    for tile in tiles_covering(0, 0, width as i64 - 1, height as i64 - 1) {
+      let rect = TileRect::of(tile, self.size);
+      let tile_cells: Vec<State> = (0..rect.height)
+        .flat_map(|y| (0..rect.width).map(move |x| (x, y)))
+        .map(|(x, y)| cell(rect.x + x as i64, rect.y + y as i64))
+        .collect();
+      let dead = tile_cells.iter().all(|&cell| cell == DEAD);
+      // an unbounded world creates dead tiles once they're reached
+      if self.size.is_some() || !dead {
+        self.tiles.create_tile(tile);
+      }
+      if dead {
+        continue;
+      }
+
+      let border = rect
+        .border(self.rule.range())
+        .into_iter()
+        .map(|(x, y)| tile_cells[x + y * rect.width])
+        .collect();
+      self.borders.insert(tile, border);
+      written.push((tile, tile_cells));
+    }
+
+    self.create_reached_tiles()?;
+    for (tile, cells) in written {
+      let slave = self.tiles.owner(tile).unwrap();
+      self.send(slave, &Message::WriteTile { tile, cells })?;
+    }
+    Ok(())
+  }
+
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    for _ in 0..generations {
+      self.next_generation()?;
+      self.generation += 1;
+    }
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let mut cells = vec![DEAD; width * height];
+    let request = Message::ReadRegion {
+      x,
+      y,
+      width,
+      height,
+    };
+    for (slave, reply) in self.request(&request)?.into_iter().enumerate() {
+      match reply {
+        Message::Region {
+          cells: ref slave_cells,
+        } if slave_cells.len() == cells.len() => {
+          // every cell is on a single slave, the others have it dead
+          for (cell, &slave_cell) in cells.iter_mut().zip(slave_cells) {
+            if slave_cell != DEAD {
+              *cell = slave_cell;
+            }
+          }
+        }
+        _ => return Err(unexpected_reply(slave)),
+      }
+    }
+    Ok(cells)
+  }
+
+  fn population(&self) -> Result<u64, Error> {
+    Ok(self.stats(false)?.0)
+  }
+
+  fn hash(&self) -> Result<u64, Error> {
+    if let Some((width, height)) = self.size {
+      let cells = self.read_region(0, 0, width, height)?;
+      return Ok(hash_world(width, height, |x, y| cells[x + y * width]));
+    }
+
+    let (x0, y0, x1, y1) = match self.stats(true)?.1 {
+      Some(bounds) => bounds,
+      None => return Ok(hash_world(0, 0, |_, _| DEAD)),
+    };
+
+    let width = (x1 - x0 + 1) as usize;
+    let height = (y1 - y0 + 1) as usize;
+    let cells = self.read_region(x0, y0, width, height)?;
+    Ok(hash_world(width, height, |x, y| cells[x + y * width]))
+  }
+}
+
+fn unexpected_reply(slave: usize) -> Error {
+  err_msg(format!("slave #{} has sent an unexpected reply", slave))
+}
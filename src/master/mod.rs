@@ -1,82 +1,200 @@
-extern crate mio;
-use self::mio::tcp::TcpListener;
-use self::mio::{Events, Poll};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::thread;
 
-use std::io::Result as IoResult;
-use std::net::SocketAddr;
+use failure::Error;
 
-mod connection;
-mod server;
-mod utils;
+mod cluster;
+mod tiles;
 
+pub use self::cluster::ClusterEngine;
+
+use rule::Rule;
+use slave;
 use utils::result::DescribeErr;
 
-pub fn listen(port: u16) -> IoResult<()> {
+/// Waits for `slaves` slaves to connect, then returns an engine which
+/// computes the world on them.
+pub fn listen(
+  port: u16,
+  rule: Rule,
+  slaves: usize,
+) -> Result<ClusterEngine<TcpStream>, Error> {
   let address = SocketAddr::from(([0, 0, 0, 0], port));
   info!(target: "master", "starting master server");
 
   trace!(target: "master", "binding server socket to {}", address);
-  let server_socket =
-    TcpListener::bind(&address).describe_err("can't bind server socket")?;
-
-  let server = server::Server::new(server_socket);
-
-  trace!(target: "master", "creating event loop");
-  let mut event_loop =
-    EventLoop::new(server).describe_err("can't create event loop")?;
-
-  info!(target: "master", "server is listening on port {}", port);
-  event_loop.run()
-}
+  let listener =
+    TcpListener::bind(address).describe_err("can't bind server socket")?;
+  info!(target: "master", "waiting for {} slaves on port {}", slaves, port);
+
+  let mut sockets = Vec::with_capacity(slaves);
+  while sockets.len() < slaves {
+    let (socket, address) =
+      listener.accept().describe_err("can't accept socket")?;
+    socket.set_nodelay(true)?;
+    info!(target: "master", "slave #{} has connected from {}", sockets.len(), address);
+    sockets.push(socket);
+  }
 
-struct EventLoop {
-  poll: Poll,
-  events: Events,
-  server: server::Server,
+  info!(target: "master", "rule = {}", rule);
+  ClusterEngine::new(rule, sockets)
 }
 
-impl EventLoop {
-  fn new(server: server::Server) -> IoResult<EventLoop> {
-    trace!(target: "master::event_loop", "creating poll");
-    let mut poll = Poll::new().describe_err("can't create poll")?;
-
-    trace!(target: "master::event_loop", "creating events collection");
-    let events = Events::with_capacity(1024);
+/// Starts `slaves` slaves on threads of this process, connected to the
+/// returned engine through Unix sockets. The slaves stop once the engine is
+/// dropped.
+pub fn spawn_local(
+  rule: Rule,
+  slaves: usize,
+  backend: &slave::Backend,
+) -> Result<ClusterEngine<UnixStream>, Error> {
+  let mut sockets = Vec::with_capacity(slaves);
+  for index in 0..slaves {
+    let (socket, slave_socket) =
+      UnixStream::pair().describe_err("can't create socket pair")?;
+    let backend = backend.clone();
+    thread::Builder::new()
+      .name(format!("slave #{}", index))
+      .spawn(move || {
+        if let Err(error) = slave::serve(slave_socket, &backend) {
+          error!(target: "slave", "slave #{} has failed: {}", index, error);
+        }
+      })?;
+    sockets.push(socket);
+  }
 
-    trace!(target: "master::event_loop", "registering server");
-    server
-      .register_into(&mut poll)
-      .describe_err("can't register server")?;
+  ClusterEngine::new(rule, sockets)
+}
 
-    Ok(EventLoop {
-      poll,
-      events,
-      server,
-    })
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use engine::Engine;
+  use rule::{State, ALIVE, DEAD};
+  use threaded::{scheduler::Scheduling, ThreadedEngine};
+  use unbounded::UnboundedEngine;
+
+  /// Returns a random soup with a quarter of the cells alive.
+  fn soup(width: usize, height: usize) -> Vec<State> {
+    let mut state = 12345u32;
+    (0..width * height)
+      .map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        if state >> 30 == 0 {
+          ALIVE
+        } else {
+          DEAD
+        }
+      })
+      .collect()
   }
 
-  fn run(&mut self) -> IoResult<()> {
-    loop {
-      trace!(target: "master::event_loop", "tick");
-      self.tick()?;
+  #[test]
+  fn cluster_matches_unbounded_engine() {
+    assert!(spawn_local(Rule::default(), 0, &slave::Backend::Cpu).is_err());
+
+    // a soup spanning several tiles, which grows beyond them
+    let (width, height) = (150, 70);
+    let soup = soup(width, height);
+
+    for &rule_str in &[
+      "B36/S23",
+      "B2/S/C3",
+      "R5,C0,M1,S34..58,B34..45",
+      "R3,C4,M0,S5..9,B4..7,NN",
+    ] {
+      let rule: Rule = rule_str.parse().unwrap();
+      let mut cluster = spawn_local(rule, 3, &slave::Backend::Cpu).unwrap();
+      let mut unbounded = UnboundedEngine::new(rule).unwrap();
+      cluster.load(width, height, &soup).unwrap();
+      unbounded.load(width, height, &soup).unwrap();
+
+      for _ in 0..4 {
+        assert_eq!(
+          cluster.hash().unwrap(),
+          unbounded.hash().unwrap(),
+          "{} at generation {}",
+          rule,
+          unbounded.generation()
+        );
+        assert_eq!(
+          cluster.population().unwrap(),
+          unbounded.population().unwrap()
+        );
+        assert_eq!(
+          cluster.read_region(-5, 60, 80, 20).unwrap(),
+          unbounded.read_region(-5, 60, 80, 20).unwrap()
+        );
+        cluster.step(3).unwrap();
+        unbounded.step(3).unwrap();
+        assert_eq!(cluster.generation(), unbounded.generation());
+      }
     }
   }
 
-  fn tick(&mut self) -> IoResult<()> {
-    let events_count = self
-      .poll
-      .poll(&mut self.events, None)
-      .describe_err("can't get events")?;
-    trace!(target: "master::event_loop", "events_count = {}", events_count);
-
-    for event in self.events.iter() {
-      trace!(target: "master::event_loop", "event = {:?}", event);
-
-      if let Err(error) = self.server.handle_event(&mut self.poll, event) {
-        return Err(error);
+  #[test]
+  fn bounded_worlds_match_threaded_engine() {
+    // worlds whose tiles are clipped on the right and bottom edges
+    for &(rule_str, width, height) in &[
+      ("B3/S23:P150,70", 150, 70),
+      ("B3/S23:T150,70", 150, 70),
+      ("B3/S23:K150*,70", 150, 70),
+      ("B3/S23:K150,70*", 150, 70),
+      ("B3/S23:C150,70", 150, 70),
+      ("B3/S23:S90", 90, 90),
+      ("R2,C0,M1,S5..8,B6..7:T150,70", 150, 70),
+    ] {
+      let rule: Rule = rule_str.parse().unwrap();
+      let soup = soup(width, height);
+      let mut cluster = spawn_local(rule, 2, &slave::Backend::Cpu).unwrap();
+      let mut threaded = ThreadedEngine::new(rule, 1, Scheduling::Static);
+      cluster.load(width, height, &soup).unwrap();
+      threaded.load(width, height, &soup).unwrap();
+
+      for _ in 0..4 {
+        cluster.step(5).unwrap();
+        threaded.step(5).unwrap();
+        assert_eq!(
+          cluster.hash().unwrap(),
+          threaded.hash().unwrap(),
+          "{} at generation {}",
+          rule,
+          threaded.generation()
+        );
       }
+      assert_eq!(
+        cluster.population().unwrap(),
+        threaded.population().unwrap()
+      );
+      // every tile of a bounded world is kept
+      let tiles = ((width + 63) / 64) * ((height + 63) / 64);
+      assert_eq!(cluster.tile_count(), tiles);
     }
+  }
 
-    Ok(())
+  #[test]
+  fn tiles_follow_gliders() {
+    let rule = Rule::default();
+    let mut cluster = spawn_local(rule, 2, &slave::Backend::Cpu).unwrap();
+    let mut unbounded = UnboundedEngine::new(rule).unwrap();
+
+    // a glider flying towards negative coordinates
+    let glider = [ALIVE, ALIVE, ALIVE, ALIVE, DEAD, DEAD, DEAD, ALIVE, DEAD];
+    cluster.load(3, 3, &glider).unwrap();
+    unbounded.load(3, 3, &glider).unwrap();
+
+    // it crosses two tiles diagonally
+    cluster.step(4 * 130).unwrap();
+    unbounded.step(4 * 130).unwrap();
+    assert_eq!(cluster.hash().unwrap(), unbounded.hash().unwrap());
+    assert_eq!(
+      cluster.read_region(-131, -131, 5, 5).unwrap(),
+      unbounded.read_region(-131, -131, 5, 5).unwrap()
+    );
+    assert_eq!(cluster.population().unwrap(), 5);
+
+    // tiles it has left are freed
+    assert!(cluster.tile_count() <= 4);
   }
 }
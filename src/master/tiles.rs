@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+
+use unbounded::world::TileCoord;
+
+/// Keeps track of which slave computes which tile of an unbounded world.
+///
+/// New tiles stay pending until [`assign_pending`] gives them to the slaves
+/// with the fewest tiles.
+///
+/// [`assign_pending`]: #method.assign_pending
+pub struct TileAssignment<S> {
+  owners: HashMap<TileCoord, S>,
+  loads: BTreeMap<S, usize>,
+  pending: Vec<TileCoord>,
+}
+
+impl<S: Copy + Ord> TileAssignment<S> {
+  pub fn new() -> Self {
+    TileAssignment {
+      owners: HashMap::new(),
+      loads: BTreeMap::new(),
+      pending: Vec::new(),
+    }
+  }
+
+  pub fn add_slave(&mut self, slave: S) {
+    self.loads.entry(slave).or_insert(0);
+  }
+
+  /// Adds a newly created tile, it stays pending until it's assigned.
+  pub fn create_tile(&mut self, tile: TileCoord) {
+    if !self.owners.contains_key(&tile) && !self.pending.contains(&tile) {
+      self.pending.push(tile);
+    }
+  }
+
+  /// Removes a tile which has been freed.
+  pub fn remove_tile(&mut self, tile: TileCoord) {
+    if let Some(slave) = self.owners.remove(&tile) {
+      *self.loads.get_mut(&slave).unwrap() -= 1;
+    }
+    self.pending.retain(|&pending| pending != tile);
+  }
+
+  /// Assigns pending tiles to the least loaded slaves and returns the newly
+  /// assigned tiles of every slave. Tiles stay pending if there are no
+  /// slaves.
+  pub fn assign_pending(&mut self) -> BTreeMap<S, Vec<TileCoord>> {
+    let mut assigned = BTreeMap::new();
+    if self.loads.is_empty() {
+      return assigned;
+    }
+
+    for tile in self.pending.drain(..) {
+      // ties are broken by the order of slaves to keep assignment stable
+      let (&slave, load) = self
+        .loads
+        .iter_mut()
+        .min_by_key(|&(_, &mut load)| load)
+        .unwrap();
+      *load += 1;
+
+      self.owners.insert(tile, slave);
+      assigned.entry(slave).or_insert_with(Vec::new).push(tile);
+    }
+
+    assigned
+  }
+
+  /// Returns the slave a tile is assigned to.
+  pub fn owner(&self, tile: TileCoord) -> Option<S> {
+    self.owners.get(&tile).cloned()
+  }
+
+  /// Returns every assigned tile and its slave, ordered by tiles.
+  pub fn assigned(&self) -> Vec<(TileCoord, S)> {
+    let mut assigned: Vec<_> = self
+      .owners
+      .iter()
+      .map(|(&tile, &slave)| (tile, slave))
+      .collect();
+    assigned.sort();
+    assigned
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tiles_are_balanced() {
+    let mut assignment = TileAssignment::new();
+    for tile in 0..5 {
+      assignment.create_tile((tile, 0));
+    }
+    assert!(assignment.assign_pending().is_empty());
+
+    assignment.add_slave(1);
+    assignment.add_slave(2);
+    let assigned = assignment.assign_pending();
+    assert_eq!(assigned[&1], vec![(0, 0), (2, 0), (4, 0)]);
+    assert_eq!(assigned[&2], vec![(1, 0), (3, 0)]);
+
+    // a new tile goes to the slave with fewer tiles, assigned ones stay
+    assignment.create_tile((-1, -1));
+    assignment.create_tile((0, 0));
+    let assigned = assignment.assign_pending();
+    assert_eq!(assigned.len(), 1);
+    assert_eq!(assigned[&2], vec![(-1, -1)]);
+    assert_eq!(assignment.owner((-1, -1)), Some(2));
+    assert_eq!(assignment.owner((5, 0)), None);
+    assert_eq!(assignment.assigned()[..2], [((-1, -1), 2), ((0, 0), 1)]);
+
+    // a freed tile makes room for the next one
+    assignment.remove_tile((0, 0));
+    assignment.remove_tile((0, 0));
+    assert_eq!(assignment.owner((0, 0)), None);
+    assignment.create_tile((9, 9));
+    assert_eq!(assignment.assign_pending()[&1], vec![(9, 9)]);
+  }
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+
+use rule::neighbors::*;
+
+/// Maximum range of a neighborhood.
+pub const MAX_RANGE: usize = 10;
+
+/// Number of 64-bit words needed for a mask of the maximum range.
+const MASK_WORDS: usize = ((2 * MAX_RANGE + 1) * (2 * MAX_RANGE + 1) + 63) / 64;
+
+/// Offsets of the neighbors of the range 1 Moore neighborhood in the order of
+/// their bits in a configuration (see [`neighbors`]), `y` grows downwards.
+///
+/// [`neighbors`]: ../rule/neighbors/index.html
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const OFFSETS: [(u8, isize, isize); 8] = [
+  (N, 0, -1), (NE, 1, -1), (E, 1, 0), (SE, 1, 1),
+  (S, 0, 1), (SW, -1, 1), (W, -1, 0), (NW, -1, -1),
+];
+
+/// Cells around a cell which are counted as its neighbors, for any range.
+///
+/// The hexagonal neighborhood is emulated on the square grid the same way as
+/// in Golly: every row is shifted by half a cell to the right relative to the
+/// row above it, so the six neighbors of a cell are its Moore neighbors
+/// except the top right and the bottom left ones.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Neighborhood {
+  /// The square of cells within the range.
+  Moore,
+  /// The diamond of cells within the range in the Manhattan distance.
+  VonNeumann,
+  /// The hexagon of cells within the range on the emulated hexagonal grid.
+  Hexagonal,
+  /// An arbitrary set of cells within the range.
+  Custom(Mask),
+}
+
+impl Neighborhood {
+  /// Returns `true` if the cell at the offset `(dx, dy)` belongs to the
+  /// neighborhood of the given range. The center cell always belongs to it,
+  /// whether it's counted or not is up to the rule.
+  pub fn contains(&self, dx: isize, dy: isize, range: usize) -> bool {
+    let range = range as isize;
+    if dx.abs() > range || dy.abs() > range {
+      return false;
+    }
+
+    match self {
+      Neighborhood::Moore => true,
+      Neighborhood::VonNeumann => dx.abs() + dy.abs() <= range,
+      Neighborhood::Hexagonal => {
+        // the distance along the excluded diagonal is the sum of offsets
+        dx * dy >= 0 || dx.abs() + dy.abs() <= range
+      }
+      Neighborhood::Custom(mask) => (dx, dy) == (0, 0) || mask.get(dx, dy),
+    }
+  }
+
+  /// Returns the number of cells in the neighborhood of the given range,
+  /// including the center cell.
+  pub fn size(&self, range: usize) -> usize {
+    let range = range as isize;
+    (-range..=range)
+      .flat_map(|dy| (-range..=range).map(move |dx| (dx, dy)))
+      .filter(|&(dx, dy)| self.contains(dx, dy, range as usize))
+      .count()
+  }
+
+  /// Splits the neighborhood of the given range (including the center cell)
+  /// into rectangles `(x0, y0, x1, y1)` with inclusive bounds relative to the
+  /// center. Backends which count alive cells with a summed-area table need
+  /// one lookup per rectangle: one for the Moore neighborhood, one per row
+  /// for the other convex shapes, one per run of cells for custom masks.
+  pub fn rectangles(&self, range: usize) -> Vec<(isize, isize, isize, isize)> {
+    let r = range as isize;
+    if let Neighborhood::Moore = self {
+      return vec![(-r, -r, r, r)];
+    }
+
+    let mut rectangles = Vec::new();
+    for dy in -r..=r {
+      let mut run_start = None;
+      for dx in -r..=r + 1 {
+        match (run_start, dx <= r && self.contains(dx, dy, range)) {
+          (None, true) => run_start = Some(dx),
+          (Some(start), false) => {
+            rectangles.push((start, dy, dx - 1, dy));
+            run_start = None;
+          }
+          _ => {}
+        }
+      }
+    }
+    rectangles
+  }
+
+  /// Returns the bits (see [`neighbors`]) of the cells of the range 1
+  /// Moore neighborhood which belong to this neighborhood of range 1.
+  ///
+  /// [`neighbors`]: ../rule/neighbors/index.html
+  pub fn configuration_mask(&self) -> u8 {
+    OFFSETS
+      .iter()
+      .filter(|&&(_, dx, dy)| self.contains(dx, dy, 1))
+      .fold(0, |mask, &(bit, _, _)| mask | bit)
+  }
+}
+
+impl fmt::Debug for Neighborhood {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Neighborhood::Moore => write!(f, "Moore"),
+      Neighborhood::VonNeumann => write!(f, "VonNeumann"),
+      Neighborhood::Hexagonal => write!(f, "Hexagonal"),
+      Neighborhood::Custom(mask) => write!(f, "Custom({})", mask),
+    }
+  }
+}
+
+/// A set of cells within a range around the center cell.
+///
+/// It's written as hexadecimal digits which list the cells of the square of
+/// the range row by row from the top left corner, the most significant bit
+/// of the first digit is the first cell. The last digit is padded with zeros.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mask {
+  range: u8,
+  bits: [u64; MASK_WORDS],
+}
+
+impl Mask {
+  fn side(&self) -> usize {
+    2 * self.range as usize + 1
+  }
+
+  fn get(&self, dx: isize, dy: isize) -> bool {
+    let range = self.range as isize;
+    if dx.abs() > range || dy.abs() > range {
+      return false;
+    }
+
+    let index = (dx + range) as usize + (dy + range) as usize * self.side();
+    (self.bits[index / 64] >> (index % 64)) & 1 != 0
+  }
+
+  /// Parses a mask of the given range from hexadecimal digits.
+  pub fn parse(s: &str, range: usize) -> Result<Mask, &'static str> {
+    if range > MAX_RANGE {
+      return Err("the range of a mask is too large");
+    }
+
+    let mut mask = Mask {
+      range: range as u8,
+      bits: [0; MASK_WORDS],
+    };
+    let cells = mask.side() * mask.side();
+    if s.len() != (cells + 3) / 4 {
+      return Err("expected a hexadecimal digit for every 4 cells of a mask");
+    }
+
+    for (i, c) in s.chars().enumerate() {
+      let digit = c.to_digit(16).ok_or("expected hexadecimal digits")?;
+      for bit in 0..4 {
+        let index = i * 4 + bit;
+        if digit & (8 >> bit) == 0 {
+          continue;
+        }
+        if index >= cells {
+          return Err("padding bits of a mask must be zero");
+        }
+        mask.bits[index / 64] |= 1 << (index % 64);
+      }
+    }
+
+    Ok(mask)
+  }
+}
+
+impl fmt::Display for Mask {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let cells = self.side() * self.side();
+    for i in 0..(cells + 3) / 4 {
+      let digit = (0..4)
+        .map(|bit| i * 4 + bit)
+        .filter(|&index| index < cells)
+        .filter(|&index| (self.bits[index / 64] >> (index % 64)) & 1 != 0)
+        .fold(0, |digit, index| digit | 8 >> (index % 4));
+      write!(f, "{:x}", digit)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sizes() {
+    assert_eq!(Neighborhood::Moore.size(1), 9);
+    assert_eq!(Neighborhood::Moore.size(2), 25);
+    assert_eq!(Neighborhood::VonNeumann.size(1), 5);
+    assert_eq!(Neighborhood::VonNeumann.size(2), 13);
+    assert_eq!(Neighborhood::Hexagonal.size(1), 7);
+    assert_eq!(Neighborhood::Hexagonal.size(2), 19);
+  }
+
+  #[test]
+  fn configuration_masks() {
+    assert_eq!(Neighborhood::Moore.configuration_mask(), 0xff);
+    assert_eq!(Neighborhood::VonNeumann.configuration_mask(), N | E | S | W);
+    assert_eq!(Neighborhood::Hexagonal.configuration_mask(), !(NE | SW));
+  }
+
+  #[test]
+  fn rectangles_cover_neighborhood() {
+    let mask = Mask::parse("a5a5a58", 2).unwrap();
+    for neighborhood in &[
+      Neighborhood::Moore,
+      Neighborhood::VonNeumann,
+      Neighborhood::Hexagonal,
+      Neighborhood::Custom(mask),
+    ] {
+      for range in 1..4 {
+        let covered: usize = neighborhood
+          .rectangles(range)
+          .iter()
+          .map(|&(x0, y0, x1, y1)| ((x1 - x0 + 1) * (y1 - y0 + 1)) as usize)
+          .sum();
+        assert_eq!(covered, neighborhood.size(range), "{:?}", neighborhood);
+      }
+    }
+  }
+
+  #[test]
+  fn masks() {
+    // a cross of range 1: 010 111 010, padded with 3 zeros
+    let mask = Mask::parse("5d0", 1).unwrap();
+    assert_eq!(mask.to_string(), "5d0");
+    assert_eq!(
+      Neighborhood::Custom(mask).configuration_mask(),
+      Neighborhood::VonNeumann.configuration_mask()
+    );
+
+    assert!(Mask::parse("5d", 1).is_err());
+    assert!(Mask::parse("5dg", 1).is_err());
+    assert!(Mask::parse("5d1", 1).is_err());
+  }
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use failure::{err_msg, Error};
+
+use engine::Engine;
+use rule::{Rule, State, ALIVE, DEAD};
+
+/// Maximum length of lines of written RLE files, as recommended by the format.
+const MAX_LINE_LENGTH: usize = 70;
+
+/// Number of states which are written as one letter in multi-state RLE,
+/// higher states are prefixed with one of `p..=y`.
+const STATES_PER_LETTER: usize = 24;
+
+/// A rectangular pattern of cells in the [RLE format] of Golly. Two-state
+/// patterns are written with `b` and `o`, patterns with dying states of
+/// "Generations" rules with `.`, `A..=X`, `pA..=pX` and so on.
+///
+/// [RLE format]: http://golly.sourceforge.net/Help/formats.html#rle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+  pub width: usize,
+  pub height: usize,
+  /// Cells row by row.
+  pub cells: Vec<State>,
+  /// The rule given in the header of the pattern.
+  pub rule: Option<Rule>,
+}
+
+impl Pattern {
+  /// Creates a pattern of dead cells.
+  pub fn new(width: usize, height: usize) -> Self {
+    Pattern {
+      width,
+      height,
+      cells: vec![DEAD; width * height],
+      rule: None,
+    }
+  }
+
+  /// Reads a pattern from an RLE file.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    let path = path.as_ref();
+    let rle = fs::read_to_string(path).map_err(|error| {
+      err_msg(format!("can't read pattern {}: {}", path.display(), error))
+    })?;
+    rle.parse()
+  }
+
+  /// Writes the pattern to an RLE file.
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    fs::write(path, self.to_string()).map_err(|error| {
+      err_msg(format!("can't write pattern {}: {}", path.display(), error))
+    })
+  }
+
+  /// Reads a region of the world of `engine` as a pattern.
+  pub fn read(
+    engine: &dyn Engine,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Self, Error> {
+    Ok(Pattern {
+      width,
+      height,
+      cells: engine.read_region(x, y, width, height)?,
+      rule: None,
+    })
+  }
+
+  /// Loads the pattern into `engine`, see [`Engine::load`].
+  ///
+  /// [`Engine::load`]: ../engine/trait.Engine.html#tymethod.load
+  pub fn load_into(&self, engine: &mut dyn Engine) -> Result<(), Error> {
+    engine.load(self.width, self.height, &self.cells)
+  }
+
+  pub fn get(&self, x: usize, y: usize) -> State {
+    self.cells[x + y * self.width]
+  }
+
+  pub fn set(&mut self, x: usize, y: usize, state: State) {
+    self.cells[x + y * self.width] = state;
+  }
+
+  /// Copies the cells of `pattern` with its top left corner at `(x, y)`.
+  pub fn paste(&mut self, pattern: &Pattern, x: usize, y: usize) {
+    for py in 0..pattern.height {
+      for px in 0..pattern.width {
+        self.set(x + px, y + py, pattern.get(px, py));
+      }
+    }
+  }
+
+  /// Returns the number of alive cells.
+  pub fn population(&self) -> usize {
+    self.cells.iter().filter(|&&state| state == ALIVE).count()
+  }
+
+  fn parse_header(&mut self, header: &str) -> Result<(), Error> {
+    let mut rest = header;
+    loop {
+      let (field, next) = match rest.find(',') {
+        Some(index) => (&rest[..index], Some(&rest[index + 1..])),
+        None => (rest, None),
+      };
+      let mut parts = field.splitn(2, '=');
+      let key = parts.next().unwrap().trim();
+      let value = parts
+        .next()
+        .ok_or_else(|| err_msg(format!("invalid header field '{}'", field)))?
+        .trim();
+
+      let parse_size = |value: &str| {
+        value
+          .parse::<usize>()
+          .map_err(|_| err_msg(format!("invalid size '{}'", value)))
+      };
+
+      match key {
+        "x" => self.width = parse_size(value)?,
+        "y" => self.height = parse_size(value)?,
+        "rule" => {
+          // rules of bounded grids and Larger than Life rules have commas,
+          // so the rule takes the rest of the line
+          let rule = rest.splitn(2, '=').nth(1).unwrap().trim();
+          self.rule = Some(rule.parse()?);
+          return Ok(());
+        }
+        _ => {}
+      }
+
+      match next {
+        Some(next) => rest = next,
+        None => return Ok(()),
+      }
+    }
+  }
+}
+
+impl FromStr for Pattern {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut lines = s
+      .lines()
+      .map(|line| line.trim())
+      .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let mut pattern = Pattern::new(0, 0);
+    pattern.parse_header(
+      lines
+        .next()
+        .ok_or_else(|| err_msg("the pattern has no header"))?,
+    )?;
+    pattern.cells = vec![DEAD; pattern.width * pattern.height];
+
+    let (mut x, mut y): (usize, usize) = (0, 0);
+    let mut count: Option<usize> = None;
+    let mut prefix: Option<usize> = None;
+    for c in lines.flat_map(|line| line.chars()) {
+      let state = match c {
+        '0'..='9' if prefix.is_none() => {
+          let digit = c.to_digit(10).unwrap() as usize;
+          count = count
+            .unwrap_or(0)
+            .checked_mul(10)
+            .and_then(|count| count.checked_add(digit));
+          if count.is_none() {
+            return Err(err_msg("run count too large"));
+          }
+          continue;
+        }
+        'p'..='y' if prefix.is_none() => {
+          prefix = Some(c as usize - 'p' as usize + 1);
+          continue;
+        }
+        '!' => break,
+        '$' => {
+          y = y.saturating_add(count.take().unwrap_or(1));
+          x = 0;
+          continue;
+        }
+        c if c.is_whitespace() => continue,
+        'b' | '.' if prefix.is_none() => DEAD,
+        'o' if prefix.is_none() => ALIVE,
+        'A'..='X' => {
+          let letter = c as usize - 'A' as usize + 1;
+          let state = prefix.take().unwrap_or(0) * STATES_PER_LETTER + letter;
+          if state > usize::from(State::max_value()) {
+            return Err(err_msg(format!("invalid state {}", state)));
+          }
+          state as State
+        }
+        _ => return Err(err_msg(format!("unexpected '{}' in the pattern", c))),
+      };
+
+      let run = count.take().unwrap_or(1);
+      if run > pattern.width - x || (y >= pattern.height && state != DEAD) {
+        return Err(err_msg(format!(
+          "the pattern doesn't fit into {}x{} cells",
+          pattern.width, pattern.height
+        )));
+      }
+
+      if state != DEAD {
+        for x in x..x + run {
+          pattern.set(x, y, state);
+        }
+      }
+      x += run;
+    }
+
+    Ok(pattern)
+  }
+}
+
+impl fmt::Display for Pattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "x = {}, y = {}", self.width, self.height)?;
+    if let Some(rule) = self.rule {
+      write!(f, ", rule = {}", rule)?;
+    }
+    writeln!(f)?;
+
+    let multi_state = self.cells.iter().any(|&state| state > ALIVE);
+    let mut writer = RunWriter {
+      f,
+      line_length: 0,
+      multi_state,
+    };
+
+    let mut empty_rows = 0;
+    for (y, row) in self.cells.chunks(self.width.max(1)).enumerate() {
+      // dead cells at the end of a row are implied
+      let length = row
+        .iter()
+        .rposition(|&state| state != DEAD)
+        .map_or(0, |x| x + 1);
+      if length == 0 {
+        empty_rows += 1;
+        continue;
+      }
+
+      if y > empty_rows {
+        writer.write_run(empty_rows + 1, None)?;
+      } else if empty_rows > 0 {
+        writer.write_run(empty_rows, None)?;
+      }
+      empty_rows = 0;
+
+      let mut x = 0;
+      while x < length {
+        let state = row[x];
+        let run = row[x..length].iter().take_while(|&&s| s == state).count();
+        writer.write_run(run, Some(state))?;
+        x += run;
+      }
+    }
+
+    writeln!(writer.f, "!")
+  }
+}
+
+/// Writes runs of cells and ends of rows, wrapping lines at
+/// [`MAX_LINE_LENGTH`](constant.MAX_LINE_LENGTH.html).
+struct RunWriter<'a, 'b: 'a> {
+  f: &'a mut fmt::Formatter<'b>,
+  line_length: usize,
+  multi_state: bool,
+}
+
+impl<'a, 'b> RunWriter<'a, 'b> {
+  /// Writes a run of cells of `state`, or of ends of rows if it's `None`.
+  fn write_run(&mut self, run: usize, state: Option<State>) -> fmt::Result {
+    let tag = match state {
+      None => "$".to_owned(),
+      Some(state) if !self.multi_state => {
+        (if state == DEAD { "b" } else { "o" }).to_owned()
+      }
+      Some(DEAD) => ".".to_owned(),
+      Some(state) => {
+        let state = usize::from(state) - 1;
+        let letter = (b'A' + (state % STATES_PER_LETTER) as u8) as char;
+        match state / STATES_PER_LETTER {
+          0 => letter.to_string(),
+          prefix => format!("{}{}", (b'p' + prefix as u8 - 1) as char, letter),
+        }
+      }
+    };
+
+    let item = if run > 1 {
+      format!("{}{}", run, tag)
+    } else {
+      tag
+    };
+
+    if self.line_length + item.len() > MAX_LINE_LENGTH {
+      writeln!(self.f)?;
+      self.line_length = 0;
+    }
+    self.line_length += item.len();
+    self.f.write_str(&item)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GLIDER: &str = "#N Glider\nx = 3, y = 3, rule = B3/S23\nbob$2bo$3o!\n";
+
+  #[test]
+  fn rle() {
+    let glider: Pattern = GLIDER.parse().unwrap();
+    assert_eq!((glider.width, glider.height), (3, 3));
+    assert_eq!(glider.cells, vec![0, 1, 0, 0, 0, 1, 1, 1, 1]);
+    assert_eq!(glider.rule, Some(Rule::default()));
+    assert_eq!(
+      glider.to_string(),
+      "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n"
+    );
+
+    let mut pattern = Pattern::new(30, 4);
+    pattern.set(29, 0, ALIVE);
+    pattern.set(0, 3, 2);
+    pattern.set(1, 3, 30);
+    pattern.set(2, 3, 255);
+    let rle = pattern.to_string();
+    assert_eq!(rle, "x = 30, y = 4\n29.A3$BpFyO!\n");
+    assert_eq!(rle.parse::<Pattern>().unwrap(), pattern);
+
+    assert!("x = 2, y = 1\n3o!".parse::<Pattern>().is_err());
+    assert!("x = 2, y = 1\nbz!".parse::<Pattern>().is_err());
+    assert!("bo!".parse::<Pattern>().is_err());
+    assert!("x = 2, y\nbo!".parse::<Pattern>().is_err());
+    let error = "x = 2, y = 1\n99999999999999999999999o!"
+      .parse::<Pattern>()
+      .unwrap_err();
+    assert_eq!(error.to_string(), "run count too large");
+    assert!("x = 2, y = 1\no18446744073709551615o!"
+      .parse::<Pattern>()
+      .is_err());
+  }
+
+  #[test]
+  fn rules_with_commas() {
+    for &rule in &[
+      "B3/S23:T16,16",
+      "B3/S23:K10*,10",
+      "R5,C0,M1,S34..58,B34..45",
+    ] {
+      let mut pattern = Pattern::new(3, 2);
+      pattern.set(1, 1, ALIVE);
+      pattern.rule = Some(rule.parse().unwrap());
+
+      let rle = pattern.to_string();
+      assert!(rle.starts_with(&format!("x = 3, y = 2, rule = {}\n", rule)));
+      assert_eq!(rle.parse::<Pattern>().unwrap(), pattern);
+    }
+  }
+}
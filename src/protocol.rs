@@ -0,0 +1,495 @@
+//! Messages exchanged between the master and slaves.
+//!
+//! Every message is sent as a frame: a 32-bit big-endian length of the body
+//! followed by the body itself. The first byte of the body is the tag of the
+//! message, the rest depends on the message type.
+//!
+//! The master drives the slaves: a slave only sends a message in reply to
+//! [`Step`], [`ReadRegion`] or [`GetStats`].
+//!
+//! [`Step`]: enum.Message.html#variant.Step
+//! [`ReadRegion`]: enum.Message.html#variant.ReadRegion
+//! [`GetStats`]: enum.Message.html#variant.GetStats
+
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+
+use rule::{Rule, State};
+use unbounded::world::TileCoord;
+
+/// Length of the frame header which contains the length of the body.
+pub const HEADER_LENGTH: usize = 4;
+/// Messages with longer bodies are rejected to protect from garbage input.
+pub const MAX_BODY_LENGTH: usize = 256 * 1024 * 1024;
+
+const SETUP_TAG: u8 = 1;
+const ASSIGN_TILES_TAG: u8 = 2;
+const CLEAR_TAG: u8 = 3;
+const WRITE_TILE_TAG: u8 = 4;
+const STEP_TAG: u8 = 5;
+const BORDERS_TAG: u8 = 6;
+const READ_REGION_TAG: u8 = 7;
+const REGION_TAG: u8 = 8;
+const GET_STATS_TAG: u8 = 9;
+const STATS_TAG: u8 = 10;
+
+/// Cells of tiles, see [`tiling`](../tiling/index.html) for their order.
+pub type TileCells = Vec<(TileCoord, Vec<State>)>;
+
+/// The population and the bounds `(x0, y0, x1, y1)` (inclusive) of cells
+/// which aren't dead, see [`Stats`](enum.Message.html#variant.Stats).
+pub type Stats = (u64, Option<(i64, i64, i64, i64)>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+  /// Sent by the master to every slave right after it has connected.
+  Setup { rule: Rule },
+  /// Sent by the master when tiles of an unbounded world are assigned to the
+  /// slave. The tiles are dead until they're written.
+  AssignTiles { tiles: Vec<TileCoord> },
+  /// Drops every tile of the slave before a new world is loaded. The size
+  /// is given for bounded worlds, whose tiles are clipped to it and never
+  /// freed.
+  Clear { size: Option<(usize, usize)> },
+  /// Replaces the cells of an assigned tile, row by row.
+  WriteTile { tile: TileCoord, cells: Vec<State> },
+  /// Computes the next generation of every tile of the slave from the halos
+  /// of all of them. The slave replies with [`Borders`].
+  ///
+  /// [`Borders`]: #variant.Borders
+  Step { halos: TileCells },
+  /// The borders of every tile of the slave after a generation, and the
+  /// tiles which have been freed because all of their cells are dead.
+  Borders {
+    borders: TileCells,
+    freed: Vec<TileCoord>,
+  },
+  /// Asks for the cells of a region, the slave replies with [`Region`].
+  ///
+  /// [`Region`]: #variant.Region
+  ReadRegion {
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  },
+  /// The cells of a region, row by row. Cells of tiles of other slaves are
+  /// dead.
+  Region { cells: Vec<State> },
+  /// Asks for the population of the tiles and, if `bounds` is set, their
+  /// bounds, which GPU slaves have to read their tiles for. The slave replies
+  /// with [`Stats`].
+  ///
+  /// [`Stats`]: #variant.Stats
+  GetStats { bounds: bool },
+  /// The number of alive cells of the tiles of a slave and the bounds
+  /// `(x0, y0, x1, y1)` (inclusive) of the cells which aren't dead, if they
+  /// were asked for.
+  Stats {
+    population: u64,
+    bounds: Option<(i64, i64, i64, i64)>,
+  },
+}
+
+impl Message {
+  /// Encodes the message into a frame which is ready to be sent.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+
+    match self {
+      Message::Setup { rule } => {
+        encoder.put_u8(SETUP_TAG);
+        encoder.put_str(&rule.to_string());
+      }
+      Message::AssignTiles { tiles } => {
+        encoder.put_u8(ASSIGN_TILES_TAG);
+        encoder.put_tiles(tiles);
+      }
+      Message::Clear { size } => {
+        encoder.put_u8(CLEAR_TAG);
+        match size {
+          Some((width, height)) => {
+            encoder.put_u8(1);
+            encoder.put_u32(*width as u32);
+            encoder.put_u32(*height as u32);
+          }
+          None => encoder.put_u8(0),
+        }
+      }
+      Message::WriteTile { tile, cells } => {
+        encoder.put_u8(WRITE_TILE_TAG);
+        encoder.put_tile(*tile);
+        encoder.put_cells(cells);
+      }
+      Message::Step { halos } => {
+        encoder.put_u8(STEP_TAG);
+        encoder.put_tile_cells(halos);
+      }
+      Message::Borders { borders, freed } => {
+        encoder.put_u8(BORDERS_TAG);
+        encoder.put_tile_cells(borders);
+        encoder.put_tiles(freed);
+      }
+      Message::ReadRegion {
+        x,
+        y,
+        width,
+        height,
+      } => {
+        encoder.put_u8(READ_REGION_TAG);
+        encoder.put_i64(*x);
+        encoder.put_i64(*y);
+        encoder.put_u32(*width as u32);
+        encoder.put_u32(*height as u32);
+      }
+      Message::Region { cells } => {
+        encoder.put_u8(REGION_TAG);
+        encoder.put_cells(cells);
+      }
+      Message::GetStats { bounds } => {
+        encoder.put_u8(GET_STATS_TAG);
+        encoder.put_u8(*bounds as u8);
+      }
+      Message::Stats { population, bounds } => {
+        encoder.put_u8(STATS_TAG);
+        encoder.put_i64(*population as i64);
+        match bounds {
+          Some((x0, y0, x1, y1)) => {
+            encoder.put_u8(1);
+            for &value in &[x0, y0, x1, y1] {
+              encoder.put_i64(*value);
+            }
+          }
+          None => encoder.put_u8(0),
+        }
+      }
+    }
+
+    encoder.finish()
+  }
+
+  /// Decodes a message from a frame body (i.e. without the header).
+  pub fn decode(body: &[u8]) -> IoResult<Message> {
+    let mut decoder = Decoder::new(body);
+
+    let message = match decoder.get_u8()? {
+      SETUP_TAG => {
+        let rule = decoder.get_str()?.parse().map_err(invalid_data)?;
+        Message::Setup { rule }
+      }
+      ASSIGN_TILES_TAG => Message::AssignTiles {
+        tiles: decoder.get_tiles()?,
+      },
+      CLEAR_TAG => Message::Clear {
+        size: match decoder.get_u8()? {
+          0 => None,
+          _ => Some((decoder.get_u32()? as usize, decoder.get_u32()? as usize)),
+        },
+      },
+      WRITE_TILE_TAG => Message::WriteTile {
+        tile: decoder.get_tile()?,
+        cells: decoder.get_cells()?,
+      },
+      STEP_TAG => Message::Step {
+        halos: decoder.get_tile_cells()?,
+      },
+      BORDERS_TAG => Message::Borders {
+        borders: decoder.get_tile_cells()?,
+        freed: decoder.get_tiles()?,
+      },
+      READ_REGION_TAG => Message::ReadRegion {
+        x: decoder.get_i64()?,
+        y: decoder.get_i64()?,
+        width: decoder.get_u32()? as usize,
+        height: decoder.get_u32()? as usize,
+      },
+      REGION_TAG => Message::Region {
+        cells: decoder.get_cells()?,
+      },
+      GET_STATS_TAG => Message::GetStats {
+        bounds: decoder.get_u8()? != 0,
+      },
+      STATS_TAG => {
+        let population = decoder.get_i64()? as u64;
+        let bounds = match decoder.get_u8()? {
+          0 => None,
+          _ => Some((
+            decoder.get_i64()?,
+            decoder.get_i64()?,
+            decoder.get_i64()?,
+            decoder.get_i64()?,
+          )),
+        };
+        Message::Stats { population, bounds }
+      }
+      tag => return Err(invalid_data(format!("unknown message tag {}", tag))),
+    };
+
+    decoder.finish()?;
+    Ok(message)
+  }
+
+  pub fn read_from<R: Read>(reader: &mut R) -> IoResult<Message> {
+    let mut header = [0; HEADER_LENGTH];
+    reader.read_exact(&mut header)?;
+
+    let mut body = vec![0; body_length(header)?];
+    reader.read_exact(&mut body)?;
+    Message::decode(&body)
+  }
+
+  pub fn write_to<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+    writer.write_all(&self.encode())?;
+    writer.flush()
+  }
+}
+
+/// Reads the length of the body from a frame header.
+pub fn body_length(header: [u8; HEADER_LENGTH]) -> IoResult<usize> {
+  let length = header
+    .iter()
+    .fold(0, |length, &byte| length << 8 | byte as usize);
+
+  if length > MAX_BODY_LENGTH {
+    return Err(invalid_data(format!("message is too long ({})", length)));
+  }
+
+  Ok(length)
+}
+
+fn invalid_data<E: ToString>(error: E) -> IoError {
+  IoError::new(ErrorKind::InvalidData, error.to_string())
+}
+
+struct Encoder {
+  bytes: Vec<u8>,
+}
+
+impl Encoder {
+  fn new() -> Self {
+    Encoder {
+      bytes: vec![0; HEADER_LENGTH],
+    }
+  }
+
+  fn put_u8(&mut self, value: u8) {
+    self.bytes.push(value);
+  }
+
+  fn put_u32(&mut self, value: u32) {
+    for i in (0..4).rev() {
+      self.bytes.push((value >> (i * 8)) as u8);
+    }
+  }
+
+  fn put_i64(&mut self, value: i64) {
+    for i in (0..8).rev() {
+      self.bytes.push((value >> (i * 8)) as u8);
+    }
+  }
+
+  fn put_str(&mut self, value: &str) {
+    self.put_u32(value.len() as u32);
+    self.bytes.extend_from_slice(value.as_bytes());
+  }
+
+  fn put_tile(&mut self, (x, y): TileCoord) {
+    self.put_i64(x);
+    self.put_i64(y);
+  }
+
+  fn put_tiles(&mut self, tiles: &[TileCoord]) {
+    self.put_u32(tiles.len() as u32);
+    for &tile in tiles {
+      self.put_tile(tile);
+    }
+  }
+
+  fn put_cells(&mut self, cells: &[State]) {
+    self.put_u32(cells.len() as u32);
+    self.bytes.extend_from_slice(cells);
+  }
+
+  fn put_tile_cells(&mut self, tiles: &[(TileCoord, Vec<State>)]) {
+    self.put_u32(tiles.len() as u32);
+    for (tile, cells) in tiles {
+      self.put_tile(*tile);
+      self.put_cells(cells);
+    }
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    let length = self.bytes.len() - HEADER_LENGTH;
+    for i in 0..HEADER_LENGTH {
+      self.bytes[i] = (length >> ((HEADER_LENGTH - 1 - i) * 8)) as u8;
+    }
+    self.bytes
+  }
+}
+
+struct Decoder<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    Decoder { bytes }
+  }
+
+  fn take(&mut self, length: usize) -> IoResult<&'a [u8]> {
+    if self.bytes.len() < length {
+      return Err(invalid_data("unexpected end of message"));
+    }
+
+    let (taken, rest) = self.bytes.split_at(length);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  fn get_u8(&mut self) -> IoResult<u8> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn get_u32(&mut self) -> IoResult<u32> {
+    let bytes = self.take(4)?;
+    Ok(
+      bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | u32::from(byte)),
+    )
+  }
+
+  fn get_i64(&mut self) -> IoResult<i64> {
+    let bytes = self.take(8)?;
+    Ok(
+      bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | i64::from(byte)),
+    )
+  }
+
+  fn get_str(&mut self) -> IoResult<&'a str> {
+    let length = self.get_u32()? as usize;
+    let bytes = self.take(length)?;
+    ::std::str::from_utf8(bytes).map_err(invalid_data)
+  }
+
+  fn get_tile(&mut self) -> IoResult<TileCoord> {
+    Ok((self.get_i64()?, self.get_i64()?))
+  }
+
+  fn get_tiles(&mut self) -> IoResult<Vec<TileCoord>> {
+    let count = self.get_u32()? as usize;
+    // every tile takes 16 bytes, this also limits the allocation
+    let mut tiles = Vec::with_capacity(count.min(self.bytes.len() / 16));
+    for _ in 0..count {
+      tiles.push(self.get_tile()?);
+    }
+    Ok(tiles)
+  }
+
+  fn get_cells(&mut self) -> IoResult<Vec<State>> {
+    let length = self.get_u32()? as usize;
+    Ok(self.take(length)?.to_vec())
+  }
+
+  fn get_tile_cells(&mut self) -> IoResult<TileCells> {
+    let count = self.get_u32()? as usize;
+    // every tile takes 20 bytes at least, this also limits the allocation
+    let mut tiles = Vec::with_capacity(count.min(self.bytes.len() / 20));
+    for _ in 0..count {
+      tiles.push((self.get_tile()?, self.get_cells()?));
+    }
+    Ok(tiles)
+  }
+
+  fn finish(self) -> IoResult<()> {
+    if self.bytes.is_empty() {
+      Ok(())
+    } else {
+      Err(invalid_data("unexpected bytes at the end of message"))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(message: Message) {
+    let frame = message.encode();
+    let decoded = Message::read_from(&mut &frame[..]).unwrap();
+    assert_eq!(decoded, message);
+  }
+
+  #[test]
+  fn setup() {
+    for rule in &[
+      "B36/S23",
+      "B2-a/S12/C3",
+      "R5,C0,M1,S34..58,B34..45,NN",
+      "B3/S23:K200*,100",
+    ] {
+      round_trip(Message::Setup {
+        rule: rule.parse().unwrap(),
+      });
+    }
+  }
+
+  #[test]
+  fn assign_tiles() {
+    round_trip(Message::AssignTiles { tiles: vec![] });
+    round_trip(Message::AssignTiles {
+      tiles: vec![(0, 0), (-1, 5), (i64::min_value(), i64::max_value())],
+    });
+  }
+
+  #[test]
+  fn tiles_and_regions() {
+    round_trip(Message::Clear { size: None });
+    round_trip(Message::Clear {
+      size: Some((200, 100)),
+    });
+    round_trip(Message::WriteTile {
+      tile: (-3, 7),
+      cells: vec![0, 1, 2, 0],
+    });
+    round_trip(Message::Step {
+      halos: vec![((0, 0), vec![1; 260]), ((1, -1), vec![])],
+    });
+    round_trip(Message::Borders {
+      borders: vec![((2, 3), vec![0, 1])],
+      freed: vec![(-1, 0)],
+    });
+    round_trip(Message::ReadRegion {
+      x: -10,
+      y: 5,
+      width: 80,
+      height: 24,
+    });
+    round_trip(Message::Region { cells: vec![1, 0] });
+    round_trip(Message::GetStats { bounds: true });
+    round_trip(Message::Stats {
+      population: 5,
+      bounds: Some((-1, -2, 3, 4)),
+    });
+    round_trip(Message::Stats {
+      population: 0,
+      bounds: None,
+    });
+  }
+
+  #[test]
+  fn unknown_tag() {
+    assert!(Message::decode(&[0xff]).is_err());
+  }
+
+  #[test]
+  fn trailing_bytes() {
+    let mut frame = Message::Setup {
+      rule: Rule::default(),
+    }
+    .encode();
+    frame.push(0);
+    assert!(Message::decode(&frame[HEADER_LENGTH..]).is_err());
+  }
+}
@@ -0,0 +1,1117 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use neighborhood::{Mask, Neighborhood, MAX_RANGE};
+use topology::{BoundedGrid, Topology};
+
+/// Conway's Game of Life in the B/S notation.
+pub const CONWAY: &str = "B3/S23";
+
+/// Maximum number of neighbors a cell can have in the Moore neighborhood.
+const MAX_NEIGHBORS: usize = 8;
+
+/// State of a cell. Rules with two states use only [`DEAD`] and [`ALIVE`],
+/// "Generations" rules additionally have dying states `2..states`.
+///
+/// [`DEAD`]: constant.DEAD.html
+/// [`ALIVE`]: constant.ALIVE.html
+pub type State = u8;
+
+pub const DEAD: State = 0;
+pub const ALIVE: State = 1;
+
+/// Bits of a neighborhood configuration. A configuration is a byte in which
+/// the bit of every alive neighbor is set, the neighbors are numbered
+/// clockwise starting from the top one.
+pub mod neighbors {
+  pub const N: u8 = 1 << 0;
+  pub const NE: u8 = 1 << 1;
+  pub const E: u8 = 1 << 2;
+  pub const SE: u8 = 1 << 3;
+  pub const S: u8 = 1 << 4;
+  pub const SW: u8 = 1 << 5;
+  pub const W: u8 = 1 << 6;
+  pub const NW: u8 = 1 << 7;
+}
+
+use self::neighbors::*;
+
+/// Letters of the Hensel notation for every number of alive neighbors, in the
+/// canonical order.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const LETTERS: [&str; MAX_NEIGHBORS + 1] = [
+  "", "ce", "ceaikn", "ceaiknjqry", "ceaiknjqrtwyz", "ceaiknjqry", "ceaikn",
+  "ce", "",
+];
+
+/// A representative configuration for every letter of the Hensel notation
+/// for 1-4 alive neighbors, in the same order as in [`LETTERS`]. All other
+/// configurations of a letter are obtained by rotating and reflecting its
+/// representative, configurations with 5-7 alive neighbors are complements
+/// of the ones with 3-1 alive neighbors and use the same letters.
+///
+/// [`LETTERS`]: constant.LETTERS.html
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const REPRESENTATIVES: [&[u8]; 5] = [
+  &[],
+  // c, e
+  &[NE, N],
+  // c, e, a, i, k, n
+  &[NE | SE, N | E, N | NE, N | S, N | SE, NE | SW],
+  // c, e, a, i, k, n, j, q, r, y
+  &[
+    NE | SE | SW, N | E | S, N | NE | E, NW | N | NE, N | E | SW,
+    N | NE | SE, N | NE | W, N | NE | SW, N | NE | S, N | SE | SW,
+  ],
+  // c, e, a, i, k, n, j, q, r, t, w, y, z
+  &[
+    NE | SE | SW | NW, N | E | S | W, N | NE | E | SE, N | NE | SE | S,
+    N | NE | SE | W, N | NE | SE | NW, N | NE | S | W, N | NE | E | SW,
+    N | NE | E | S, N | NE | S | NW, N | NE | SW | W, N | NE | SE | SW,
+    N | NE | S | SW,
+  ],
+];
+
+/// An isotropic rule on the Moore neighborhood, i.e. a rule which determines
+/// the next state of a cell by its current state and the configuration of its
+/// alive neighbors, treating configurations which differ only by rotation or
+/// reflection equally. This includes all life-like (totalistic) rules, which
+/// only depend on the number of alive neighbors.
+///
+/// Rules are written in the standard notations:
+///
+/// - `B3/S23` (or `B3S23`) - the "B/S" notation, digits after `B` list the
+///   numbers of neighbors which cause a birth, digits after `S` list the
+///   numbers of neighbors which allow a cell to survive;
+/// - `B2-a/S12` - the Hensel notation, letters after a digit select only some
+///   configurations of that number of neighbors, a `-` excludes the listed
+///   configurations instead;
+/// - `23/3` - the "S/B" notation, survival conditions come first;
+/// - `B2/S/C3` or `/2/3` - a "Generations" rule with the given total number
+///   of states;
+/// - `B2/S34H` or `B2/S3V` - a totalistic rule on the hexagonal or the von
+///   Neumann neighborhood (see [`Neighborhood`]);
+/// - `R5,C0,M1,S34..58,B34..45,NM` - a Larger than Life rule, see below.
+///
+/// In "Generations" rules an alive cell which doesn't survive doesn't die
+/// immediately: it passes through all dying states, one per generation, and
+/// only then becomes dead. Dying cells aren't counted as alive neighbors and
+/// can't be born.
+///
+/// Conditions are stored as bitmasks of the letters for every number of
+/// alive neighbors. The rule is evaluated with a lookup table indexed by the
+/// configuration of neighbors and the state of the cell, so the cost per cell
+/// doesn't depend on the rule.
+///
+/// Larger than Life rules are totalistic rules on a neighborhood of a larger
+/// range. `R` is the range (from 1 to [`MAX_RANGE`]), `C` is the number of
+/// states (`C0` and `C2` both mean 2 states), `M1` means that the cell itself
+/// is counted as its own neighbor, `S` and `B` are inclusive ranges of the
+/// numbers of alive neighbors for survival and birth, and `NM`, `NN`, `NH`
+/// select the Moore, the von Neumann or the hexagonal neighborhood, while
+/// `N@` followed by a [`Mask`] selects a custom one. `C`, `M` and `N` are
+/// optional. Backends count alive cells in such neighborhoods themselves and
+/// evaluate the rule with [`next_state_by_count`].
+///
+/// [`Neighborhood`]: ../neighborhood/enum.Neighborhood.html
+/// [`Mask`]: ../neighborhood/struct.Mask.html
+/// [`MAX_RANGE`]: ../neighborhood/constant.MAX_RANGE.html
+/// [`next_state_by_count`]: #method.next_state_by_count
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+  conditions: Conditions,
+  states: u8,
+  grid: Option<BoundedGrid>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Conditions {
+  Isotropic {
+    birth: [u16; MAX_NEIGHBORS + 1],
+    survival: [u16; MAX_NEIGHBORS + 1],
+    neighborhood: Neighborhood,
+    table: [u64; 8],
+  },
+  LargerThanLife {
+    range: u8,
+    neighborhood: Neighborhood,
+    middle: bool,
+    survival: (u16, u16),
+    birth: (u16, u16),
+  },
+}
+
+impl Rule {
+  /// Creates a totalistic rule with two states from the numbers of alive
+  /// neighbors which cause a birth and which allow a cell to survive. Fails
+  /// if any number is larger than 8.
+  pub fn new(birth: &[u8], survival: &[u8]) -> Result<Self, ParseRuleError> {
+    fn to_conditions(counts: &[u8]) -> Option<[u16; MAX_NEIGHBORS + 1]> {
+      let mut conditions = [0; MAX_NEIGHBORS + 1];
+      for &n in counts {
+        let n = n as usize;
+        if n > MAX_NEIGHBORS {
+          return None;
+        }
+        conditions[n] = all_letters(n);
+      }
+      Some(conditions)
+    }
+
+    match (to_conditions(birth), to_conditions(survival)) {
+      (Some(birth), Some(survival)) => Ok(Rule::from_conditions(
+        birth,
+        survival,
+        2,
+        Neighborhood::Moore,
+      )),
+      _ => {
+        let digits = |counts: &[u8]| -> String {
+          counts.iter().map(|n| n.to_string()).collect()
+        };
+        Err(ParseRuleError {
+          rule: format!("B{}/S{}", digits(birth), digits(survival)),
+          reason: format!(
+            "expected numbers of neighbors from 0 to {}",
+            MAX_NEIGHBORS
+          ),
+        })
+      }
+    }
+  }
+
+  /// Builds the lookup table of a rule on a range 1 neighborhood. Rules on
+  /// neighborhoods other than Moore are totalistic, so only neighbors in the
+  /// neighborhood are counted and the letters are ignored.
+  fn from_conditions(
+    birth: [u16; MAX_NEIGHBORS + 1],
+    survival: [u16; MAX_NEIGHBORS + 1],
+    states: u8,
+    neighborhood: Neighborhood,
+  ) -> Self {
+    let mut table = [0; 8];
+    let mask = neighborhood.configuration_mask();
+
+    for (configuration, &(n, letter)) in classify().iter().enumerate() {
+      let (n, letter) = match neighborhood {
+        Neighborhood::Moore => (n, letter),
+        _ => ((configuration as u8 & mask).count_ones() as usize, 0),
+      };
+
+      let bit = 1 << letter;
+      if birth[n] & bit != 0 {
+        set_table_bit(&mut table, configuration);
+      }
+      if survival[n] & bit != 0 {
+        set_table_bit(&mut table, 1 << 8 | configuration);
+      }
+    }
+
+    Rule {
+      conditions: Conditions::Isotropic {
+        birth,
+        survival,
+        neighborhood,
+        table,
+      },
+      states,
+      grid: None,
+    }
+  }
+
+  /// Returns the number of states, which is 2 unless it's a "Generations"
+  /// rule.
+  pub fn states(&self) -> u8 {
+    self.states
+  }
+
+  /// Returns the bounded grid of the rule if it has one. Engines simulate such
+  /// rules in a world of the size of the grid.
+  pub fn grid(&self) -> Option<BoundedGrid> {
+    self.grid
+  }
+
+  /// Returns how the edges of the world are joined, worlds of rules without
+  /// a bounded grid are planes.
+  pub fn topology(&self) -> Topology {
+    self.grid.map_or(Topology::Plane, |grid| grid.topology)
+  }
+
+  /// Returns the range of the neighborhood, which is 1 unless it's a Larger
+  /// than Life rule.
+  pub fn range(&self) -> usize {
+    match self.conditions {
+      Conditions::Isotropic { .. } => 1,
+      Conditions::LargerThanLife { range, .. } => range as usize,
+    }
+  }
+
+  pub fn neighborhood(&self) -> Neighborhood {
+    match self.conditions {
+      Conditions::Isotropic { neighborhood, .. } => neighborhood,
+      Conditions::LargerThanLife { neighborhood, .. } => neighborhood,
+    }
+  }
+
+  /// Returns `true` for Larger than Life rules, which are evaluated with
+  /// [`next_state_by_count`], other rules are evaluated with [`next_state`]
+  /// using the configuration of the range 1 Moore neighborhood (the rule
+  /// itself ignores neighbors outside of its neighborhood).
+  ///
+  /// [`next_state_by_count`]: #method.next_state_by_count
+  /// [`next_state`]: #method.next_state
+  pub fn is_larger_than_life(&self) -> bool {
+    match self.conditions {
+      Conditions::Isotropic { .. } => false,
+      Conditions::LargerThanLife { .. } => true,
+    }
+  }
+
+  /// Returns bitmasks of birth and survival conditions (the bit `n` is set if
+  /// the condition holds for `n` alive neighbors) if the rule is a totalistic
+  /// rule on a range 1 neighborhood. Only neighbors within
+  /// [`neighborhood`] are counted.
+  ///
+  /// [`neighborhood`]: #method.neighborhood
+  pub fn totalistic_masks(&self) -> Option<(u16, u16)> {
+    fn to_mask(conditions: &[u16; MAX_NEIGHBORS + 1]) -> Option<u16> {
+      let mut mask = 0;
+      for (n, &letters) in conditions.iter().enumerate() {
+        if letters == all_letters(n) {
+          mask |= 1 << n;
+        } else if letters != 0 {
+          return None;
+        }
+      }
+      Some(mask)
+    }
+
+    let (birth, survival) = match self.conditions {
+      Conditions::Isotropic {
+        ref birth,
+        ref survival,
+        ..
+      } => (birth, survival),
+      Conditions::LargerThanLife { .. } => return None,
+    };
+
+    match (to_mask(birth), to_mask(survival)) {
+      (Some(birth), Some(survival)) => Some((birth, survival)),
+      _ => None,
+    }
+  }
+
+  /// Computes the next state of a cell with the given configuration of
+  /// alive neighbors (see [`neighbors`]).
+  ///
+  /// Panics if it's a Larger than Life rule.
+  ///
+  /// [`neighbors`]: neighbors/index.html
+  #[inline]
+  pub fn next_cell(&self, cell: bool, neighbors: u8) -> bool {
+    let table = match self.conditions {
+      Conditions::Isotropic { ref table, .. } => table,
+      Conditions::LargerThanLife { .. } => {
+        panic!("{} isn't a rule on the range 1 Moore neighborhood", self)
+      }
+    };
+
+    let index = (cell as usize) << 8 | neighbors as usize;
+    (table[index / 64] >> (index % 64)) & 1 != 0
+  }
+
+  /// Computes the next state of a cell in the given state with the given
+  /// configuration of alive neighbors. Unlike [`next_cell`] this handles
+  /// dying states of "Generations" rules as well.
+  ///
+  /// [`next_cell`]: #method.next_cell
+  #[inline]
+  pub fn next_state(&self, state: State, neighbors: u8) -> State {
+    let lives = match state {
+      DEAD | ALIVE => self.next_cell(state == ALIVE, neighbors),
+      _ => false,
+    };
+    self.advance(state, lives)
+  }
+
+  /// Computes the next state of a cell of a Larger than Life rule. `count`
+  /// is the number of alive cells in the neighborhood of the cell, including
+  /// the cell itself regardless of `M`, which makes it possible to count
+  /// cells with a summed-area table or a sliding window.
+  ///
+  /// Panics if it isn't a Larger than Life rule.
+  #[inline]
+  pub fn next_state_by_count(&self, state: State, count: u32) -> State {
+    let (middle, survival, birth) = match self.conditions {
+      Conditions::LargerThanLife {
+        middle,
+        survival,
+        birth,
+        ..
+      } => (middle, survival, birth),
+      Conditions::Isotropic { .. } => {
+        panic!("{} isn't a Larger than Life rule", self)
+      }
+    };
+
+    let contains = |(min, max): (u16, u16), count: u32| {
+      u32::from(min) <= count && count <= u32::from(max)
+    };
+    let lives = match state {
+      DEAD => contains(birth, count),
+      ALIVE if middle => contains(survival, count),
+      ALIVE => contains(survival, count - 1),
+      _ => false,
+    };
+    self.advance(state, lives)
+  }
+
+  /// Returns the next state of a cell, given whether an alive cell survives
+  /// or a dead cell is born.
+  #[inline]
+  fn advance(&self, state: State, lives: bool) -> State {
+    match state {
+      DEAD => lives as State,
+      ALIVE if lives => ALIVE,
+      _ if state + 1 < self.states => state + 1,
+      _ => DEAD,
+    }
+  }
+}
+
+fn set_table_bit(table: &mut [u64; 8], index: usize) {
+  table[index / 64] |= 1 << (index % 64);
+}
+
+fn all_letters(n: usize) -> u16 {
+  match LETTERS[n].len() {
+    // configurations without letters are stored as a single letter
+    0 => 1,
+    len => (1 << len) - 1,
+  }
+}
+
+/// Finds the number of alive neighbors and the index of the Hensel letter
+/// for every neighborhood configuration.
+fn classify() -> [(usize, u8); 256] {
+  const ALL: u8 = 0xff;
+
+  let mut classes = [(0, 0); 256];
+  classes[ALL as usize] = (MAX_NEIGHBORS, 0);
+
+  for (n, representatives) in REPRESENTATIVES.iter().enumerate() {
+    for (letter, &representative) in representatives.iter().enumerate() {
+      for configuration in symmetries(representative) {
+        let letter = letter as u8;
+        classes[configuration as usize] = (n, letter);
+        // complements of configurations with 4 alive neighbors are covered
+        // by their own representatives
+        if n < MAX_NEIGHBORS / 2 {
+          classes[(ALL ^ configuration) as usize] = (MAX_NEIGHBORS - n, letter);
+        }
+      }
+    }
+  }
+
+  classes
+}
+
+/// Returns all rotations and reflections of a configuration.
+fn symmetries(configuration: u8) -> Vec<u8> {
+  // neighbors are numbered clockwise, so rotating the neighborhood by 90
+  // degrees is a rotation of the bits by 2
+  let reflected = (0..8).fold(0u8, |result, bit| {
+    if configuration & (1 << bit) != 0 {
+      result | 1 << ((8 - bit) % 8)
+    } else {
+      result
+    }
+  });
+
+  (0..4)
+    .flat_map(|rotation| {
+      vec![
+        configuration.rotate_left(rotation * 2),
+        reflected.rotate_left(rotation * 2),
+      ]
+    })
+    .collect()
+}
+
+impl Default for Rule {
+  fn default() -> Self {
+    Rule::new(&[3], &[2, 3]).unwrap()
+  }
+}
+
+impl fmt::Debug for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Rule({})", self)
+  }
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn write_conditions(
+      f: &mut fmt::Formatter,
+      conditions: &[u16; MAX_NEIGHBORS + 1],
+    ) -> fmt::Result {
+      for (n, &letters) in conditions.iter().enumerate() {
+        if letters == 0 {
+          continue;
+        }
+
+        write!(f, "{}", n)?;
+        if letters == all_letters(n) {
+          continue;
+        }
+
+        // use the shorter form
+        let count = letters.count_ones() as usize;
+        let (prefix, listed) = if count * 2 > LETTERS[n].len() {
+          ("-", !letters)
+        } else {
+          ("", letters)
+        };
+
+        write!(f, "{}", prefix)?;
+        for (letter, c) in LETTERS[n].chars().enumerate() {
+          if listed & (1 << letter) != 0 {
+            write!(f, "{}", c)?;
+          }
+        }
+      }
+      Ok(())
+    }
+
+    match self.conditions {
+      Conditions::Isotropic {
+        ref birth,
+        ref survival,
+        neighborhood,
+        ..
+      } => {
+        write!(f, "B")?;
+        write_conditions(f, birth)?;
+        write!(f, "/S")?;
+        write_conditions(f, survival)?;
+        if self.states > 2 {
+          write!(f, "/C{}", self.states)?;
+        }
+        match neighborhood {
+          Neighborhood::VonNeumann => write!(f, "V")?,
+          Neighborhood::Hexagonal => write!(f, "H")?,
+          _ => {}
+        }
+      }
+
+      Conditions::LargerThanLife {
+        range,
+        neighborhood,
+        middle,
+        survival,
+        birth,
+      } => {
+        write!(
+          f,
+          "R{},C{},M{},S{}..{},B{}..{}",
+          range,
+          if self.states > 2 { self.states } else { 0 },
+          middle as u8,
+          survival.0,
+          survival.1,
+          birth.0,
+          birth.1
+        )?;
+        match neighborhood {
+          Neighborhood::Moore => {}
+          Neighborhood::VonNeumann => write!(f, ",NN")?,
+          Neighborhood::Hexagonal => write!(f, ",NH")?,
+          Neighborhood::Custom(mask) => write!(f, ",N@{}", mask)?,
+        }
+      }
+    }
+
+    if let Some(grid) = self.grid {
+      write!(f, ":{}", grid)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Rule {
+  type Err = ParseRuleError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = |reason: &str| ParseRuleError {
+      rule: s.to_owned(),
+      reason: reason.to_owned(),
+    };
+
+    let mut parts = s.trim().splitn(2, ':');
+    let mut rule = parse_unbounded(parts.next().unwrap())
+      .map_err(|reason| error(&reason))?;
+
+    if let Some(grid) = parts.next() {
+      let grid = BoundedGrid::parse(grid).map_err(error)?;
+      if grid.width.min(grid.height) <= rule.range() {
+        return Err(error("a bounded grid must be larger than the range"));
+      }
+      rule.grid = Some(grid);
+    }
+
+    Ok(rule)
+  }
+}
+
+/// Parses a rule without a bounded grid.
+fn parse_unbounded(rule: &str) -> Result<Rule, String> {
+  if rule.starts_with(&['R', 'r'][..]) {
+    return parse_larger_than_life(rule);
+  }
+
+  // a suffix selects a neighborhood other than Moore
+  let (rule, neighborhood) = match rule.chars().last() {
+    Some('H') | Some('h') => (&rule[..rule.len() - 1], Neighborhood::Hexagonal),
+    Some('V') | Some('v') => {
+      (&rule[..rule.len() - 1], Neighborhood::VonNeumann)
+    }
+    _ => (rule, Neighborhood::Moore),
+  };
+
+  let (birth, survival, states) =
+    if !rule.chars().any(|c| c.is_ascii_alphabetic()) {
+      // the S/B notation, optionally followed by the number of states
+      let mut parts = rule.split('/');
+      match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(survival), Some(birth), states, None) => (
+          parse_conditions(birth)?,
+          parse_conditions(survival)?,
+          states.map_or(Ok(2), parse_states)?,
+        ),
+        _ => return Err("expected two or three parts separated by '/'".into()),
+      }
+    } else {
+      parse_birth_survival(rule)?
+    };
+
+  if neighborhood != Neighborhood::Moore {
+    let size = neighborhood.size(1) - 1;
+    for conditions in &[birth, survival] {
+      for (n, &letters) in conditions.iter().enumerate() {
+        if letters != 0 && (n > size || letters != all_letters(n)) {
+          return Err(
+            "rules on hexagonal and von Neumann neighborhoods must be \
+             totalistic and can't have more neighbors than the neighborhood"
+              .into(),
+          );
+        }
+      }
+    }
+  }
+
+  Ok(Rule::from_conditions(birth, survival, states, neighborhood))
+}
+
+/// Parses a rule in the B/S notation into birth and survival conditions and
+/// the number of states.
+fn parse_birth_survival(
+  rule: &str,
+) -> Result<
+  ([u16; MAX_NEIGHBORS + 1], [u16; MAX_NEIGHBORS + 1], u8),
+  &'static str,
+> {
+  // parts are either separated by '/' or just follow each other (as in
+  // "B3S23"), except for the number of states which must be separated by '/'
+  // since 'c' is also a Hensel letter
+  let mut birth = None;
+  let mut survival = None;
+  let mut states = None;
+
+  let mut rest = rule;
+  while !rest.is_empty() {
+    let prefix = rest.chars().next().unwrap();
+    let conditions_start = prefix.len_utf8();
+    let part_end = rest[conditions_start..]
+      .find(|c: char| match c {
+        '/' | 'B' | 'b' | 'S' | 's' => true,
+        _ => false,
+      })
+      .map_or(rest.len(), |index| conditions_start + index);
+
+    let value = &rest[conditions_start..part_end];
+    match prefix.to_ascii_uppercase() {
+      'B' if birth.is_none() => birth = Some(parse_conditions(value)?),
+      'S' if survival.is_none() => survival = Some(parse_conditions(value)?),
+      'C' if states.is_none() => states = Some(parse_states(value)?),
+      'B' | 'S' | 'C' => return Err("duplicate part"),
+      _ => return Err("expected 'B', 'S' or 'C'"),
+    }
+
+    rest = &rest[part_end..];
+    if rest.starts_with('/') {
+      rest = &rest[1..];
+    }
+  }
+
+  match (birth, survival) {
+    (Some(birth), Some(survival)) => Ok((birth, survival, states.unwrap_or(2))),
+    _ => Err("both 'B' and 'S' conditions are required"),
+  }
+}
+
+/// Parses conditions like `2-a3` (digits, each optionally followed by Hensel
+/// letters) into bitmasks of letters for every number of alive neighbors.
+fn parse_conditions(s: &str) -> Result<[u16; MAX_NEIGHBORS + 1], &'static str> {
+  let mut conditions = [0; MAX_NEIGHBORS + 1];
+  let mut chars = s.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    let n = match c.to_digit(10) {
+      Some(n) if n as usize <= MAX_NEIGHBORS => n as usize,
+      _ => return Err("expected numbers of neighbors from 0 to 8"),
+    };
+
+    let negated = chars.peek() == Some(&'-');
+    if negated {
+      chars.next();
+    }
+
+    let mut letters = 0;
+    while let Some(&c) = chars.peek() {
+      if !c.is_ascii_alphabetic() {
+        break;
+      }
+      chars.next();
+
+      match LETTERS[n].find(c) {
+        Some(letter) => letters |= 1 << letter,
+        None => return Err("invalid Hensel letter"),
+      }
+    }
+
+    conditions[n] |= if letters == 0 {
+      if negated {
+        return Err("expected Hensel letters after '-'");
+      }
+      all_letters(n)
+    } else if negated {
+      all_letters(n) & !letters
+    } else {
+      letters
+    };
+  }
+
+  Ok(conditions)
+}
+
+/// Parses a Larger than Life rule like `R5,C0,M1,S34..58,B34..45,NM`.
+fn parse_larger_than_life(s: &str) -> Result<Rule, String> {
+  fn parse_number<T: FromStr>(s: &str) -> Result<T, &'static str> {
+    s.parse().map_err(|_| "expected a number")
+  }
+
+  fn parse_interval(s: &str) -> Result<(u16, u16), &'static str> {
+    let mut bounds = s.splitn(2, "..");
+    match (bounds.next(), bounds.next()) {
+      (Some(min), Some(max)) => Ok((parse_number(min)?, parse_number(max)?)),
+      _ => Err("expected a range of numbers like 34..58"),
+    }
+  }
+
+  let mut range = None;
+  let mut states = 2;
+  let mut middle = false;
+  let mut survival = None;
+  let mut birth = None;
+  let mut neighborhood = None;
+
+  for part in s.split(',') {
+    let mut chars = part.trim().chars();
+    let prefix = chars.next().map(|c| c.to_ascii_uppercase());
+    let value = chars.as_str();
+
+    match prefix {
+      Some('R') => range = Some(parse_number::<usize>(value)?),
+      Some('C') => {
+        states = match parse_number::<u8>(value)? {
+          0 => 2,
+          1 => return Err("expected number of states from 2 to 255".into()),
+          states => states,
+        }
+      }
+      Some('M') => {
+        middle = match value {
+          "0" => false,
+          "1" => true,
+          _ => return Err("expected M0 or M1".into()),
+        }
+      }
+      Some('S') => survival = Some(parse_interval(value)?),
+      Some('B') => birth = Some(parse_interval(value)?),
+      // masks depend on the range, so they're parsed later
+      Some('N') => neighborhood = Some(value),
+      _ => return Err("expected 'R', 'C', 'M', 'S', 'B' or 'N'".into()),
+    }
+  }
+
+  let (range, survival, birth) = match (range, survival, birth) {
+    (Some(range), Some(survival), Some(birth)) => (range, survival, birth),
+    _ => return Err("'R', 'S' and 'B' are required".into()),
+  };
+
+  if range < 1 || range > MAX_RANGE {
+    return Err(format!("expected range from 1 to {}", MAX_RANGE));
+  }
+
+  let neighborhood = match neighborhood {
+    None | Some("M") | Some("m") => Neighborhood::Moore,
+    Some("N") | Some("n") => Neighborhood::VonNeumann,
+    Some("H") | Some("h") => Neighborhood::Hexagonal,
+    Some(value) if value.starts_with('@') => {
+      Neighborhood::Custom(Mask::parse(&value[1..], range)?)
+    }
+    Some(_) => {
+      return Err("expected NM, NN, NH or N@ followed by a mask".into())
+    }
+  };
+
+  let size = neighborhood.size(range) as u16;
+  for &(min, max) in &[survival, birth] {
+    if min > max || max > size {
+      return Err("a range of neighbors is empty or too large".into());
+    }
+  }
+
+  Ok(Rule {
+    conditions: Conditions::LargerThanLife {
+      range: range as u8,
+      neighborhood,
+      middle,
+      survival,
+      birth,
+    },
+    states,
+    grid: None,
+  })
+}
+
+fn parse_states(s: &str) -> Result<u8, &'static str> {
+  match s.parse() {
+    Ok(states) if states >= 2 => Ok(states),
+    _ => Err("expected number of states from 2 to 255"),
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRuleError {
+  rule: String,
+  reason: String,
+}
+
+impl fmt::Display for ParseRuleError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid rule '{}': {}", self.rule, self.reason)
+  }
+}
+
+impl Error for ParseRuleError {
+  fn description(&self) -> &str {
+    &self.reason
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(s: &str) -> Rule {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn conway() {
+    let rule = parse(CONWAY);
+    assert_eq!(rule, Rule::default());
+    assert_eq!(rule.totalistic_masks(), Some((0b1000, 0b1100)));
+
+    assert!(rule.next_cell(false, N | E | S));
+    assert!(!rule.next_cell(false, N | S));
+    assert!(rule.next_cell(true, NW | SE));
+    assert!(rule.next_cell(true, N | NE | NW));
+    assert!(!rule.next_cell(true, N | E | S | W));
+  }
+
+  #[test]
+  fn notations() {
+    let high_life = Rule::new(&[3, 6], &[2, 3]).unwrap();
+    assert_eq!(parse("B36/S23"), high_life);
+    assert_eq!(parse("b36/s23"), high_life);
+    assert_eq!(parse("B36S23"), high_life);
+    assert_eq!(parse("S23/B36"), high_life);
+    assert_eq!(parse("23/36"), high_life);
+    assert_eq!(parse("B/S"), Rule::new(&[], &[]).unwrap());
+    assert_eq!(parse("/3"), Rule::new(&[3], &[]).unwrap());
+  }
+
+  #[test]
+  fn display() {
+    assert_eq!(parse("23/36").to_string(), "B36/S23");
+    assert_eq!(parse("B0/S8").to_string(), "B0/S8");
+    assert_eq!(parse("B/S").to_string(), "B/S");
+    assert_eq!(parse("B2-a/S12").to_string(), "B2-a/S12");
+    assert_eq!(parse("B2cekin/S1ce2").to_string(), "B2-a/S12");
+    assert_eq!(parse("B3/S2-i34q").to_string(), "B3/S2-i34q");
+    assert_eq!(parse("B3ai4-ceikn/S").to_string(), "B3ai4-ceikn/S");
+  }
+
+  #[test]
+  fn invalid_rules() {
+    for rule in &[
+      "",
+      "B3",
+      "B39/S23",
+      "B3/S23/X",
+      "B3/B3/S23",
+      "3/2/1",
+      "3/2/3/4",
+      "B2/S/C",
+      "B2/S/C3/C3",
+      "R0,S1..2,B1..2",
+      "R11,S1..2,B1..2",
+      "R1,S1..2",
+      "R1,S3..2,B1..2",
+      "R1,S1..10,B1..2",
+      "R1,S1..2,B1..2,NX",
+      "R1,C1,S1..2,B1..2",
+      "R1,S1,B1..2",
+      "R1,S1..2,B1..2,N@5d",
+      "B2a/S34H",
+      "B7/S34H",
+      "B5/S3V",
+      "B3/S23:",
+      "B3/S23:X10,10",
+      "B3/S23:T1,10",
+      "R5,S1..2,B1..2:T5,10",
+      "a",
+      "Ä3",
+      "B1a/S",
+      "B2-/S",
+      "B3x/S23",
+    ] {
+      assert!(rule.parse::<Rule>().is_err(), "{:?} was parsed", rule);
+    }
+
+    let error = Rule::new(&[3, 9], &[2]).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "invalid rule 'B39/S2': expected numbers of neighbors from 0 to 8"
+    );
+    let error = "R11,S1..2,B1..2".parse::<Rule>().unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!(
+        "invalid rule 'R11,S1..2,B1..2': expected range from 1 to {}",
+        MAX_RANGE
+      )
+    );
+  }
+
+  #[test]
+  fn every_configuration_is_classified() {
+    let mut letter_counts = [[0; 13]; MAX_NEIGHBORS + 1];
+    for (configuration, &(n, letter)) in classify().iter().enumerate() {
+      assert_eq!((configuration as u8).count_ones() as usize, n);
+      letter_counts[n][letter as usize] += 1;
+    }
+
+    for n in 0..=MAX_NEIGHBORS {
+      let letters = LETTERS[n].len().max(1);
+      let total: usize = letter_counts[n].iter().sum();
+      assert!(letter_counts[n][..letters].iter().all(|&count| count > 0));
+      assert!(letter_counts[n][letters..].iter().all(|&count| count == 0));
+      assert_eq!(total, binomial(8, n));
+    }
+  }
+
+  fn binomial(n: usize, k: usize) -> usize {
+    (0..k).fold(1, |result, i| result * (n - i) / (i + 1))
+  }
+
+  /// A configuration of every letter as drawn in Golly's tables of the
+  /// Hensel notation, `x` is the cell itself.
+  #[cfg_attr(rustfmt, rustfmt_skip)]
+  const GOLLY_LETTERS: [(&str, [&str; 3]); 31] = [
+    ("1c", ["o..", ".x.", "..."]),
+    ("1e", [".o.", ".x.", "..."]),
+    ("2c", ["o.o", ".x.", "..."]),
+    ("2e", [".o.", "ox.", "..."]),
+    ("2a", ["oo.", ".x.", "..."]),
+    ("2i", ["...", "oxo", "..."]),
+    ("2k", ["o..", ".xo", "..."]),
+    ("2n", ["..o", ".x.", "o.."]),
+    ("3c", ["o.o", ".x.", "o.."]),
+    ("3e", [".o.", "oxo", "..."]),
+    ("3a", ["oo.", "ox.", "..."]),
+    ("3i", ["ooo", ".x.", "..."]),
+    ("3k", [".o.", ".xo", "o.."]),
+    ("3n", ["o.o", "ox.", "..."]),
+    ("3j", [".oo", "ox.", "..."]),
+    ("3q", [".oo", ".x.", "o.."]),
+    ("3r", ["o..", "oxo", "..."]),
+    ("3y", ["o..", ".xo", "o.."]),
+    ("4c", ["o.o", ".x.", "o.o"]),
+    ("4e", [".o.", "oxo", ".o."]),
+    ("4a", ["ooo", "ox.", "..."]),
+    ("4i", ["o.o", "oxo", "..."]),
+    ("4k", ["oo.", ".xo", "o.."]),
+    ("4n", ["ooo", ".x.", "o.."]),
+    ("4j", [".o.", "oxo", "o.."]),
+    ("4q", [".oo", ".xo", "o.."]),
+    ("4r", ["oo.", "oxo", "..."]),
+    ("4y", ["o.o", ".xo", "o.."]),
+    ("4t", ["o..", "oxo", "o.."]),
+    ("4w", [".oo", "ox.", "o.."]),
+    ("4z", ["..o", "oxo", "o.."]),
+  ];
+
+  #[test]
+  fn letters_match_golly() {
+    let classes = classify();
+    for &(name, ref rows) in GOLLY_LETTERS.iter() {
+      let positions = [NW, N, NE, W, 0, E, SW, S, SE];
+      let configuration = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .zip(positions.iter())
+        .filter(|&(c, _)| c == 'o')
+        .fold(0, |configuration, (_, &bit)| configuration | bit);
+
+      let letter_name = |(n, letter): (usize, u8)| {
+        let letter = LETTERS[n].chars().nth(letter as usize).unwrap();
+        format!("{}{}", n, letter)
+      };
+      assert_eq!(letter_name(classes[configuration as usize]), name);
+
+      // complements of 1-3 alive neighbors have the same letter
+      let (n, letter) = classes[configuration as usize];
+      if n < MAX_NEIGHBORS / 2 {
+        let complement = classes[!configuration as usize];
+        assert_eq!(complement, (MAX_NEIGHBORS - n, letter), "{}", name);
+      }
+    }
+  }
+
+  #[test]
+  fn generations() {
+    let brians_brain = parse("B2/S/C3");
+    assert_eq!(brians_brain.states(), 3);
+    assert_eq!(brians_brain.totalistic_masks(), Some((0b100, 0)));
+    assert_eq!(parse("b2/s/c3"), brians_brain);
+    assert_eq!(parse("/2/3"), brians_brain);
+    assert_eq!(brians_brain.to_string(), "B2/S/C3");
+    assert_eq!(parse("345/2/4").to_string(), "B2/S345/C4");
+    assert_eq!(parse("B3/S23/C2"), Rule::default());
+
+    let star_wars = parse("345/2/4");
+    assert_eq!(star_wars.states(), 4);
+    assert_eq!(star_wars.next_state(DEAD, N | S), ALIVE);
+    assert_eq!(star_wars.next_state(ALIVE, N | E | S), ALIVE);
+    assert_eq!(star_wars.next_state(ALIVE, N), 2);
+    assert_eq!(star_wars.next_state(2, N | E | S), 3);
+    assert_eq!(star_wars.next_state(3, N | S), DEAD);
+
+    assert_eq!(Rule::default().next_state(ALIVE, N), DEAD);
+  }
+
+  #[test]
+  fn larger_than_life() {
+    let bosco = parse("R5,C0,M1,S34..58,B34..45,NM");
+    assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45");
+    assert_eq!(parse("r5,c2,m1,s34..58,b34..45"), bosco);
+    assert_eq!(bosco.range(), 5);
+    assert_eq!(bosco.neighborhood(), Neighborhood::Moore);
+    assert!(bosco.is_larger_than_life());
+    assert_eq!(bosco.totalistic_masks(), None);
+
+    assert_eq!(bosco.next_state_by_count(DEAD, 33), DEAD);
+    assert_eq!(bosco.next_state_by_count(DEAD, 34), ALIVE);
+    assert_eq!(bosco.next_state_by_count(DEAD, 46), DEAD);
+    assert_eq!(bosco.next_state_by_count(ALIVE, 58), ALIVE);
+    assert_eq!(bosco.next_state_by_count(ALIVE, 59), DEAD);
+
+    // without M1 the cell itself isn't counted
+    let rule = parse("R2,C3,S2..3,B3..3,NN");
+    assert_eq!(rule.to_string(), "R2,C3,M0,S2..3,B3..3,NN");
+    assert_eq!(rule.neighborhood(), Neighborhood::VonNeumann);
+    assert_eq!(rule.next_state_by_count(ALIVE, 4), ALIVE);
+    assert_eq!(rule.next_state_by_count(ALIVE, 5), 2);
+    assert_eq!(rule.next_state_by_count(2, 3), DEAD);
+
+    let rule = parse("R1,C0,M0,S2..3,B2..2,N@5d0");
+    assert_eq!(rule.to_string(), "R1,C0,M0,S2..3,B2..2,N@5d0");
+    assert_eq!(
+      parse("r2,s2..3,b3..3,nh").to_string(),
+      "R2,C0,M0,S2..3,B3..3,NH"
+    );
+
+    assert_eq!(parse("B3/S23").range(), 1);
+    assert!(!parse("B3/S23").is_larger_than_life());
+  }
+
+  #[test]
+  fn neighborhoods() {
+    let hex = parse("B2/S34H");
+    assert_eq!(hex.to_string(), "B2/S34H");
+    assert_eq!(hex.neighborhood(), Neighborhood::Hexagonal);
+    assert_eq!(parse("34/2h"), hex);
+    assert_eq!(hex.totalistic_masks(), Some((0b100, 0b11000)));
+    // NE and SW aren't neighbors on the hexagonal grid
+    assert!(hex.next_cell(false, N | S));
+    assert!(!hex.next_cell(false, N | NE | SW));
+
+    let von_neumann = parse("B1/S/C3V");
+    assert_eq!(von_neumann.to_string(), "B1/S/C3V");
+    assert_eq!(von_neumann.states(), 3);
+    assert!(von_neumann.next_cell(false, N | NE | SE | SW | NW));
+    assert!(!von_neumann.next_cell(false, N | E));
+  }
+
+  #[test]
+  fn bounded_grids() {
+    let rule = parse("B3/S23:T200,100");
+    assert_eq!(rule.to_string(), "B3/S23:T200,100");
+    assert_eq!(rule.topology(), Topology::Torus);
+    assert_eq!(
+      rule.grid().map(|grid| (grid.width, grid.height)),
+      Some((200, 100))
+    );
+    assert_eq!(parse("34/2/3H:S50").to_string(), "B2/S34/C3H:S50");
+    assert_eq!(
+      parse("R5,C0,M1,S34..58,B34..45:K100*,50").to_string(),
+      "R5,C0,M1,S34..58,B34..45:K100*,50"
+    );
+
+    assert_eq!(parse("B3/S23").grid(), None);
+    assert_eq!(parse("B3/S23").topology(), Topology::Plane);
+  }
+
+  #[test]
+  fn non_totalistic() {
+    let rule = parse("B2-a/S12");
+    assert_eq!(rule.totalistic_masks(), None);
+
+    // 2a: the neighbors are adjacent
+    assert!(!rule.next_cell(false, N | NE));
+    assert!(!rule.next_cell(false, E | SE));
+    assert!(!rule.next_cell(false, SW | W));
+    // 2i, 2n: the neighbors are on the opposite sides
+    assert!(rule.next_cell(false, N | S));
+    assert!(rule.next_cell(false, NE | SW));
+
+    assert!(rule.next_cell(true, NE));
+    assert!(rule.next_cell(true, N | NE));
+    assert!(!rule.next_cell(true, N | NE | E));
+  }
+}
@@ -0,0 +1,317 @@
+extern crate rayon;
+use self::rayon::prelude::*;
+
+use std::collections::HashMap;
+use std::mem;
+
+use failure::{err_msg, Error};
+
+use super::Tiles;
+use protocol::{Stats, TileCells};
+use rule::{Rule, State, ALIVE, DEAD};
+use threaded::world::{compute_next_generation, Cells, Sector, World};
+use tiling::{check_halos, copy_region, not_assigned, union, TileRect};
+use unbounded::world::TileCoord;
+
+/// The tiles of a slave which are computed on the CPU, every tile on a
+/// thread of its own.
+pub struct HostTiles {
+  rule: Rule,
+  /// The size of a bounded world.
+  size: Option<(usize, usize)>,
+  tiles: HashMap<TileCoord, HostTile>,
+}
+
+/// A tile and the world its next generation is computed into.
+struct HostTile {
+  rect: TileRect,
+  cells: World,
+  next: World,
+  /// See [`TileRect::border`](../../tiling/struct.TileRect.html#method.border).
+  border: Vec<(usize, usize)>,
+}
+
+impl HostTile {
+  fn new(rect: TileRect, rule: &Rule) -> Self {
+    let mut cells = World::new(rect.width, rect.height);
+    if rule.states() > 2 {
+      cells.allocate_dying_cells();
+    }
+
+    HostTile {
+      rect,
+      next: cells.clone(),
+      cells,
+      border: rect.border(rule.range()),
+    }
+  }
+
+  /// Computes the next generation of the tile and returns its border.
+  fn step(&mut self, rule: &Rule, halo: &[State]) -> Vec<State> {
+    {
+      let padded = Padded {
+        rect: self.rect,
+        range: rule.range(),
+        cells: &self.cells,
+        halo,
+      };
+      let sectors = [Sector::new(0, 0, self.rect.width, self.rect.height)];
+      let mut destination = self.next.split_mut(&sectors).pop().unwrap();
+      compute_next_generation(
+        &padded,
+        &sectors[0],
+        rule,
+        &mut destination,
+        0,
+        0,
+      );
+    }
+    mem::swap(&mut self.cells, &mut self.next);
+
+    self
+      .border
+      .iter()
+      .map(|&(x, y)| self.cells.get(x, y))
+      .collect()
+  }
+}
+
+/// A tile padded with its halo.
+struct Padded<'a> {
+  rect: TileRect,
+  range: usize,
+  cells: &'a World,
+  halo: &'a [State],
+}
+
+impl<'a> Cells for Padded<'a> {
+  fn cell(&self, x: isize, y: isize) -> State {
+    if self.rect.contains(x, y) {
+      self.cells.get(x as usize, y as usize)
+    } else {
+      self
+        .rect
+        .halo_index(self.range, x, y)
+        .map_or(DEAD, |index| self.halo[index])
+    }
+  }
+
+  fn row<'b>(
+    &'b self,
+    y: isize,
+    words: &'b mut [u64; 3],
+  ) -> (&'b [u64], isize) {
+    // a tile is a single word wide, the words start a word to the left of it
+    *words = [0; 3];
+    let inside = y >= 0 && y < self.rect.height as isize;
+    if inside {
+      words[1] = self.cells.row(y).unwrap()[0];
+    }
+
+    let (width, range) = (self.rect.width as isize, self.range as isize);
+    for x in -range..width + range {
+      if inside && x >= 0 && x < width {
+        continue;
+      }
+      if self.cell(x, y) == ALIVE {
+        let bit = (x + 64) as usize;
+        words[bit / 64] |= 1 << (bit % 64);
+      }
+    }
+    (words, -64)
+  }
+
+  fn has_dying_cells(&self) -> bool {
+    self.cells.has_dying_cells()
+  }
+}
+
+impl HostTiles {
+  pub fn new(rule: Rule) -> Self {
+    HostTiles {
+      rule,
+      size: None,
+      tiles: HashMap::new(),
+    }
+  }
+}
+
+impl Tiles for HostTiles {
+  fn assign(&mut self, tiles: &[TileCoord]) -> Result<(), Error> {
+    for &tile in tiles {
+      let (rule, size) = (&self.rule, self.size);
+      self
+        .tiles
+        .entry(tile)
+        .or_insert_with(|| HostTile::new(TileRect::of(tile, size), rule));
+    }
+    Ok(())
+  }
+
+  fn clear(&mut self, size: Option<(usize, usize)>) {
+    self.size = size;
+    self.tiles.clear();
+  }
+
+  fn count(&self) -> usize {
+    self.tiles.len()
+  }
+
+  fn write(&mut self, tile: TileCoord, cells: &[State]) -> Result<(), Error> {
+    let tile = self
+      .tiles
+      .get_mut(&tile)
+      .ok_or_else(|| not_assigned(tile))?;
+    let (width, height) = (tile.rect.width, tile.rect.height);
+    if cells.len() != width * height {
+      return Err(err_msg(format!(
+        "a tile has {} cells, not {}",
+        width * height,
+        cells.len()
+      )));
+    }
+
+    for y in 0..height {
+      for x in 0..width {
+        tile.cells.set(x, y, cells[x + y * width]);
+      }
+    }
+    Ok(())
+  }
+
+  fn step(
+    &mut self,
+    halos: TileCells,
+  ) -> Result<(TileCells, Vec<TileCoord>), Error> {
+    let range = self.rule.range();
+    let halos = check_halos(
+      halos,
+      self
+        .tiles
+        .iter()
+        .map(|(&coord, tile)| (coord, tile.rect.halo(range).len())),
+    )?;
+
+    let rule = &self.rule;
+    let borders: TileCells = self
+      .tiles
+      .par_iter_mut()
+      .map(|(&coord, tile)| (coord, tile.step(rule, &halos[&coord])))
+      .collect();
+
+    let mut freed = Vec::new();
+    let mut kept = Vec::with_capacity(borders.len());
+    for (coord, border) in borders {
+      if self.size.is_none() && self.tiles[&coord].cells.bounds().is_none() {
+        self.tiles.remove(&coord);
+        freed.push(coord);
+      } else {
+        kept.push((coord, border));
+      }
+    }
+    Ok((kept, freed))
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let mut cells = vec![DEAD; width * height];
+    for tile in self.tiles.values() {
+      copy_region(&mut cells, (x, y, width, height), tile.rect, |x, y| {
+        tile.cells.get(x, y)
+      });
+    }
+    Ok(cells)
+  }
+
+  fn stats(&self, with_bounds: bool) -> Result<Stats, Error> {
+    let population = self
+      .tiles
+      .values()
+      .map(|tile| tile.cells.population() as u64)
+      .sum();
+
+    let mut bounds = None;
+    if with_bounds {
+      for tile in self.tiles.values() {
+        let rect = tile.rect;
+        bounds = union(
+          bounds,
+          tile.cells.bounds().map(|(x0, y0, x1, y1)| {
+            (
+              rect.x + x0 as i64,
+              rect.y + y0 as i64,
+              rect.x + x1 as i64,
+              rect.y + y1 as i64,
+            )
+          }),
+        );
+      }
+    }
+    Ok((population, bounds))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use unbounded::world::TiledWorld;
+
+  #[test]
+  fn tiles_match_unbounded_world() {
+    let rule: Rule = "B36/S23".parse().unwrap();
+    let range = rule.range();
+    let mut tiles = HostTiles::new(rule);
+    tiles.assign(&[(0, 0), (1, 0)]).unwrap();
+
+    // a glider crossing from the first tile into the second one
+    let mut world = TiledWorld::new();
+    let mut cells = vec![DEAD; 64 * 64];
+    for &(x, y) in &[(62, 10), (63, 11), (61, 12), (62, 12), (63, 12)] {
+      world.set(x, y, ALIVE);
+      cells[x as usize + y as usize * 64] = ALIVE;
+    }
+    tiles.write((0, 0), &cells).unwrap();
+
+    for _ in 0..16 {
+      // the second tile is freed until the glider reaches it, the master
+      // would create it again
+      tiles.assign(&[(1, 0)]).unwrap();
+
+      // halos are taken from the world, like the master does from borders
+      let halos = [(0, 0), (1, 0)]
+        .iter()
+        .map(|&coord| {
+          let rect = TileRect::of(coord, None);
+          let halo = rect
+            .halo(range)
+            .into_iter()
+            .map(|(x, y)| world.get(rect.x + x as i64, rect.y + y as i64))
+            .collect();
+          (coord, halo)
+        })
+        .collect();
+      let count = tiles.count();
+      let (borders, freed) = tiles.step(halos).unwrap();
+      world.next_generation(&rule);
+      assert_eq!(borders.len() + freed.len(), count);
+
+      assert_eq!(tiles.stats(true).unwrap(), (5, world.bounds()));
+    }
+
+    // the glider has left the first tile
+    assert_eq!(tiles.count(), 1);
+    let region = tiles.read_region(60, 10, 10, 8).unwrap();
+    for (index, &cell) in region.iter().enumerate() {
+      let (x, y) = (60 + index as i64 % 10, 10 + index as i64 / 10);
+      assert_eq!(cell, world.get(x, y), "({}, {})", x, y);
+    }
+
+    assert!(tiles.step(vec![((0, 0), vec![])]).is_err());
+    assert!(tiles.write((2, 0), &cells).is_err());
+  }
+}
@@ -1,5 +1,186 @@
-use std::io::Result as IoResult;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
 
-pub fn connect(_hostname: String, _port: u16) -> IoResult<()> {
-  Ok(())
+use failure::{err_msg, Error};
+
+use gpu::device::{self, DeviceSelection};
+use gpu::tiles::DeviceTiles;
+use protocol::{Message, Stats, TileCells};
+use rule::{Rule, State};
+use unbounded::world::TileCoord;
+use utils::result::DescribeErr;
+
+pub mod host;
+use self::host::HostTiles;
+
+/// Where a slave keeps the tiles assigned to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+  Cpu,
+  /// Tiles stay in buffers of the selected OpenCL device.
+  Gpu(DeviceSelection),
+}
+
+/// The tiles of a slave, see [`Message`] for what the methods do.
+///
+/// [`Message`]: ../protocol/enum.Message.html
+pub trait Tiles {
+  /// Adds dead tiles, tiles which are already assigned are kept as they are.
+  fn assign(&mut self, tiles: &[TileCoord]) -> Result<(), Error>;
+
+  /// Drops every tile, the next ones are clipped to `size` if the world is
+  /// bounded.
+  fn clear(&mut self, size: Option<(usize, usize)>);
+
+  /// Returns the number of assigned tiles.
+  fn count(&self) -> usize;
+
+  /// Replaces the cells of an assigned tile, row by row.
+  fn write(&mut self, tile: TileCoord, cells: &[State]) -> Result<(), Error>;
+
+  /// Computes the next generation of every tile from their `halos` and
+  /// returns their borders. Tiles of unbounded worlds whose cells are all
+  /// dead are freed and returned instead of their borders.
+  fn step(
+    &mut self,
+    halos: TileCells,
+  ) -> Result<(TileCells, Vec<TileCoord>), Error>;
+
+  /// Returns the cells of a region, cells of other slaves are dead.
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error>;
+
+  /// Returns the number of alive cells and, if `bounds` is set, the bounds
+  /// of the cells which aren't dead.
+  fn stats(&self, bounds: bool) -> Result<Stats, Error>;
+}
+
+/// Creates the tiles of `backend`. Without any OpenCL platform GPU slaves
+/// keep their tiles on the CPU.
+fn create_tiles(
+  rule: Rule,
+  backend: &Backend,
+) -> Result<Box<dyn Tiles>, Error> {
+  Ok(match *backend {
+    Backend::Cpu => Box::new(HostTiles::new(rule)),
+    Backend::Gpu(ref selection) => {
+      if device::is_available() {
+        Box::new(DeviceTiles::new(rule, selection)?)
+      } else {
+        warn!(
+          target: "slave",
+          "there's no OpenCL platform, keeping tiles on the CPU"
+        );
+        Box::new(HostTiles::new(rule))
+      }
+    }
+  })
+}
+
+/// Handles a message of the master and returns the reply, if there's any.
+fn handle(
+  tiles: &mut dyn Tiles,
+  message: Message,
+) -> Result<Option<Message>, Error> {
+  Ok(match message {
+    Message::Setup { .. } => unreachable!(),
+    Message::AssignTiles { tiles: assigned } => {
+      tiles.assign(&assigned)?;
+      info!(
+        target: "slave",
+        "{} tiles have been assigned, {} in total",
+        assigned.len(),
+        tiles.count(),
+      );
+      None
+    }
+    Message::Clear { size } => {
+      tiles.clear(size);
+      None
+    }
+    Message::WriteTile { tile, cells } => {
+      tiles.write(tile, &cells)?;
+      None
+    }
+    Message::Step { halos } => {
+      let (borders, freed) = tiles.step(halos)?;
+      if !freed.is_empty() {
+        debug!(target: "slave", "{} tiles have been freed", freed.len());
+      }
+      Some(Message::Borders { borders, freed })
+    }
+    Message::ReadRegion {
+      x,
+      y,
+      width,
+      height,
+    } => Some(Message::Region {
+      cells: tiles.read_region(x, y, width, height)?,
+    }),
+    Message::GetStats { bounds } => {
+      let (population, bounds) = tiles.stats(bounds)?;
+      Some(Message::Stats { population, bounds })
+    }
+    Message::Borders { .. }
+    | Message::Region { .. }
+    | Message::Stats { .. } => {
+      return Err(err_msg("slaves don't accept replies"))
+    }
+  })
+}
+
+pub fn connect(
+  hostname: String,
+  port: u16,
+  backend: Backend,
+) -> Result<(), Error> {
+  info!(target: "slave", "connecting to {}:{}", hostname, port);
+  let socket = TcpStream::connect((hostname.as_str(), port))
+    .describe_err("can't connect to master")?;
+  socket.set_nodelay(true)?;
+  info!(target: "slave", "connected to {}:{}", hostname, port);
+
+  serve(socket, &backend)
+}
+
+/// Computes the tiles the master assigns through `stream` until the master
+/// disconnects.
+pub fn serve<S: Read + Write>(
+  mut stream: S,
+  backend: &Backend,
+) -> Result<(), Error> {
+  // tiles are created once the master has sent the rule
+  let mut tiles = None;
+
+  loop {
+    let message = match Message::read_from(&mut stream) {
+      Ok(message) => message,
+      Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => {
+        info!(target: "slave", "master has disconnected");
+        return Ok(());
+      }
+      Err(error) => Err(error).describe_err("can't read message")?,
+    };
+    trace!(target: "slave", "message = {:?}", message);
+
+    if let Message::Setup { rule } = message {
+      info!(target: "slave", "rule = {}", rule);
+      tiles = Some(create_tiles(rule, backend)?);
+      continue;
+    }
+
+    let tiles = tiles
+      .as_mut()
+      .ok_or_else(|| err_msg("the master hasn't sent the setup"))?;
+    if let Some(reply) = handle(&mut **tiles, message)? {
+      reply
+        .write_to(&mut stream)
+        .describe_err("can't send reply")?;
+    }
+  }
 }
@@ -1,416 +1,328 @@
-extern crate crossbeam;
+extern crate num_cpus;
+extern crate rayon;
+use self::rayon::prelude::*;
 
-use std::ptr;
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::mem;
 
 extern crate rand;
-use self::rand::Rng;
-
-mod world;
-use self::world::{Sector, World};
-
-pub fn run() {
-  let mut world = create_world();
-
-  // let n = 61;
-
-  // {
-  //   macro_rules! cell {
-  //     ($x:expr, $y:expr) => {
-  //       world.set($x + n, $y, true);
-  //     };
-  //   }
-
-  //   cell!(25, 1);
-
-  //   cell!(23, 2);
-  //   cell!(25, 2);
-
-  //   cell!(13, 3);
-  //   cell!(14, 3);
-  //   cell!(21, 3);
-  //   cell!(22, 3);
-  //   cell!(35, 3);
-  //   cell!(36, 3);
-
-  //   cell!(12, 4);
-  //   cell!(16, 4);
-  //   cell!(21, 4);
-  //   cell!(22, 4);
-  //   cell!(35, 4);
-  //   cell!(36, 4);
-
-  //   cell!(1, 5);
-  //   cell!(2, 5);
-  //   cell!(11, 5);
-  //   cell!(17, 5);
-  //   cell!(21, 5);
-  //   cell!(22, 5);
-
-  //   cell!(1, 6);
-  //   cell!(2, 6);
-  //   cell!(11, 6);
-  //   cell!(15, 6);
-  //   cell!(17, 6);
-  //   cell!(18, 6);
-  //   cell!(23, 6);
-  //   cell!(25, 6);
-
-  //   cell!(11, 7);
-  //   cell!(17, 7);
-  //   cell!(25, 7);
-
-  //   cell!(12, 8);
-  //   cell!(16, 8);
-
-  //   cell!(13, 9);
-  //   cell!(14, 9);
-  // }
-
-  // {
-  //   macro_rules! cell {
-  //     ($x:expr, $y:expr) => {
-  //       world.set($x + n, 49 - $y, true);
-  //     };
-  //   }
-
-  //   cell!(25, 1);
-
-  //   cell!(23, 2);
-  //   cell!(25, 2);
-
-  //   cell!(13, 3);
-  //   cell!(14, 3);
-  //   cell!(21, 3);
-  //   cell!(22, 3);
-  //   cell!(35, 3);
-  //   cell!(36, 3);
-
-  //   cell!(12, 4);
-  //   cell!(16, 4);
-  //   cell!(21, 4);
-  //   cell!(22, 4);
-  //   cell!(35, 4);
-  //   cell!(36, 4);
-
-  //   cell!(1, 5);
-  //   cell!(2, 5);
-  //   cell!(11, 5);
-  //   cell!(17, 5);
-  //   cell!(21, 5);
-  //   cell!(22, 5);
-
-  //   cell!(1, 6);
-  //   cell!(2, 6);
-  //   cell!(11, 6);
-  //   cell!(15, 6);
-  //   cell!(17, 6);
-  //   cell!(18, 6);
-  //   cell!(23, 6);
-  //   cell!(25, 6);
-
-  //   cell!(11, 7);
-  //   cell!(17, 7);
-  //   cell!(25, 7);
-
-  //   cell!(12, 8);
-  //   cell!(16, 8);
-
-  //   cell!(13, 9);
-  //   cell!(14, 9);
-  // }
-
-  // {
-  //   macro_rules! cell {
-  //     ($x:expr, $y:expr) => {
-  //       world.set(199 - $x - n, $y, true);
-  //     };
-  //   }
-
-  //   cell!(25, 1);
-
-  //   cell!(23, 2);
-  //   cell!(25, 2);
-
-  //   cell!(13, 3);
-  //   cell!(14, 3);
-  //   cell!(21, 3);
-  //   cell!(22, 3);
-  //   cell!(35, 3);
-  //   cell!(36, 3);
-
-  //   cell!(12, 4);
-  //   cell!(16, 4);
-  //   cell!(21, 4);
-  //   cell!(22, 4);
-  //   cell!(35, 4);
-  //   cell!(36, 4);
-
-  //   cell!(1, 5);
-  //   cell!(2, 5);
-  //   cell!(11, 5);
-  //   cell!(17, 5);
-  //   cell!(21, 5);
-  //   cell!(22, 5);
-
-  //   cell!(1, 6);
-  //   cell!(2, 6);
-  //   cell!(11, 6);
-  //   cell!(15, 6);
-  //   cell!(17, 6);
-  //   cell!(18, 6);
-  //   cell!(23, 6);
-  //   cell!(25, 6);
-
-  //   cell!(11, 7);
-  //   cell!(17, 7);
-  //   cell!(25, 7);
-
-  //   cell!(12, 8);
-  //   cell!(16, 8);
-
-  //   cell!(13, 9);
-  //   cell!(14, 9);
-  // }
-
-  // {
-  //   macro_rules! cell {
-  //     ($x:expr, $y:expr) => {
-  //       world.set(199 - $x - n, 49 - $y, true);
-  //     };
-  //   }
-
-  //   cell!(25, 1);
-
-  //   cell!(23, 2);
-  //   cell!(25, 2);
-
-  //   cell!(13, 3);
-  //   cell!(14, 3);
-  //   cell!(21, 3);
-  //   cell!(22, 3);
-  //   cell!(35, 3);
-  //   cell!(36, 3);
-
-  //   cell!(12, 4);
-  //   cell!(16, 4);
-  //   cell!(21, 4);
-  //   cell!(22, 4);
-  //   cell!(35, 4);
-  //   cell!(36, 4);
-
-  //   cell!(1, 5);
-  //   cell!(2, 5);
-  //   cell!(11, 5);
-  //   cell!(17, 5);
-  //   cell!(21, 5);
-  //   cell!(22, 5);
-
-  //   cell!(1, 6);
-  //   cell!(2, 6);
-  //   cell!(11, 6);
-  //   cell!(15, 6);
-  //   cell!(17, 6);
-  //   cell!(18, 6);
-  //   cell!(23, 6);
-  //   cell!(25, 6);
-
-  //   cell!(11, 7);
-  //   cell!(17, 7);
-  //   cell!(25, 7);
-
-  //   cell!(12, 8);
-  //   cell!(16, 8);
-
-  //   cell!(13, 9);
-  //   cell!(14, 9);
-  // }
-
-  let w = world.width;
-  let h = world.height;
-
-  #[cfg_attr(rustfmt, rustfmt_skip)]
-  let sectors = vec![
-    Sector::new(0,     0,     w / 2, h / 2),
-    Sector::new(w / 2, 0,     w / 2, h / 2),
-    Sector::new(0,     h / 2, w / 2, h / 2),
-    Sector::new(w / 2, h / 2, w / 2, h / 2),
-  ];
-
-  // let mut world = Arc::new(world);
-
-  let mut generation = 0;
-  // let generation = Arc::new(Mutex::new(0));
-
-  // {
-  //   let world_ptr = &mut world as *mut World;
-  //   let world_copy = unsafe { &mut *world_ptr };
-
-  //   let thread_generation = generation.clone();
-
-  //   thread::spawn(move || loop {
-  //     print!("{}", world_copy);
-  //     println!("{}", thread_generation.lock().unwrap());
-  //     thread::sleep(Duration::from_millis(10));
-  //     print!("{}[{}A", 27 as char, world_copy.height + 1);
-  //   });
-  // }
-
-  // loop {
-  //   measure_time(format!("generation #{}", generation).as_str(), || {
-  //     // world = parallel_next_generation(&world, &sectors);
-  //     parallel_next_generation_2(&mut world, &sectors);
-  //   });
-  //   generation += 1;
-  // }
-
-  let mut next_world = World::new(world.width, world.height);
-
-  crossbeam::scope(|scope| {
-    let world_ptr = &mut world as *mut World;
-    let next_world_ptr = &mut next_world as *mut World;
-
-    let mut sector_senders = Vec::with_capacity(4);
-    let mut done_receivers = Vec::with_capacity(4);
-
-    for _ in 0..4 {
-      let thread_world = unsafe { &mut *world_ptr };
-      let thread_next_world = unsafe { &mut *next_world_ptr };
-
-      let (sector_sender, sector_receiver) = mpsc::channel();
-      let (done_sender, done_receiver) = mpsc::channel();
-
-      scope.spawn(move || loop {
-        let sector = sector_receiver.recv().unwrap();
-
-        let sector_world = thread_world.next_generation(sector);
-
-        for y in 0..sector.height {
-          for x in 0..sector.width {
-            let cell = sector_world.get(x, y);
-            thread_next_world.set(sector.x + x, sector.y + y, cell);
-          }
-        }
 
-        done_sender.send(()).unwrap();
-      });
+use failure::Error;
 
-      sector_senders.push(sector_sender);
-      done_receivers.push(done_receiver);
-    }
+use engine::Engine;
+use rule::{Rule, State, DEAD};
 
-    loop {
-      // println!("generation {}", generation);
-      // print!("{}", world);
-      // print!("{}[{}A", 27 as char, world.height + 1);
-
-      measure_time(format!("generation #{}", generation).as_str(), || {
-        for index in 0..4 {
-          let tx = &sector_senders[index];
-          let sector = &sectors[index];
-          tx.send(sector).unwrap();
-        }
+pub mod scheduler;
+pub mod world;
+use self::scheduler::{Schedule, Scheduling};
+use self::world::{Sector, TileStats, World, TILE_SIZE};
 
-        for index in 0..4 {
-          let rx = &done_receivers[index];
-          rx.recv().unwrap();
-        }
+/// Returns the default number of threads, which is the number of cores.
+pub fn default_threads() -> usize {
+  num_cpus::get()
+}
 
-        unsafe {
-          ptr::swap(world_ptr, next_world_ptr);
-        }
-      });
+/// The threaded backend: a bounded world whose sectors are computed on a
+/// pool of threads.
+pub struct ThreadedEngine {
+  rule: Rule,
+  threads: usize,
+  scheduling: Scheduling,
+  pool: rayon::ThreadPool,
+  schedule: Schedule,
+  world: World,
+  /// Holds the previous generation, which skipped tiles repeat, see
+  /// `World::next_generation_into`.
+  next_world: World,
+  generation: u64,
+}
 
-      generation += 1;
+impl ThreadedEngine {
+  pub fn new(rule: Rule, threads: usize, scheduling: Scheduling) -> Self {
+    let pool = rayon::ThreadPoolBuilder::new()
+      .num_threads(threads)
+      .build()
+      .unwrap();
+
+    ThreadedEngine {
+      rule,
+      threads,
+      scheduling,
+      pool,
+      schedule: Schedule::new(0, 0, threads, scheduling),
+      world: World::new(0, 0),
+      next_world: World::new(0, 0),
+      generation: 0,
     }
-  });
+  }
+
+  /// Replaces the world and resets the generation to 0.
+  pub fn load_world(&mut self, mut world: World) {
+    self.schedule =
+      Schedule::new(world.width, world.height, self.threads, self.scheduling);
+    debug!(
+      target: "threaded",
+      "{} units on {} threads, {} scheduling",
+      self.schedule.units.len(),
+      self.schedule.threads,
+      self.scheduling
+    );
+
+    let mut next_world = world.clone();
+    if self.rule.states() > 2 {
+      // every sector has to be able to write dying cells
+      world.allocate_dying_cells();
+      next_world.allocate_dying_cells();
+    }
+
+    self.world = world;
+    self.next_world = next_world;
+    self.generation = 0;
+  }
+
+  /// Computes the next generation.
+  pub fn next_generation(&mut self) {
+    let stats = {
+      // every thread reads the whole world and writes only its own sectors
+      let (world, rule) = (&self.world, &self.rule);
+      let mut sectors = self.next_world.split_mut(&self.schedule.units);
+      self.pool.install(|| {
+        sectors
+          .par_iter_mut()
+          .map(|sector| world.next_generation_into(rule, sector))
+          .reduce(TileStats::default, TileStats::add)
+      })
+    };
+
+    mem::swap(&mut self.world, &mut self.next_world);
+
+    self.generation += 1;
+    debug!(
+      target: "threaded",
+      "generation #{}: computed {} tiles, skipped {}",
+      self.generation,
+      stats.computed,
+      stats.skipped
+    );
+  }
 }
 
-fn parallel_next_generation_unsafe(
-  world: &World,
-  sectors: &Vec<Sector>,
-) -> World {
-  let mut next_world = World::new(world.width, world.height);
+impl Engine for ThreadedEngine {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    let mut world = World::new(width, height);
+    for y in 0..height {
+      for x in 0..width {
+        let cell = cells[x + y * width];
+        if cell != DEAD {
+          world.set(x, y, cell);
+        }
+      }
+    }
 
-  crossbeam::scope(|scope| {
-    let next_world_ptr = &mut next_world as *mut World;
+    self.load_world(world);
+    Ok(())
+  }
 
-    for sector in sectors {
-      let thread_next_world = unsafe { &mut *next_world_ptr };
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    for _ in 0..generations {
+      self.next_generation();
+    }
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let world = &self.world;
+    let in_world = |x: i64, y: i64| {
+      x >= 0 && y >= 0 && x < world.width as i64 && y < world.height as i64
+    };
+
+    let mut cells = Vec::with_capacity(width * height);
+    for y in y..y + height as i64 {
+      for x in x..x + width as i64 {
+        cells.push(if in_world(x, y) {
+          world.get(x as usize, y as usize)
+        } else {
+          DEAD
+        });
+      }
+    }
+    Ok(cells)
+  }
 
-      scope.spawn(move || {
-        let sector_world = world.next_generation(sector);
+  fn population(&self) -> Result<u64, Error> {
+    Ok(self.world.population() as u64)
+  }
 
-        for y in 0..sector.height {
-          for x in 0..sector.width {
-            let cell = sector_world.get(x, y);
-            thread_next_world.set(sector.x + x, sector.y + y, cell);
-          }
-        }
-      });
-    }
-  });
+  fn hash(&self) -> Result<u64, Error> {
+    Ok(self.world.hash())
+  }
+}
 
-  next_world
+/// Splits a world into at least `parts` sectors (unless the world has fewer
+/// tiles) which cover every cell exactly once. The sectors are strips of
+/// whole tiles, which are split into columns if there are more parts than
+/// rows of tiles, so threads never write the same tile.
+pub fn partition(width: usize, height: usize, parts: usize) -> Vec<Sector> {
+  let tiles = |cells: usize| (cells + TILE_SIZE - 1) / TILE_SIZE;
+  let (columns, rows) = (tiles(width), tiles(height));
+  if columns == 0 || rows == 0 {
+    return Vec::new();
+  }
+
+  let row_parts = parts.max(1).min(rows);
+  let column_parts = ((parts + row_parts - 1) / row_parts).max(1).min(columns);
+
+  // splits `count` tiles into `parts` ranges of cells which differ by one
+  // tile at most, clipped to `size` cells
+  let split = |count: usize, parts: usize, size: usize| {
+    (0..parts)
+      .map(|part| {
+        let start = part * count / parts * TILE_SIZE;
+        let end = ((part + 1) * count / parts * TILE_SIZE).min(size);
+        (start, end - start)
+      })
+      .collect::<Vec<_>>()
+  };
+
+  let mut sectors = Vec::with_capacity(row_parts * column_parts);
+  for (y, height) in split(rows, row_parts, height) {
+    for &(x, width) in &split(columns, column_parts, width) {
+      sectors.push(Sector::new(x, y, width, height));
+    }
+  }
+  sectors
 }
 
-fn parallel_next_generation_safe(
-  world: &mut Arc<World>,
-  sectors: &Vec<Sector>,
-) {
-  let threads = measure_time("threads", || {
-    crossbeam::scope(|scope| {
-      sectors
-        .iter()
-        .map(|sector| {
-          let thread_world = world.clone();
-          scope.spawn(move || (sector, thread_world.next_generation(sector)))
-        })
-        .collect::<Vec<_>>()
-    })
-  });
-
-  measure_time("merge", || {
-    for thread in threads {
-      let (sector, sector_world) = thread.join();
-      for y in 0..sector.height {
-        for x in 0..sector.width {
-          let cell = sector_world.get(x, y);
-          Arc::get_mut(world)
-            .unwrap()
-            .set(sector.x + x, sector.y + y, cell);
+#[cfg(test)]
+mod tests {
+  extern crate test;
+  use self::rand::{Rng, SeedableRng};
+  use self::test::Bencher;
+
+  use super::*;
+  use rule::ALIVE;
+
+  #[test]
+  fn partition_covers_every_cell_once() {
+    for &(width, height) in &[(1, 1), (63, 65), (200, 50), (1000, 129), (64, 1)]
+    {
+      for parts in 1..20 {
+        let sectors = partition(width, height, parts);
+        let tiles = ((width + 63) / 64) * ((height + 63) / 64);
+        assert!(sectors.len() >= parts.min(tiles));
+
+        let mut covered = vec![0; width * height];
+        for sector in &sectors {
+          assert_eq!(sector.x % TILE_SIZE, 0);
+          assert_eq!(sector.y % TILE_SIZE, 0);
+          for y in sector.y..sector.y + sector.height {
+            for x in sector.x..sector.x + sector.width {
+              covered[x + y * width] += 1;
+            }
+          }
         }
+        assert!(covered.iter().all(|&count| count == 1));
       }
     }
-  });
-}
-
-fn create_world() -> World {
-  measure_time("create world", || {
-    let mut world = World::new(10_000, 10_000);
-    // let mut world = World::new(200, 50);
-
-    let mut rng = rand::thread_rng();
-    for _ in 0..100_000_000 as u32 {
-      // for _ in 0..5000 as u32 {
-      let x = rng.gen_range(0, world.width);
-      let y = rng.gen_range(0, world.height);
-      world.set(x, y, true);
+    assert!(partition(0, 10, 4).is_empty());
+  }
+
+  #[test]
+  fn thread_counts_agree() {
+    let rule = Rule::default();
+    let mut world = World::new(300, 150);
+    for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      world.set(x + 60, y + 60, ALIVE);
+    }
+    for x in 100..250 {
+      world.set(x, 140 - x / 3, ALIVE);
     }
 
+    let mut hashes = Vec::new();
+    for &scheduling in &[Scheduling::Static, Scheduling::Dynamic] {
+      for &threads in &[1, 2, 3, 7] {
+        let mut engine = ThreadedEngine::new(rule, threads, scheduling);
+        engine.load_world(world.clone());
+        engine.step(100).unwrap();
+        hashes.push(engine.hash().unwrap());
+      }
+    }
+    assert!(hashes.iter().all(|&hash| hash == hashes[0]));
+  }
+
+  const BENCH_SIZE: usize = 1024;
+  const BENCH_THREADS: usize = 4;
+  const BENCH_GENERATIONS: u64 = 4;
+
+  /// Creates a world of random cells which is alive within `alive_size`
+  /// cells of its top left corner only.
+  fn random_world(alive_size: usize) -> World {
+    let mut world = World::new(BENCH_SIZE, BENCH_SIZE);
+    let mut rng = rand::prng::XorShiftRng::from_seed([1; 16]);
+    for y in 0..alive_size {
+      for x in 0..alive_size {
+        if rng.gen() {
+          world.set(x, y, ALIVE);
+        }
+      }
+    }
     world
-  })
-}
-
-pub fn measure_time<F, R>(name: &str, f: F) -> R
-where
-  F: FnOnce() -> R,
-{
-  let start_time = Instant::now();
-  let ret = f();
-  println!("{} - {} µs", name, start_time.elapsed().as_micros());
-  ret
+  }
+
+  // most tiles of sparse worlds are stable and skipped, so most of the work
+  // is in the sector of one thread
+  fn sparse_world() -> World {
+    random_world(BENCH_SIZE / 4)
+  }
+
+  fn dense_world() -> World {
+    random_world(BENCH_SIZE)
+  }
+
+  fn bench_simulation(
+    bencher: &mut Bencher,
+    world: World,
+    scheduling: Scheduling,
+  ) {
+    let mut engine =
+      ThreadedEngine::new(Rule::default(), BENCH_THREADS, scheduling);
+    bencher.iter(|| {
+      engine.load_world(world.clone());
+      engine.step(BENCH_GENERATIONS).unwrap();
+    });
+  }
+
+  #[bench]
+  fn sparse_static(bencher: &mut Bencher) {
+    bench_simulation(bencher, sparse_world(), Scheduling::Static);
+  }
+
+  #[bench]
+  fn sparse_dynamic(bencher: &mut Bencher) {
+    bench_simulation(bencher, sparse_world(), Scheduling::Dynamic);
+  }
+
+  #[bench]
+  fn dense_static(bencher: &mut Bencher) {
+    bench_simulation(bencher, dense_world(), Scheduling::Static);
+  }
+
+  #[bench]
+  fn dense_dynamic(bencher: &mut Bencher) {
+    bench_simulation(bencher, dense_world(), Scheduling::Dynamic);
+  }
 }
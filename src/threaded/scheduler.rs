@@ -0,0 +1,79 @@
+use std::fmt;
+
+use super::partition;
+use super::world::{Sector, TILE_SIZE};
+
+/// How the sectors of a world are distributed over threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+  /// Every thread computes one sector of the world of the same size.
+  Static,
+  /// The world is split into single tiles, which idle threads steal from
+  /// busy ones, so threads with stable (and therefore skipped) tiles help
+  /// the others.
+  Dynamic,
+}
+
+impl fmt::Display for Scheduling {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Scheduling::Static => "static",
+      Scheduling::Dynamic => "dynamic",
+    })
+  }
+}
+
+/// The units of work of a world and the number of threads computing them.
+/// Units are computed on a rayon pool, whose threads steal units from each
+/// other once their own are done.
+pub struct Schedule {
+  pub units: Vec<Sector>,
+  pub threads: usize,
+}
+
+impl Schedule {
+  pub fn new(
+    width: usize,
+    height: usize,
+    threads: usize,
+    scheduling: Scheduling,
+  ) -> Self {
+    let threads = threads.max(1);
+    let units = match scheduling {
+      Scheduling::Static => partition(width, height, threads),
+      Scheduling::Dynamic => {
+        let tiles = |cells: usize| (cells + TILE_SIZE - 1) / TILE_SIZE;
+        partition(width, height, tiles(width) * tiles(height))
+      }
+    };
+
+    Schedule {
+      threads: threads.min(units.len()).max(1),
+      units,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn units() {
+    let schedule = Schedule::new(1000, 300, 3, Scheduling::Static);
+    assert_eq!(schedule.units.len(), 3);
+    assert_eq!(schedule.threads, 3);
+
+    let schedule = Schedule::new(1000, 300, 8, Scheduling::Dynamic);
+    assert_eq!(schedule.units.len(), 16 * 5);
+    assert_eq!(schedule.threads, 8);
+    assert!(schedule
+      .units
+      .iter()
+      .all(|unit| unit.width <= TILE_SIZE && unit.height <= TILE_SIZE));
+
+    let schedule = Schedule::new(100, 50, 8, Scheduling::Dynamic);
+    assert_eq!(schedule.units.len(), 2);
+    assert_eq!(schedule.threads, 2);
+  }
+}
@@ -1,35 +1,257 @@
 use std::fmt;
+use std::mem;
 
-// extern crate bit_vec;
-// use self::bit_vec::BitVec;
+use neighborhood::Neighborhood;
+use rule::neighbors::*;
+use rule::{Rule, State, ALIVE, DEAD};
+use topology::Topology;
+use utils::hash::hash_world;
+use utils::render;
+use utils::summed_area::SummedAreaTable;
 
+/// Number of cells in a word of the bit-packed storage.
+pub const WORD_BITS: usize = 64;
+
+/// Width and height of the tiles whose activity is tracked, see
+/// [`next_generation_into`]. Tiles are whole words wide.
+///
+/// [`next_generation_into`]: struct.World.html#method.next_generation_into
+pub const TILE_SIZE: usize = WORD_BITS;
+
+/// Numbers of tiles which were computed and skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileStats {
+  pub computed: usize,
+  pub skipped: usize,
+}
+
+impl TileStats {
+  pub fn add(self, other: TileStats) -> TileStats {
+    TileStats {
+      computed: self.computed + other.computed,
+      skipped: self.skipped + other.skipped,
+    }
+  }
+}
+
+/// A bounded world. Alive cells are bit-packed, 64 cells per word, so
+/// life-like rules can compute 64 cells at once (see
+/// [`next_generation_bitwise`]). Rows start at word boundaries and bits
+/// beyond the width are always zero.
+///
+/// [`next_generation_bitwise`]: fn.next_generation_bitwise.html
+#[derive(Clone)]
 pub struct World {
   pub width: usize,
   pub height: usize,
-  // data: BitVec,
-  data: Vec<bool>,
+  words_per_row: usize,
+  alive: Vec<u64>,
+  /// States of dying cells of "Generations" rules, a byte per cell. It's
+  /// only allocated when the first dying cell is set.
+  dying: Vec<State>,
+  /// Whether a tile may differ from the same tile two generations ago, row
+  /// by row. Tiles which were set or pasted are always marked.
+  changed_tiles: Vec<bool>,
 }
 
 impl World {
   pub fn new(width: usize, height: usize) -> Self {
+    let words_per_row = (width + WORD_BITS - 1) / WORD_BITS;
     World {
       width,
       height,
-      // data: BitVec::from_elem(width * height, false),
-      data: vec![false; width * height],
+      words_per_row,
+      alive: vec![0; words_per_row * height],
+      dying: Vec::new(),
+      changed_tiles: vec![true; tiles(width) * tiles(height)],
     }
   }
 
-  pub fn get(&self, x: usize, y: usize) -> bool {
+  pub fn get(&self, x: usize, y: usize) -> State {
     self.assert_in_bounds(x, y);
-    // self.data.get(y * self.width + x).unwrap()
-    self.data[y * self.width + x]
+    let (word, bit) = self.position(x, y);
+    if (self.alive[word] >> bit) & 1 != 0 {
+      ALIVE
+    } else if self.dying.is_empty() {
+      DEAD
+    } else {
+      self.dying[y * self.width + x]
+    }
   }
 
-  pub fn set(&mut self, x: usize, y: usize, cell: bool) {
+  pub fn set(&mut self, x: usize, y: usize, cell: State) {
     self.assert_in_bounds(x, y);
-    // self.data.set(y * self.width + x, cell);
-    self.data[y * self.width + x] = cell;
+    let (word, bit) = self.position(x, y);
+    if cell == ALIVE {
+      self.alive[word] |= 1 << bit;
+    } else {
+      self.alive[word] &= !(1 << bit);
+    }
+
+    if self.dying.is_empty() && cell != DEAD && cell != ALIVE {
+      self.allocate_dying_cells();
+    }
+    if !self.dying.is_empty() {
+      self.dying[y * self.width + x] = if cell == ALIVE { DEAD } else { cell };
+    }
+
+    let tile = self.tile_index(x / TILE_SIZE, y / TILE_SIZE);
+    self.changed_tiles[tile] = true;
+  }
+
+  /// Returns the number of alive cells.
+  pub fn population(&self) -> usize {
+    self
+      .alive
+      .iter()
+      .map(|word| word.count_ones() as usize)
+      .sum()
+  }
+
+  /// Returns `true` if all cells are dead.
+  pub fn is_empty(&self) -> bool {
+    self.population() == 0 && self.dying.iter().all(|&cell| cell == DEAD)
+  }
+
+  /// Returns `true` if the states of dying cells are allocated.
+  pub fn has_dying_cells(&self) -> bool {
+    !self.dying.is_empty()
+  }
+
+  /// Returns the smallest rectangle `(x0, y0, x1, y1)` (inclusive) which
+  /// contains all cells which aren't dead, or `None` if the world is empty.
+  /// Alive cells are found a word at a time.
+  pub fn bounds(&self) -> Option<(usize, usize, usize, usize)> {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    let mut add = |x0: usize, x1: usize, y: usize| {
+      bounds = Some(match bounds {
+        None => (x0, y, x1, y),
+        Some((bx0, by0, bx1, by1)) => {
+          (bx0.min(x0), by0.min(y), bx1.max(x1), by1.max(y))
+        }
+      });
+    };
+
+    if self.words_per_row > 0 {
+      for (y, row) in self.alive.chunks(self.words_per_row).enumerate() {
+        for (word, &cells) in row.iter().enumerate() {
+          if cells != 0 {
+            let x = word * WORD_BITS;
+            let x0 = x + cells.trailing_zeros() as usize;
+            let x1 = x + WORD_BITS - 1 - cells.leading_zeros() as usize;
+            add(x0, x1, y);
+          }
+        }
+      }
+    }
+    for (index, &cell) in self.dying.iter().enumerate() {
+      if cell != DEAD {
+        let (x, y) = (index % self.width, index / self.width);
+        add(x, x, y);
+      }
+    }
+
+    bounds
+  }
+
+  /// Allocates the states of dying cells, which otherwise happens when the
+  /// first dying cell is set.
+  pub fn allocate_dying_cells(&mut self) {
+    if self.dying.is_empty() {
+      self.dying = vec![DEAD; self.width * self.height];
+    }
+  }
+
+  /// Splits this world into mutable views of `sectors`, so every sector can
+  /// be written by a different thread. The sectors must be aligned to tiles
+  /// and mustn't overlap.
+  pub fn split_mut<'a>(
+    &'a mut self,
+    sectors: &'a [Sector],
+  ) -> Vec<SectorMut<'a>> {
+    for sector in sectors {
+      debug_assert!(
+        sector.x % TILE_SIZE == 0 && sector.y % TILE_SIZE == 0,
+        "sector {:?} isn't aligned to tiles",
+        sector
+      );
+    }
+
+    // a tile is a word wide, so a sector is a rectangle of words
+    let words: Vec<_> = sectors
+      .iter()
+      .map(|s| (s.x / WORD_BITS, s.y, tiles(s.width), s.height))
+      .collect();
+    let cells: Vec<_> = sectors
+      .iter()
+      .map(|s| (s.x, s.y, s.width, s.height))
+      .collect();
+    let tile_rects: Vec<_> = sectors
+      .iter()
+      .map(|s| {
+        (
+          s.x / TILE_SIZE,
+          s.y / TILE_SIZE,
+          tiles(s.width),
+          tiles(s.height),
+        )
+      })
+      .collect();
+
+    let alive =
+      split_rows(&mut self.alive, self.words_per_row, TILE_SIZE, &words);
+    let dying = split_rows(&mut self.dying, self.width, TILE_SIZE, &cells);
+    let changed_tiles =
+      split_rows(&mut self.changed_tiles, tiles(self.width), 1, &tile_rects);
+
+    sectors
+      .iter()
+      .zip(alive)
+      .zip(dying)
+      .zip(changed_tiles)
+      .map(|(((sector, alive), dying), changed_tiles)| SectorMut {
+        sector,
+        alive,
+        dying,
+        changed_tiles,
+      })
+      .collect()
+  }
+
+  fn tile_index(&self, column: usize, row: usize) -> usize {
+    row * tiles(self.width) + column
+  }
+
+  /// Returns the index of the word which contains a cell and the index of
+  /// its bit in that word.
+  fn position(&self, x: usize, y: usize) -> (usize, usize) {
+    (y * self.words_per_row + x / WORD_BITS, x % WORD_BITS)
+  }
+
+  /// Returns the words of a row, or `None` for rows outside of the world.
+  pub fn row(&self, y: isize) -> Option<&[u64]> {
+    if y < 0 || y >= self.height as isize {
+      return None;
+    }
+
+    let start = y as usize * self.words_per_row;
+    Some(&self.alive[start..start + self.words_per_row])
+  }
+
+  /// Returns the state of a cell which may be outside of the world, mapping
+  /// it into the world with `topology`.
+  pub fn get_wrapped(&self, x: isize, y: isize, topology: Topology) -> State {
+    match topology.wrap(x, y, self.width, self.height) {
+      Some((x, y)) => self.get(x, y),
+      None => DEAD,
+    }
+  }
+
+  /// Computes a stable hash of this world, see [`WorldHasher`] for details.
+  ///
+  /// [`WorldHasher`]: ../../utils/hash/struct.WorldHasher.html
+  pub fn hash(&self) -> u64 {
+    hash_world(self.width, self.height, |x, y| self.get(x, y))
   }
 
   fn assert_in_bounds(&self, x: usize, y: usize) {
@@ -48,57 +270,505 @@ impl World {
     );
   }
 
-  pub fn next_generation(&self, sector: &Sector) -> Self {
-    let mut next_world = World::new(sector.width, sector.height);
+  /// Computes the next generation of a sector into its view in the next
+  /// world, which must hold either the previous generation or a copy of this
+  /// world (for the first generation).
+  ///
+  /// Tiles whose neighborhood of 3x3 tiles hasn't changed in the last two
+  /// generations are skipped: they repeat their previous state, which is
+  /// already in the next world. This covers both still lifes and oscillators
+  /// of period 2. Tiles on the edges of a world whose edges are joined are
+  /// always computed, because their neighbors are elsewhere.
+  pub fn next_generation_into(
+    &self,
+    rule: &Rule,
+    next: &mut SectorMut,
+  ) -> TileStats {
+    let sector = next.sector;
+    let columns = tiles(self.width);
+    let rows = tiles(self.height);
+    let wraps = rule.topology() != Topology::Plane;
+    let mut stats = TileStats::default();
 
-    for y in 0..sector.height {
-      for x in 0..sector.width {
-        let cell = self.get(sector.x + x, sector.y + y);
+    let first_row = sector.y / TILE_SIZE;
+    let first_column = sector.x / TILE_SIZE;
+    for row in first_row..first_row + tiles(sector.height) {
+      for column in first_column..first_column + tiles(sector.width) {
+        let on_edge =
+          column == 0 || row == 0 || column == columns - 1 || row == rows - 1;
+        let active = (wraps && on_edge)
+          || (row.max(1) - 1..=(row + 1).min(rows - 1)).any(|row| {
+            (column.max(1) - 1..=(column + 1).min(columns - 1))
+              .any(|column| self.changed_tiles[self.tile_index(column, row)])
+          });
 
-        let n = self.count_neighbors(sector.x + x, sector.y + y);
-        let next_cell = if cell { n >= 2 && n <= 3 } else { n == 3 };
+        let (tile_row, tile_column) = (row - first_row, column - first_column);
+        if !active {
+          next.changed_tiles[tile_row][tile_column] = false;
+          stats.skipped += 1;
+          continue;
+        }
 
-        next_world.set(x, y, next_cell);
+        let (x, y) = (column * TILE_SIZE, row * TILE_SIZE);
+        let tile = Sector::new(
+          x,
+          y,
+          TILE_SIZE.min(sector.x + sector.width - x),
+          TILE_SIZE.min(sector.y + sector.height - y),
+        );
+        next.changed_tiles[tile_row][tile_column] = self.write_next_generation(
+          &tile,
+          rule,
+          next,
+          x - sector.x,
+          y - sector.y,
+        );
+        stats.computed += 1;
       }
     }
 
+    stats
+  }
+
+  /// Computes the next generation of a sector into a new world of the size
+  /// of the sector.
+  pub fn next_generation(&self, sector: &Sector, rule: &Rule) -> Self {
+    let mut next_world = World::new(sector.width, sector.height);
+    if rule.states() > 2 {
+      next_world.allocate_dying_cells();
+    }
+
+    {
+      let sectors = [Sector::new(0, 0, sector.width, sector.height)];
+      let mut destination = next_world.split_mut(&sectors).pop().unwrap();
+      self.write_next_generation(sector, rule, &mut destination, 0, 0);
+    }
+
     next_world
   }
 
-  fn count_neighbors(&self, x: usize, y: usize) -> u8 {
+  /// Computes the next generation of a sector and writes it into
+  /// `destination` with its top left corner at `(left, top)` (relative to
+  /// the sector of the destination), so nothing is allocated or copied.
+  /// `left` must be at a word boundary. Returns `true` if any cell of the
+  /// destination has changed.
+  pub fn write_next_generation(
+    &self,
+    sector: &Sector,
+    rule: &Rule,
+    destination: &mut SectorMut,
+    left: usize,
+    top: usize,
+  ) -> bool {
+    let cells = Wrapped {
+      world: self,
+      topology: rule.topology(),
+    };
+    compute_next_generation(&cells, sector, rule, destination, left, top)
+  }
+
+  /// Returns the configuration of alive neighbors of a cell, see
+  /// [`neighbors`].
+  ///
+  /// [`neighbors`]: ../../rule/neighbors/index.html
+  fn neighborhood(&self, x: usize, y: usize, topology: Topology) -> u8 {
+    let w = self.width;
+    let h = self.height;
+
+    let on_edge = x == 0 || y == 0 || x == w - 1 || y == h - 1;
+    if on_edge && topology != Topology::Plane {
+      return self.wrapped_neighborhood(x, y, topology);
+    }
+
     let mut result = 0;
 
     macro_rules! neighbor {
-      ($condition:expr, $x:expr, $y:expr) => {
-        if $condition && self.get($x, $y) {
-          result += 1;
+      ($bit:expr, $condition:expr, $x:expr, $y:expr) => {
+        if $condition && self.get($x, $y) == ALIVE {
+          result |= $bit;
         }
       };
     }
 
-    let w = self.width;
-    let h = self.height;
+    #[cfg_attr(rustfmt, rustfmt_skip)] {
+      neighbor!(N,  y > 0                 , x,     y - 1); // top
+      neighbor!(NE, x < w - 1 && y > 0    , x + 1, y - 1); // top right
+      neighbor!(E,  x < w - 1             , x + 1, y    ); // right
+      neighbor!(SE, x < w - 1 && y < h - 1, x + 1, y + 1); // bottom right
+      neighbor!(S,  y < h - 1             , x,     y + 1); // bottom
+      neighbor!(SW, x > 0     && y < h - 1, x - 1, y + 1); // bottom left
+      neighbor!(W,  x > 0                 , x - 1, y    ); // left
+      neighbor!(NW, x > 0     && y > 0    , x - 1, y - 1); // top left
+    }
+
+    result
+  }
+
+  /// Same as [`neighborhood`], but maps neighbors outside of the world into
+  /// it with `topology`, which is only needed for cells on the edges.
+  ///
+  /// [`neighborhood`]: #method.neighborhood
+  fn wrapped_neighborhood(&self, x: usize, y: usize, topology: Topology) -> u8 {
+    let mut result = 0;
+    let (x, y) = (x as isize, y as isize);
+
+    macro_rules! neighbor {
+      ($bit:expr, $x:expr, $y:expr) => {
+        if self.get_wrapped($x, $y, topology) == ALIVE {
+          result |= $bit;
+        }
+      };
+    }
 
     #[cfg_attr(rustfmt, rustfmt_skip)] {
-      neighbor!(y > 0                 , x,     y - 1); // top
-      neighbor!(x < w - 1 && y > 0    , x + 1, y - 1); // top right
-      neighbor!(x < w - 1             , x + 1, y    ); // right
-      neighbor!(x < w - 1 && y < h - 1, x + 1, y + 1); // bottom right
-      neighbor!(y < h - 1             , x,     y + 1); // bottom
-      neighbor!(x > 0     && y < h - 1, x - 1, y + 1); // bottom left
-      neighbor!(x > 0                 , x - 1, y    ); // left
-      neighbor!(x > 0     && y > 0    , x - 1, y - 1); // top left
+      neighbor!(N,  x,     y - 1); // top
+      neighbor!(NE, x + 1, y - 1); // top right
+      neighbor!(E,  x + 1, y    ); // right
+      neighbor!(SE, x + 1, y + 1); // bottom right
+      neighbor!(S,  x,     y + 1); // bottom
+      neighbor!(SW, x - 1, y + 1); // bottom left
+      neighbor!(W,  x - 1, y    ); // left
+      neighbor!(NW, x - 1, y - 1); // top left
     }
 
     result
   }
 }
 
+/// Cells which the next generation of a sector is computed from. Cells are
+/// addressed like the cells of the sector, but they may be outside of it, so
+/// the tiles of an unbounded world can be computed from their neighboring
+/// tiles like the sectors of a world.
+pub trait Cells {
+  /// Returns the state of a cell.
+  fn cell(&self, x: isize, y: isize) -> State;
+
+  /// Returns the words of alive cells of the row `y` and the cell which the
+  /// first word starts at. Rows which aren't stored in one piece are copied
+  /// into `words`. Missing words are dead cells, it's only used with
+  /// totalistic Moore rules on a plane.
+  fn row<'b>(&'b self, y: isize, words: &'b mut [u64; 3])
+    -> (&'b [u64], isize);
+
+  /// Returns `true` if some cells may be dying, their states aren't in the
+  /// alive bits of [`row`].
+  ///
+  /// [`row`]: #tymethod.row
+  fn has_dying_cells(&self) -> bool;
+
+  /// Returns the configuration of alive neighbors of a cell, see
+  /// [`neighbors`].
+  ///
+  /// [`neighbors`]: ../../rule/neighbors/index.html
+  fn neighborhood(&self, x: isize, y: isize) -> u8 {
+    let mut result = 0;
+
+    macro_rules! neighbor {
+      ($bit:expr, $x:expr, $y:expr) => {
+        if self.cell($x, $y) == ALIVE {
+          result |= $bit;
+        }
+      };
+    }
+
+    #[cfg_attr(rustfmt, rustfmt_skip)] {
+      neighbor!(N,  x,     y - 1); // top
+      neighbor!(NE, x + 1, y - 1); // top right
+      neighbor!(E,  x + 1, y    ); // right
+      neighbor!(SE, x + 1, y + 1); // bottom right
+      neighbor!(S,  x,     y + 1); // bottom
+      neighbor!(SW, x - 1, y + 1); // bottom left
+      neighbor!(W,  x - 1, y    ); // left
+      neighbor!(NW, x - 1, y - 1); // top left
+    }
+
+    result
+  }
+}
+
+/// The cells of a world, where cells outside of the world are mapped into it
+/// with `topology`.
+struct Wrapped<'a> {
+  world: &'a World,
+  topology: Topology,
+}
+
+impl<'a> Cells for Wrapped<'a> {
+  fn cell(&self, x: isize, y: isize) -> State {
+    self.world.get_wrapped(x, y, self.topology)
+  }
+
+  fn row<'b>(&'b self, y: isize, _: &'b mut [u64; 3]) -> (&'b [u64], isize) {
+    // rows outside of the world are empty
+    (self.world.row(y).unwrap_or(&[]), 0)
+  }
+
+  fn has_dying_cells(&self) -> bool {
+    self.world.has_dying_cells()
+  }
+
+  fn neighborhood(&self, x: isize, y: isize) -> u8 {
+    self
+      .world
+      .neighborhood(x as usize, y as usize, self.topology)
+  }
+}
+
+/// Computes the next generation of a sector of `cells` and writes it into
+/// `destination`, see [`World::write_next_generation`].
+///
+/// [`World::write_next_generation`]: struct.World.html#method.write_next_generation
+pub fn compute_next_generation<C: Cells>(
+  cells: &C,
+  sector: &Sector,
+  rule: &Rule,
+  destination: &mut SectorMut,
+  left: usize,
+  top: usize,
+) -> bool {
+  debug_assert!(left % WORD_BITS == 0, "{} isn't at a word boundary", left);
+
+  if rule.is_larger_than_life() {
+    return next_generation_by_count(
+      cells,
+      sector,
+      rule,
+      destination,
+      left,
+      top,
+    );
+  }
+
+  let is_life_like = rule.states() == 2
+    && rule.neighborhood() == Neighborhood::Moore
+    && rule.topology() == Topology::Plane
+    && !cells.has_dying_cells();
+  match rule.totalistic_masks() {
+    Some((birth, survival)) if is_life_like => next_generation_bitwise(
+      cells,
+      sector,
+      (birth, survival),
+      destination,
+      left,
+      top,
+    ),
+    _ => next_generation_by_cells(cells, sector, rule, destination, left, top),
+  }
+}
+
+/// Computes the next generation of a sector cell by cell, which works with
+/// every range 1 rule and topology.
+fn next_generation_by_cells<C: Cells>(
+  cells: &C,
+  sector: &Sector,
+  rule: &Rule,
+  destination: &mut SectorMut,
+  left: usize,
+  top: usize,
+) -> bool {
+  let mut changed = false;
+
+  for y in 0..sector.height {
+    for x in 0..sector.width {
+      let (cx, cy) = ((sector.x + x) as isize, (sector.y + y) as isize);
+      let cell = cells.cell(cx, cy);
+
+      let neighbors = cells.neighborhood(cx, cy);
+      let next_cell = rule.next_state(cell, neighbors);
+
+      changed |= destination.set(left + x, top + y, next_cell);
+    }
+  }
+
+  changed
+}
+
+/// Computes the next generation of a sector with a totalistic two-state
+/// Moore rule on a plane, 64 cells at once. The eight neighbors of the
+/// cells of a word are the words shifted by one cell, which are summed
+/// with full adders into four bit planes of the count, so the count of
+/// every cell is in the same bit of the planes.
+fn next_generation_bitwise<C: Cells>(
+  cells: &C,
+  sector: &Sector,
+  (birth, survival): (u16, u16),
+  destination: &mut SectorMut,
+  left: usize,
+  top: usize,
+) -> bool {
+  let mut changed = false;
+
+  let (mut above_words, mut middle_words, mut below_words) =
+    ([0; 3], [0; 3], [0; 3]);
+
+  for y in 0..sector.height {
+    // `window` treats words outside of the rows as dead cells
+    let wy = (sector.y + y) as isize;
+    let (above, above_x) = cells.row(wy - 1, &mut above_words);
+    let (middle, middle_x) = cells.row(wy, &mut middle_words);
+    let (below, below_x) = cells.row(wy + 1, &mut below_words);
+
+    for word in 0..(sector.width + WORD_BITS - 1) / WORD_BITS {
+      let x = (sector.x + word * WORD_BITS) as isize;
+
+      let alive = window(middle, x - middle_x);
+      let counts = count_neighbors([
+        window(above, x - above_x - 1),
+        window(above, x - above_x),
+        window(above, x - above_x + 1),
+        window(middle, x - middle_x - 1),
+        window(middle, x - middle_x + 1),
+        window(below, x - below_x - 1),
+        window(below, x - below_x),
+        window(below, x - below_x + 1),
+      ]);
+
+      let mut next = 0;
+      for n in 0..=8 {
+        let has_count = count_equals(counts, n);
+        if (birth >> n) & 1 != 0 {
+          next |= has_count & !alive;
+        }
+        if (survival >> n) & 1 != 0 {
+          next |= has_count & alive;
+        }
+      }
+
+      // only bits within the width of the sector are written
+      let width = (sector.width - word * WORD_BITS).min(WORD_BITS);
+      let mask = if width < WORD_BITS {
+        (1 << width) - 1
+      } else {
+        !0
+      };
+      changed |=
+        destination.set_word(left / WORD_BITS + word, top + y, next, mask);
+    }
+  }
+
+  changed
+}
+
+/// Computes the next generation of a sector with a Larger than Life rule.
+/// Alive cells in the sector and its halo of the rule's range are counted
+/// once into a summed-area table, then the neighborhood of every cell is
+/// counted with one lookup per rectangle of the neighborhood (see
+/// [`Neighborhood::rectangles`]).
+///
+/// [`Neighborhood::rectangles`]: ../../neighborhood/enum.Neighborhood.html#method.rectangles
+fn next_generation_by_count<C: Cells>(
+  cells: &C,
+  sector: &Sector,
+  rule: &Rule,
+  destination: &mut SectorMut,
+  left: usize,
+  top: usize,
+) -> bool {
+  let range = rule.range();
+  let rectangles = rule.neighborhood().rectangles(range);
+
+  let (halo_x, halo_y) = (
+    sector.x as isize - range as isize,
+    sector.y as isize - range as isize,
+  );
+  let table = SummedAreaTable::new(
+    sector.width + 2 * range,
+    sector.height + 2 * range,
+    |x, y| cells.cell(halo_x + x as isize, halo_y + y as isize) == ALIVE,
+  );
+
+  let mut changed = false;
+
+  for y in 0..sector.height {
+    for x in 0..sector.width {
+      let cell = cells.cell((sector.x + x) as isize, (sector.y + y) as isize);
+
+      // coordinates of the cell in the table
+      let tx = (x + range) as isize;
+      let ty = (y + range) as isize;
+
+      let count = rectangles
+        .iter()
+        .map(|&(x0, y0, x1, y1)| table.sum(tx + x0, ty + y0, tx + x1, ty + y1))
+        .sum();
+
+      let next_cell = rule.next_state_by_count(cell, count);
+      changed |= destination.set(left + x, top + y, next_cell);
+    }
+  }
+
+  changed
+}
+
+/// Returns the number of tiles needed to cover `cells`.
+fn tiles(cells: usize) -> usize {
+  (cells + TILE_SIZE - 1) / TILE_SIZE
+}
+
+/// Returns 64 cells of a row starting at the cell `x`, which may be negative.
+/// Cells outside of the row are dead.
+fn window(row: &[u64], x: isize) -> u64 {
+  let word_bits = WORD_BITS as isize;
+  let word = if x < 0 {
+    (x + 1) / word_bits - 1
+  } else {
+    x / word_bits
+  };
+  let shift = (x - word * word_bits) as u32;
+
+  let get = |word: isize| {
+    if word < 0 || word >= row.len() as isize {
+      0
+    } else {
+      row[word as usize]
+    }
+  };
+
+  if shift == 0 {
+    get(word)
+  } else {
+    (get(word) >> shift) | (get(word + 1) << (WORD_BITS as u32 - shift))
+  }
+}
+
+/// Returns the sum and the carry bits of three words.
+fn full_add(a: u64, b: u64, c: u64) -> (u64, u64) {
+  let ab = a ^ b;
+  (ab ^ c, (a & b) | (c & ab))
+}
+
+/// Returns the sum and the carry bits of two words.
+fn half_add(a: u64, b: u64) -> (u64, u64) {
+  (a ^ b, a & b)
+}
+
+/// Sums eight words bit by bit into four bit planes of the counts, from the
+/// least significant one.
+fn count_neighbors(n: [u64; 8]) -> [u64; 4] {
+  let (ones_a, twos_a) = full_add(n[0], n[1], n[2]);
+  let (ones_b, twos_b) = full_add(n[3], n[4], n[5]);
+  let (ones_c, twos_c) = half_add(n[6], n[7]);
+
+  let (ones, twos_d) = full_add(ones_a, ones_b, ones_c);
+  let (twos_abc, fours_a) = full_add(twos_a, twos_b, twos_c);
+  let (twos, fours_b) = half_add(twos_abc, twos_d);
+  let (fours, eights) = half_add(fours_a, fours_b);
+
+  [ones, twos, fours, eights]
+}
+
+/// Returns the bits of the cells whose count in the bit planes is `n`.
+fn count_equals(counts: [u64; 4], n: usize) -> u64 {
+  counts.iter().enumerate().fold(!0, |equal, (bit, &plane)| {
+    equal & if (n >> bit) & 1 != 0 { plane } else { !plane }
+  })
+}
+
 impl fmt::Display for World {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     for y in 0..self.height {
       for x in 0..self.width {
-        write!(f, "{}", if self.get(x, y) { 'x' } else { '.' })?;
+        write!(f, "{}", render::symbol(self.get(x, y)))?;
       }
       write!(f, "\n")?;
     }
@@ -125,3 +795,522 @@ impl Sector {
     }
   }
 }
+
+/// The cells of a sector of a world which can be written independently of
+/// other sectors, see [`World::split_mut`].
+///
+/// [`World::split_mut`]: struct.World.html#method.split_mut
+pub struct SectorMut<'a> {
+  pub sector: &'a Sector,
+  /// Words of the rows of the sector.
+  alive: Vec<&'a mut [u64]>,
+  /// States of dying cells of the rows of the sector, empty if the world has
+  /// no dying cells.
+  dying: Vec<&'a mut [State]>,
+  /// Changed tiles of the rows of tiles of the sector.
+  changed_tiles: Vec<&'a mut [bool]>,
+}
+
+impl<'a> SectorMut<'a> {
+  /// Sets a cell (relative to the sector) and returns `true` if it has
+  /// changed.
+  fn set(&mut self, x: usize, y: usize, cell: State) -> bool {
+    let (word, bit) = (x / WORD_BITS, x % WORD_BITS);
+    let alive = &mut self.alive[y][word];
+    let previous = *alive;
+    if cell == ALIVE {
+      *alive |= 1 << bit;
+    } else {
+      *alive &= !(1 << bit);
+    }
+    let mut changed = *alive != previous;
+
+    if self.dying.is_empty() {
+      assert!(
+        cell == DEAD || cell == ALIVE,
+        "dying cells of the sector {:?} aren't allocated",
+        self.sector
+      );
+    } else {
+      let dying = if cell == ALIVE { DEAD } else { cell };
+      changed |= self.dying[y][x] != dying;
+      self.dying[y][x] = dying;
+    }
+
+    changed
+  }
+
+  /// Replaces the cells of `mask` in a word (relative to the sector) with
+  /// the alive cells of `cells`, the others become dead. Returns `true` if
+  /// any cell has changed.
+  fn set_word(&mut self, word: usize, y: usize, cells: u64, mask: u64) -> bool {
+    let alive = &mut self.alive[y][word];
+    let mut changed = (*alive ^ cells) & mask != 0;
+    *alive = (*alive & !mask) | (cells & mask);
+
+    if !self.dying.is_empty() {
+      let start = word * WORD_BITS;
+      let end = (start + WORD_BITS).min(self.dying[y].len());
+      for x in start..end {
+        if (mask >> (x - start)) & 1 != 0 {
+          changed |= self.dying[y][x] != DEAD;
+          self.dying[y][x] = DEAD;
+        }
+      }
+    }
+
+    changed
+  }
+}
+
+/// Splits `cells`, which are rows of `row_length` elements, into the rows of
+/// the non-overlapping rectangles `rects` given as `(x, y, width, height)`.
+/// Rectangles start and end at bands of `band_height` rows (or at the last
+/// row), so the rectangles in a row are only looked up once per band.
+fn split_rows<'a, T>(
+  cells: &'a mut [T],
+  row_length: usize,
+  band_height: usize,
+  rects: &[(usize, usize, usize, usize)],
+) -> Vec<Vec<&'a mut [T]>> {
+  let mut rows: Vec<Vec<&mut [T]>> = rects
+    .iter()
+    .map(|&(_, _, _, height)| Vec::with_capacity(height))
+    .collect();
+  if row_length == 0 || cells.is_empty() {
+    return rows;
+  }
+
+  let height = cells.len() / row_length;
+  let mut bands = vec![Vec::new(); (height + band_height - 1) / band_height];
+  for (index, &(_, y, _, height)) in rects.iter().enumerate() {
+    if height == 0 {
+      continue;
+    }
+    for band in &mut bands[y / band_height..(y + height - 1) / band_height + 1]
+    {
+      band.push(index);
+    }
+  }
+  for band in &mut bands {
+    band.sort_by_key(|&index| rects[index].0);
+  }
+
+  for (y, row) in cells.chunks_mut(row_length).enumerate() {
+    let mut rest = row;
+    let mut offset = 0;
+    for &index in &bands[y / band_height] {
+      let (x, _, width, _) = rects[index];
+      let (_, tail) = mem::replace(&mut rest, &mut []).split_at_mut(x - offset);
+      let (part, tail) = tail.split_at_mut(width);
+      rows[index].push(part);
+      rest = tail;
+      offset = x + width;
+    }
+  }
+
+  rows
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate test;
+  use self::test::Bencher;
+
+  use super::*;
+
+  fn random_world(width: usize, height: usize) -> World {
+    let mut world = World::new(width, height);
+    let mut state = 0x2545_f491_u32;
+    for y in 0..height {
+      for x in 0..width {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        world.set(x, y, (state % 3 == 0) as State);
+      }
+    }
+    world
+  }
+
+  fn count_brute_force(world: &World, x: usize, y: usize, rule: &Rule) -> u32 {
+    let range = rule.range() as isize;
+    let mut count = 0;
+    for dy in -range..=range {
+      for dx in -range..=range {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        let in_neighborhood =
+          rule.neighborhood().contains(dx, dy, rule.range());
+        if in_neighborhood
+          && world.get_wrapped(nx, ny, rule.topology()) == ALIVE
+        {
+          count += 1;
+        }
+      }
+    }
+    count
+  }
+
+  #[test]
+  fn cells_are_stored() {
+    let mut world = World::new(70, 3);
+    world.set(0, 0, ALIVE);
+    world.set(69, 2, ALIVE);
+    world.set(64, 1, 3);
+    world.set(63, 1, ALIVE);
+    world.set(63, 1, 2);
+    world.set(0, 0, DEAD);
+
+    assert_eq!(world.get(0, 0), DEAD);
+    assert_eq!(world.get(69, 2), ALIVE);
+    assert_eq!(world.get(64, 1), 3);
+    assert_eq!(world.get(63, 1), 2);
+    assert_eq!(world.get(65, 1), DEAD);
+  }
+
+  #[test]
+  fn writing_into_sectors() {
+    let world = random_world(150, 100);
+    let sector = Sector::new(20, 30, 100, 40);
+
+    for &rule_str in &["B3/S23", "B2/S/C3", "R2,C0,M1,S5..8,B6..7"] {
+      let rule: Rule = rule_str.parse().unwrap();
+      let expected = world.next_generation(&sector, &rule);
+
+      let mut canvas = random_world(200, 80);
+      canvas.allocate_dying_cells();
+      let original = canvas.clone();
+      {
+        let sectors = [Sector::new(0, 0, 200, 80)];
+        let mut destination = canvas.split_mut(&sectors).pop().unwrap();
+        assert!(world.write_next_generation(
+          &sector,
+          &rule,
+          &mut destination,
+          64,
+          5
+        ));
+        assert!(!world.write_next_generation(
+          &sector,
+          &rule,
+          &mut destination,
+          64,
+          5
+        ));
+      }
+
+      for y in 0..80 {
+        for x in 0..200 {
+          let cell = match (x, y) {
+            (64..=163, 5..=44) => expected.get(x - 64, y - 5),
+            _ => original.get(x, y),
+          };
+          assert_eq!(canvas.get(x, y), cell, "{} {:?}", rule, (x, y));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn sectors_are_split() {
+    let mut world = World::new(300, 200);
+    world.allocate_dying_cells();
+    let sectors = [
+      Sector::new(0, 0, 128, 64),
+      Sector::new(128, 0, 172, 64),
+      Sector::new(0, 64, 300, 136),
+    ];
+
+    for (index, sector) in world.split_mut(&sectors).iter_mut().enumerate() {
+      assert_eq!(sector.alive.len(), sector.sector.height);
+      assert_eq!(sector.dying.len(), sector.sector.height);
+      assert_eq!(sector.changed_tiles.len(), tiles(sector.sector.height));
+
+      for y in 0..sector.sector.height {
+        for x in 0..sector.sector.width {
+          sector.set(x, y, ((x + y + index) % 3) as State);
+        }
+      }
+    }
+
+    for y in 0..world.height {
+      for x in 0..world.width {
+        let (sector_x, sector_y, index) = match (x, y) {
+          (0..=127, 0..=63) => (0, 0, 0),
+          (_, 0..=63) => (128, 0, 1),
+          _ => (0, 64, 2),
+        };
+        let expected = ((x - sector_x + y - sector_y + index) % 3) as State;
+        assert_eq!(world.get(x, y), expected, "{:?}", (x, y));
+      }
+    }
+  }
+
+  #[test]
+  fn stable_tiles_are_skipped() {
+    // a glider, a blinker and a block in different tiles of a 4x3 tiles world
+    let mut world = World::new(4 * TILE_SIZE, 3 * TILE_SIZE - 10);
+    for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      world.set(x + 10, y + 10, ALIVE);
+    }
+    for &(x, y) in &[(200, 100), (201, 100), (202, 100)] {
+      world.set(x, y, ALIVE);
+    }
+    for &(x, y) in &[(150, 170), (151, 170), (150, 171), (151, 171)] {
+      world.set(x, y, ALIVE);
+    }
+
+    for &rule_str in &["B3/S23", "B3/S23:T256,182", "B3/S23/C3"] {
+      let rule: Rule = rule_str.parse().unwrap();
+      let sectors = [Sector::new(0, 0, world.width, world.height)];
+      let mut expected = world.clone();
+      let (mut current, mut next) = (world.clone(), world.clone());
+      if rule.states() > 2 {
+        current.allocate_dying_cells();
+        next.allocate_dying_cells();
+      }
+
+      for generation in 0..20 {
+        let stats = {
+          let mut next_sector = next.split_mut(&sectors).pop().unwrap();
+          current.next_generation_into(&rule, &mut next_sector)
+        };
+        mem::swap(&mut current, &mut next);
+        expected = expected.next_generation(&sectors[0], &rule);
+
+        assert_eq!(current.hash(), expected.hash(), "{}", rule);
+        assert_eq!(stats.computed + stats.skipped, 12);
+        if generation >= 2 && rule_str == "B3/S23" {
+          // only the tiles around the glider are computed
+          assert_eq!(stats.computed, 4, "{}", rule);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn bit_counting() {
+    for n in 0..256_u64 {
+      // every bit of the words is a different combination of neighbors
+      let mut words = [0; 8];
+      for (i, word) in words.iter_mut().enumerate() {
+        if (n >> i) & 1 != 0 {
+          *word = 1 << 5;
+        }
+      }
+
+      let counts = count_neighbors(words);
+      for count in 0..=8 {
+        let has_count = (count_equals(counts, count) >> 5) & 1 != 0;
+        assert_eq!(has_count, count == n.count_ones() as usize);
+      }
+    }
+
+    let row = [0x8000_0000_0000_0001, 0x3];
+    assert_eq!(window(&row, -1), 0x2);
+    assert_eq!(window(&row, 63), 0x7);
+    assert_eq!(window(&row, 100), 0);
+  }
+
+  #[test]
+  fn bitwise_matches_cell_by_cell() {
+    let world = random_world(150, 40);
+
+    for sector in &[
+      Sector::new(0, 0, 150, 40),
+      Sector::new(37, 5, 100, 30),
+      Sector::new(64, 39, 86, 1),
+    ] {
+      for rule in &["B3/S23", "B36/S23", "B0/S8", "B2/S"] {
+        let rule: Rule = rule.parse().unwrap();
+        let bitwise = world.next_generation(sector, &rule);
+
+        let mut by_cells = World::new(sector.width, sector.height);
+        {
+          let sectors = [Sector::new(0, 0, sector.width, sector.height)];
+          let mut destination = by_cells.split_mut(&sectors).pop().unwrap();
+          let cells = Wrapped {
+            world: &world,
+            topology: rule.topology(),
+          };
+          next_generation_by_cells(
+            &cells,
+            sector,
+            &rule,
+            &mut destination,
+            0,
+            0,
+          );
+        }
+        assert_eq!(bitwise.hash(), by_cells.hash(), "{} in {:?}", rule, sector);
+      }
+    }
+  }
+
+  #[test]
+  fn larger_than_life_matches_brute_force() {
+    let world = random_world(40, 30);
+    let sector = Sector::new(3, 10, 30, 17);
+
+    for rule in &[
+      "R5,C0,M1,S34..58,B34..45",
+      "R3,C4,M0,S5..9,B4..7,NN",
+      "R2,C0,M1,S4..7,B5..6,NH",
+      "R2,C0,M0,S2..4,B3..3,N@a5a5a58",
+      "R5,C0,M1,S34..58,B34..45:T40,30",
+      "R3,C0,M0,S5..9,B4..7,NN:K40*,30",
+      "R2,C0,M1,S4..7,B5..6,NH:K40,30*",
+      "R4,C0,M0,S10..20,B9..12:C40,30",
+    ] {
+      let rule: Rule = rule.parse().unwrap();
+      let next_world = world.next_generation(&sector, &rule);
+
+      for y in 0..sector.height {
+        for x in 0..sector.width {
+          let (wx, wy) = (sector.x + x, sector.y + y);
+          let count = count_brute_force(&world, wx, wy, &rule);
+          let expected = rule.next_state_by_count(world.get(wx, wy), count);
+          assert_eq!(
+            next_world.get(x, y),
+            expected,
+            "{} at {:?}",
+            rule,
+            (x, y)
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn range_1_larger_than_life_is_life() {
+    let world = random_world(20, 20);
+    let sector = Sector::new(0, 0, 20, 20);
+
+    // also checks that both ways of computing generations wrap the same way
+    for grid in &[
+      "", ":P20,20", ":T20,20", ":K20*,20", ":K20,20*", ":C20,20", ":S20",
+    ] {
+      let life: Rule = format!("B3/S23{}", grid).parse().unwrap();
+      let ltl: Rule = format!("R1,M0,S2..3,B3..3{}", grid).parse().unwrap();
+      let life = world.next_generation(&sector, &life);
+      let ltl = world.next_generation(&sector, &ltl);
+      assert_eq!(life.hash(), ltl.hash(), "{}", grid);
+    }
+  }
+
+  #[test]
+  fn glider_on_torus() {
+    let rule = "B3/S23:T8,8".parse().unwrap();
+    let mut glider = World::new(8, 8);
+    for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+      glider.set(x, y, ALIVE);
+    }
+
+    // the glider moves by one cell diagonally every 4 generations, so it
+    // crosses the edges and returns to the same place after 32 generations
+    for generation in 1..32 {
+      assert_ne!(run(glider.clone(), &rule, generation).hash(), glider.hash());
+    }
+    assert_eq!(run(glider.clone(), &rule, 32).hash(), glider.hash());
+  }
+
+  fn run(world: World, rule: &Rule, generations: usize) -> World {
+    let sector = Sector::new(0, 0, world.width, world.height);
+    (0..generations)
+      .fold(world, |world, _| world.next_generation(&sector, rule))
+  }
+
+  fn pattern(cells: &[(isize, isize)]) -> World {
+    let mut world = World::new(12, 12);
+    for &(x, y) in cells {
+      world.set((x + 5) as usize, (y + 5) as usize, ALIVE);
+    }
+    world
+  }
+
+  #[test]
+  fn hexagonal_patterns() {
+    let rule = "B2/S34H".parse().unwrap();
+
+    // a ring of six cells around an empty cell oscillates with period 3
+    let ring = pattern(&[(0, 0), (1, 0), (0, 1), (2, 1), (1, 2), (2, 2)]);
+    assert_ne!(run(ring.clone(), &rule, 1).hash(), ring.hash());
+    assert_ne!(run(ring.clone(), &rule, 2).hash(), ring.hash());
+    assert_eq!(run(ring.clone(), &rule, 3).hash(), ring.hash());
+
+    // a triangle of three mutually adjacent cells oscillates with period 2
+    let triangle = pattern(&[(0, 0), (0, 1), (1, 1)]);
+    assert_ne!(run(triangle.clone(), &rule, 1).hash(), triangle.hash());
+    assert_eq!(run(triangle.clone(), &rule, 2).hash(), triangle.hash());
+
+    // a single cell gives birth to its six neighbors
+    let rule = "B1/SH".parse().unwrap();
+    let next = run(pattern(&[(0, 0)]), &rule, 1);
+    let neighbors =
+      pattern(&[(0, -1), (1, 0), (1, 1), (0, 1), (-1, 0), (-1, -1)]);
+    assert_eq!(next.hash(), neighbors.hash());
+  }
+
+  const BENCH_SIZE: usize = 10_000;
+
+  /// Creates a 10,000 x 10,000 world of random cells. The cells are random
+  /// words, setting them one by one would take longer than a generation.
+  fn bench_world() -> World {
+    let mut world = World::new(BENCH_SIZE, BENCH_SIZE);
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for word in &mut world.alive {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      *word = state;
+    }
+    // bits beyond the width are always zero
+    if BENCH_SIZE % WORD_BITS != 0 {
+      let words_per_row = world.words_per_row;
+      for row in world.alive.chunks_mut(words_per_row) {
+        *row.last_mut().unwrap() &= (1 << (BENCH_SIZE % WORD_BITS)) - 1;
+      }
+    }
+    world
+  }
+
+  /// Computes a generation of a 10,000 x 10,000 world of random cells on one
+  /// thread.
+  #[bench]
+  fn generation_10k(bencher: &mut Bencher) {
+    let world = bench_world();
+    let rule = Rule::default();
+    let sector = Sector::new(0, 0, BENCH_SIZE, BENCH_SIZE);
+    bencher.iter(|| world.next_generation(&sector, &rule));
+  }
+
+  /// Computes the same generation cell by cell, like rules which the bitwise
+  /// path doesn't support.
+  #[bench]
+  fn generation_10k_by_cells(bencher: &mut Bencher) {
+    let world = bench_world();
+    let rule = Rule::default();
+    let sector = Sector::new(0, 0, BENCH_SIZE, BENCH_SIZE);
+    let cells = Wrapped {
+      world: &world,
+      topology: rule.topology(),
+    };
+    bencher.iter(|| {
+      let mut next_world = World::new(BENCH_SIZE, BENCH_SIZE);
+      {
+        let sectors = [Sector::new(0, 0, BENCH_SIZE, BENCH_SIZE)];
+        let mut destination = next_world.split_mut(&sectors).pop().unwrap();
+        next_generation_by_cells(
+          &cells,
+          &sector,
+          &rule,
+          &mut destination,
+          0,
+          0,
+        );
+      }
+      next_world
+    });
+  }
+}
@@ -0,0 +1,267 @@
+//! How the world of a cluster is split into tiles. Every tile is computed by
+//! one slave, which only needs the tile's halo (the cells around the tile
+//! within the range of the rule) from the other slaves. The halos are built
+//! by the master from the borders of the tiles (the cells of a tile within
+//! the range from its edges), so only borders and halos are sent every
+//! generation. Both are sent as lists of cells in the order of [`border`] and
+//! [`halo`].
+//!
+//! Tiles of a bounded world are clipped to it, and the halos of the tiles at
+//! its edges are wrapped around according to the topology of the rule.
+//!
+//! [`border`]: struct.TileRect.html#method.border
+//! [`halo`]: struct.TileRect.html#method.halo
+
+use std::collections::HashMap;
+
+use failure::{err_msg, Error};
+
+use protocol::TileCells;
+use rule::State;
+use unbounded::world::{TileCoord, TILE_SIZE};
+
+/// The cells of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+  pub x: i64,
+  pub y: i64,
+  pub width: usize,
+  pub height: usize,
+}
+
+impl TileRect {
+  /// Returns the cells of a tile, clipped to the `size` of a bounded world.
+  pub fn of((tx, ty): TileCoord, size: Option<(usize, usize)>) -> Self {
+    let (x, y) = (tx * TILE_SIZE as i64, ty * TILE_SIZE as i64);
+    let clip = |start: i64, size: Option<usize>| {
+      size.map_or(TILE_SIZE, |size| {
+        debug_assert!(start >= 0 && start < size as i64, "outside the world");
+        (size - start as usize).min(TILE_SIZE)
+      })
+    };
+
+    TileRect {
+      x,
+      y,
+      width: clip(x, size.map(|(width, _)| width)),
+      height: clip(y, size.map(|(_, height)| height)),
+    }
+  }
+
+  /// Returns `true` if the cell `(x, y)` (relative to the tile) is in the
+  /// tile.
+  pub fn contains(&self, x: isize, y: isize) -> bool {
+    x >= 0 && y >= 0 && x < self.width as isize && y < self.height as isize
+  }
+
+  /// Returns the cells of the tile (relative to it) within `range` cells
+  /// from its edges, row by row.
+  pub fn border(&self, range: usize) -> Vec<(usize, usize)> {
+    let inner = self.border_inner(range);
+    ring(self.width, self.height, inner)
+  }
+
+  /// Returns the index of a cell in the [`border`], or `None` if it's too
+  /// far from the edges.
+  ///
+  /// [`border`]: #method.border
+  pub fn border_index(
+    &self,
+    range: usize,
+    x: usize,
+    y: usize,
+  ) -> Option<usize> {
+    ring_index(self.width, self.border_inner(range), x, y)
+  }
+
+  /// Returns the cells around the tile (relative to it) within `range` cells
+  /// from its edges, row by row.
+  pub fn halo(&self, range: usize) -> Vec<(isize, isize)> {
+    let r = range as isize;
+    let (width, height) = (self.width + 2 * range, self.height + 2 * range);
+    ring(width, height, (range, range, self.width, self.height))
+      .into_iter()
+      .map(|(x, y)| (x as isize - r, y as isize - r))
+      .collect()
+  }
+
+  /// Returns the index of a cell in the [`halo`], or `None` if the cell
+  /// isn't in the halo.
+  ///
+  /// [`halo`]: #method.halo
+  pub fn halo_index(&self, range: usize, x: isize, y: isize) -> Option<usize> {
+    let r = range as isize;
+    let (x, y) = (x + r, y + r);
+    let (width, height) = (self.width + 2 * range, self.height + 2 * range);
+    if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+      return None;
+    }
+
+    let inner = (range, range, self.width, self.height);
+    ring_index(width, inner, x as usize, y as usize)
+  }
+
+  /// Returns the part of the tile which isn't in the border.
+  fn border_inner(&self, range: usize) -> (usize, usize, usize, usize) {
+    if self.width <= 2 * range || self.height <= 2 * range {
+      (0, 0, 0, 0)
+    } else {
+      (
+        range,
+        range,
+        self.width - 2 * range,
+        self.height - 2 * range,
+      )
+    }
+  }
+}
+
+/// Checks that there's a halo of the right length for every tile, given as
+/// its coordinates and the length of its halo.
+pub fn check_halos<I>(
+  halos: TileCells,
+  tiles: I,
+) -> Result<HashMap<TileCoord, Vec<State>>, Error>
+where
+  I: Iterator<Item = (TileCoord, usize)>,
+{
+  let halos: HashMap<TileCoord, Vec<State>> = halos.into_iter().collect();
+  for (coord, length) in tiles {
+    match halos.get(&coord) {
+      Some(halo) if halo.len() == length => {}
+      _ => {
+        return Err(err_msg(format!(
+          "the halo of the tile {:?} is missing or isn't {} cells long",
+          coord, length
+        )))
+      }
+    }
+  }
+  Ok(halos)
+}
+
+/// Copies the cells of a tile in the region `(x, y, width, height)` into
+/// `cells`, reading them with `cell` (relative to the tile).
+pub fn copy_region<F>(
+  cells: &mut [State],
+  (x, y, width, height): (i64, i64, usize, usize),
+  rect: TileRect,
+  cell: F,
+) where
+  F: Fn(usize, usize) -> State,
+{
+  let x0 = x.max(rect.x);
+  let y0 = y.max(rect.y);
+  let x1 = (x + width as i64).min(rect.x + rect.width as i64);
+  let y1 = (y + height as i64).min(rect.y + rect.height as i64);
+
+  for cy in y0..y1 {
+    for cx in x0..x1 {
+      let state = cell((cx - rect.x) as usize, (cy - rect.y) as usize);
+      cells[(cx - x) as usize + (cy - y) as usize * width] = state;
+    }
+  }
+}
+
+/// Returns the bounds `(x0, y0, x1, y1)` (inclusive) which contain both
+/// bounds.
+pub fn union(
+  a: Option<(i64, i64, i64, i64)>,
+  b: Option<(i64, i64, i64, i64)>,
+) -> Option<(i64, i64, i64, i64)> {
+  match (a, b) {
+    (Some((ax0, ay0, ax1, ay1)), Some((bx0, by0, bx1, by1))) => {
+      Some((ax0.min(bx0), ay0.min(by0), ax1.max(bx1), ay1.max(by1)))
+    }
+    (a, b) => a.or(b),
+  }
+}
+
+pub fn not_assigned(tile: TileCoord) -> Error {
+  err_msg(format!("the tile {:?} isn't assigned to this slave", tile))
+}
+
+/// Returns the cells of a `width` x `height` rectangle which aren't in the
+/// rectangle `inner` (given as `(x, y, width, height)`), row by row.
+fn ring(
+  width: usize,
+  height: usize,
+  inner: (usize, usize, usize, usize),
+) -> Vec<(usize, usize)> {
+  let (x0, y0, inner_width, inner_height) = inner;
+  (0..height)
+    .flat_map(|y| (0..width).map(move |x| (x, y)))
+    .filter(|&(x, y)| {
+      x < x0 || y < y0 || x >= x0 + inner_width || y >= y0 + inner_height
+    })
+    .collect()
+}
+
+/// Returns the index of a cell in the [`ring`] of a `width` wide rectangle,
+/// or `None` if the cell is in `inner`.
+///
+/// [`ring`]: fn.ring.html
+fn ring_index(
+  width: usize,
+  (x0, y0, inner_width, inner_height): (usize, usize, usize, usize),
+  x: usize,
+  y: usize,
+) -> Option<usize> {
+  let ring_width = width - inner_width;
+  if y < y0 {
+    Some(x + y * width)
+  } else if y < y0 + inner_height {
+    let start = y0 * width + (y - y0) * ring_width;
+    if x < x0 {
+      Some(start + x)
+    } else if x >= x0 + inner_width {
+      Some(start + x - inner_width)
+    } else {
+      None
+    }
+  } else {
+    let start = y0 * width + inner_height * ring_width;
+    Some(start + x + (y - y0 - inner_height) * width)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn borders_and_halos() {
+    let tile = TileRect::of((-1, 2), None);
+    assert_eq!((tile.x, tile.y), (-64, 128));
+    let clipped = TileRect::of((1, 0), Some((100, 30)));
+    assert_eq!((clipped.x, clipped.width, clipped.height), (64, 36, 30));
+
+    for &(width, height, range) in &[(64, 64, 1), (64, 64, 10), (5, 3, 2)] {
+      let tile = TileRect {
+        width,
+        height,
+        ..tile
+      };
+
+      let border = tile.border(range);
+      for (index, &(x, y)) in border.iter().enumerate() {
+        assert_eq!(tile.border_index(range, x, y), Some(index));
+      }
+      let inner =
+        (width.saturating_sub(2 * range)) * (height.saturating_sub(2 * range));
+      assert_eq!(border.len(), width * height - inner);
+
+      let halo = tile.halo(range);
+      for (index, &(x, y)) in halo.iter().enumerate() {
+        assert!(!tile.contains(x, y));
+        assert_eq!(tile.halo_index(range, x, y), Some(index));
+      }
+      assert_eq!(
+        halo.len(),
+        (width + 2 * range) * (height + 2 * range) - width * height
+      );
+      assert_eq!(tile.halo_index(range, 0, 0), None);
+      assert_eq!(tile.halo_index(range, -(range as isize) - 1, 0), None);
+    }
+  }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+
+/// How the edges of a bounded world are joined, following Golly's bounded
+/// grids. Cells beyond the edges of a plane are always dead, on the other
+/// topologies they are mapped to cells on the opposite (or, for a sphere, the
+/// adjacent) edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topology {
+  /// Edges aren't joined.
+  Plane,
+  /// Opposite edges are joined.
+  Torus,
+  /// Opposite edges are joined, one pair of them with a twist.
+  KleinBottle(Twist),
+  /// Opposite edges are joined, both pairs with a twist.
+  CrossSurface,
+  /// The top edge is joined to the left edge and the bottom edge to the
+  /// right one. The world must be square.
+  Sphere,
+}
+
+/// The pair of edges of a Klein bottle which is joined with a twist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Twist {
+  TopBottom,
+  LeftRight,
+}
+
+impl Topology {
+  /// Maps a cell at `(x, y)` (which may be outside of the world, but not
+  /// further than the size of the world) to the cell of a `width` x `height`
+  /// world it corresponds to, or returns `None` if the cell is always dead.
+  pub fn wrap(
+    self,
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+  ) -> Option<(usize, usize)> {
+    let (w, h) = (width as isize, height as isize);
+    let inside = |x: isize, y: isize| x >= 0 && x < w && y >= 0 && y < h;
+    if inside(x, y) {
+      return Some((x as usize, y as usize));
+    }
+
+    let (x, y) = match self {
+      Topology::Plane => return None,
+      Topology::Torus => (wrap(x, w).1, wrap(y, h).1),
+      Topology::KleinBottle(twist) => {
+        let ((x_laps, x), (y_laps, y)) = (wrap(x, w), wrap(y, h));
+        match twist {
+          Twist::TopBottom if y_laps % 2 != 0 => (w - 1 - x, y),
+          Twist::LeftRight if x_laps % 2 != 0 => (x, h - 1 - y),
+          _ => (x, y),
+        }
+      }
+      Topology::CrossSurface => {
+        let ((x_laps, x), (y_laps, y)) = (wrap(x, w), wrap(y, h));
+        let x = if y_laps % 2 != 0 { w - 1 - x } else { x };
+        let y = if x_laps % 2 != 0 { h - 1 - y } else { y };
+        (x, y)
+      }
+      Topology::Sphere => {
+        // cells beyond a corner don't correspond to any cell
+        let x_outside = x < 0 || x >= w;
+        let y_outside = y < 0 || y >= h;
+        if x_outside && y_outside {
+          return None;
+        }
+
+        if y < 0 {
+          (-y - 1, x)
+        } else if y >= h {
+          (w - 1 - (y - h), x)
+        } else if x < 0 {
+          (y, -x - 1)
+        } else {
+          (y, h - 1 - (x - w))
+        }
+      }
+    };
+
+    if inside(x, y) {
+      Some((x as usize, y as usize))
+    } else {
+      None
+    }
+  }
+}
+
+/// Returns how many times `value` has crossed the edges of `0..size` and the
+/// value wrapped into it.
+fn wrap(value: isize, size: isize) -> (isize, isize) {
+  let laps = if value < 0 {
+    (value + 1) / size - 1
+  } else {
+    value / size
+  };
+  (laps, value - laps * size)
+}
+
+/// A bounded world of a rule, written as a suffix of the rule like
+/// `:T200,100`: a letter for the topology (`P` for a plane, `T` for a torus,
+/// `K` for a Klein bottle, `C` for a cross-surface, `S` for a sphere) followed
+/// by the width and the height. The twisted edges of a Klein bottle are
+/// marked with `*` after their length (`:K200*,100` twists the top and
+/// bottom edges), a sphere has only one dimension (`:S100`).
+///
+/// Golly's infinite dimensions (a length of 0 like `:T0,100`) and shifted
+/// edges of tori and Klein bottles (`:T200+5,100`) aren't supported, because
+/// every bounded engine needs a finite world whose edges meet cell to cell.
+/// They are rejected with errors which say so.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoundedGrid {
+  pub topology: Topology,
+  pub width: usize,
+  pub height: usize,
+}
+
+impl BoundedGrid {
+  pub fn parse(s: &str) -> Result<BoundedGrid, &'static str> {
+    fn parse_size(s: &str) -> Result<usize, &'static str> {
+      match s.parse() {
+        Ok(0) => Err(
+          "infinite dimensions of bounded grids (like T0,100) aren't supported",
+        ),
+        Ok(size) => Ok(size),
+        _ => Err("expected a positive size of a bounded grid"),
+      }
+    }
+
+    if s.contains('+') || s.contains('-') {
+      return Err(
+        "shifted edges of bounded grids (like T200+5,100) aren't supported",
+      );
+    }
+
+    let mut chars = s.chars();
+    let letter = chars.next().map(|c| c.to_ascii_uppercase());
+    let sizes: Vec<&str> = chars.as_str().split(',').collect();
+
+    let (topology, width, height) = match (letter, &sizes[..]) {
+      (Some('S'), &[size]) => (Topology::Sphere, size, size),
+      (Some('K'), &[width, height]) => {
+        let twist = match (width.ends_with('*'), height.ends_with('*')) {
+          (true, false) => Twist::TopBottom,
+          (false, true) => Twist::LeftRight,
+          _ => {
+            return Err("expected exactly one twisted edge in a Klein bottle")
+          }
+        };
+        let width = width.trim_end_matches('*');
+        let height = height.trim_end_matches('*');
+        (Topology::KleinBottle(twist), width, height)
+      }
+      (Some(letter), &[width, height]) => {
+        let topology = match letter {
+          'P' => Topology::Plane,
+          'T' => Topology::Torus,
+          'C' => Topology::CrossSurface,
+          _ => return Err("expected a bounded grid like T200,100"),
+        };
+        (topology, width, height)
+      }
+      _ => return Err("expected a bounded grid like T200,100"),
+    };
+
+    Ok(BoundedGrid {
+      topology,
+      width: parse_size(width)?,
+      height: parse_size(height)?,
+    })
+  }
+}
+
+impl fmt::Display for BoundedGrid {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (w, h) = (self.width, self.height);
+    match self.topology {
+      Topology::Plane => write!(f, "P{},{}", w, h),
+      Topology::Torus => write!(f, "T{},{}", w, h),
+      Topology::KleinBottle(Twist::TopBottom) => write!(f, "K{}*,{}", w, h),
+      Topology::KleinBottle(Twist::LeftRight) => write!(f, "K{},{}*", w, h),
+      Topology::CrossSurface => write!(f, "C{},{}", w, h),
+      Topology::Sphere => write!(f, "S{}", w),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_and_display() {
+    for grid in &["P20,10", "T200,100", "K20*,10", "K20,10*", "C5,7", "S30"] {
+      assert_eq!(BoundedGrid::parse(grid).unwrap().to_string(), *grid);
+    }
+    assert_eq!(BoundedGrid::parse("t3,4").unwrap().to_string(), "T3,4");
+
+    for grid in &["", "T", "T3", "T,4", "S3,4", "K3,4", "K3*,4*", "X3,4"] {
+      assert!(BoundedGrid::parse(grid).is_err(), "{:?} was parsed", grid);
+    }
+
+    for grid in &["T0,4", "P4,0", "S0", "K0*,4"] {
+      let error = BoundedGrid::parse(grid).unwrap_err();
+      assert!(error.starts_with("infinite dimensions"), "{}", grid);
+    }
+    for grid in &["T200+5,100", "T200,100-3", "K20*+1,10"] {
+      let error = BoundedGrid::parse(grid).unwrap_err();
+      assert!(error.starts_with("shifted edges"), "{}", grid);
+    }
+  }
+
+  #[test]
+  fn wrap_laps() {
+    assert_eq!(wrap(-1, 5), (-1, 4));
+    assert_eq!(wrap(-5, 5), (-1, 0));
+    assert_eq!(wrap(-6, 5), (-2, 4));
+    assert_eq!(wrap(5, 5), (1, 0));
+    assert_eq!(wrap(3, 5), (0, 3));
+  }
+
+  #[test]
+  fn wrapping() {
+    let (w, h) = (5, 4);
+    let wrap = |topology: Topology, x, y| topology.wrap(x, y, w, h);
+
+    assert_eq!(wrap(Topology::Plane, 2, 3), Some((2, 3)));
+    assert_eq!(wrap(Topology::Plane, -1, 0), None);
+
+    assert_eq!(wrap(Topology::Torus, -1, 0), Some((4, 0)));
+    assert_eq!(wrap(Topology::Torus, 5, -1), Some((0, 3)));
+
+    let klein = Topology::KleinBottle(Twist::TopBottom);
+    assert_eq!(wrap(klein, 1, -1), Some((3, 3)));
+    assert_eq!(wrap(klein, -1, 1), Some((4, 1)));
+    let klein = Topology::KleinBottle(Twist::LeftRight);
+    assert_eq!(wrap(klein, 1, -1), Some((1, 3)));
+    assert_eq!(wrap(klein, -1, 1), Some((4, 2)));
+
+    assert_eq!(wrap(Topology::CrossSurface, 1, 4), Some((3, 0)));
+    assert_eq!(wrap(Topology::CrossSurface, 5, 0), Some((0, 3)));
+
+    let sphere = |x, y| Topology::Sphere.wrap(x, y, 4, 4);
+    assert_eq!(sphere(2, -1), Some((0, 2)));
+    assert_eq!(sphere(-1, 2), Some((2, 0)));
+    assert_eq!(sphere(1, 4), Some((3, 1)));
+    assert_eq!(sphere(4, 1), Some((1, 3)));
+    assert_eq!(sphere(-1, -1), None);
+  }
+}
@@ -0,0 +1,356 @@
+//! An interactive terminal UI for any [`Engine`]: the world is drawn in a
+//! viewport which fits the terminal and can be panned and zoomed out, with
+//! a status bar below it.
+//!
+//! | Key                  | Action                                    |
+//! |----------------------|-------------------------------------------|
+//! | arrows, `h j k l`    | pan                                       |
+//! | `-` / `+`            | zoom out (half blocks, braille, density)  |
+//! | space                | pause or resume                           |
+//! | `n`                  | pause and compute the next step           |
+//! | `f` / `s`            | run faster or slower                      |
+//! | `c`                  | center on the initial pattern             |
+//! | `q`, Esc, Ctrl-C     | quit                                      |
+//!
+//! [`Engine`]: ../engine/trait.Engine.html
+
+extern crate termion;
+use self::termion::event::Key;
+use self::termion::input::TermRead;
+use self::termion::raw::{IntoRawMode, RawTerminal};
+use self::termion::screen::AlternateScreen;
+use self::termion::{clear, cursor, style};
+
+use failure::Error;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use engine::Engine;
+use rule::Rule;
+
+pub mod view;
+use self::view::{Viewport, Zoom};
+
+/// Zooming out is refused when the viewport would have to read more cells
+/// than this from the engine every frame.
+const MAX_REGION_CELLS: usize = 1 << 22;
+
+/// Delays between steps which are shorter than this are skipped, so the
+/// fastest speed is as fast as the engine can go.
+const MIN_DELAY_MILLIS: u64 = 10;
+const MAX_DELAY_MILLIS: u64 = 2000;
+
+/// The world is redrawn at most once per frame.
+const FRAME_MILLIS: u64 = 33;
+
+/// How often the speed in the status bar is measured.
+const RATE_INTERVAL_MILLIS: u64 = 1000;
+
+/// Returns `true` if the standard output is a terminal the UI can draw in.
+pub fn is_available() -> bool {
+  termion::is_tty(&io::stdout())
+}
+
+/// Runs `engine`, which has been loaded with a pattern of `size` cells at the
+/// origin, `generations` at a time until the user quits.
+pub fn run(
+  engine: &mut dyn Engine,
+  rule: Rule,
+  (width, height): (usize, usize),
+  generations: u64,
+) -> Result<(), Error> {
+  let mut terminal = Terminal::new()?;
+  let mut keys = termion::async_stdin().keys();
+
+  let home = (width as i64 / 2, height as i64 / 2);
+  let mut session = Session::new(home);
+  let mut rate = Rate::new(engine.generation());
+  let mut last_step: Option<Instant> = None;
+  let mut last_frame: Option<Instant> = None;
+  let mut dirty = true;
+  let mut last_size = None;
+  let frame = Duration::from_millis(FRAME_MILLIS);
+
+  while !session.quit {
+    let size = terminal.view_size()?;
+    if last_size != Some(size) {
+      last_size = Some(size);
+      dirty = true;
+    }
+    for key in keys.by_ref() {
+      session.handle_key(key?, size);
+      dirty = true;
+    }
+
+    let step_due =
+      last_step.map_or(true, |last_step| last_step.elapsed() >= session.delay);
+    if session.steps > 0 || (!session.paused && step_due) {
+      engine.step(generations)?;
+      session.steps = session.steps.saturating_sub(1);
+      last_step = Some(Instant::now());
+      dirty = true;
+    }
+    rate.update(engine.generation());
+
+    let frame_due =
+      last_frame.map_or(true, |last_frame| last_frame.elapsed() >= frame);
+    if dirty && frame_due {
+      terminal.draw(&session, &*engine, &rule, rate.per_second)?;
+      last_frame = Some(Instant::now());
+      dirty = false;
+    } else if session.paused || !step_due {
+      // nothing to compute until the next key or step
+      thread::sleep(Duration::from_millis(5));
+    }
+  }
+
+  Ok(())
+}
+
+/// The state of the UI which is changed with the keyboard.
+struct Session {
+  viewport: Viewport,
+  /// Center of the initial pattern.
+  home: (i64, i64),
+  paused: bool,
+  /// Steps to compute even though the simulation is paused.
+  steps: u64,
+  /// Delay between steps, 0 runs as fast as possible.
+  delay: Duration,
+  quit: bool,
+}
+
+impl Session {
+  fn new(home: (i64, i64)) -> Self {
+    Session {
+      viewport: Viewport {
+        center: home,
+        zoom: Zoom::Cells,
+      },
+      home,
+      paused: false,
+      steps: 0,
+      delay: Duration::from_millis(0),
+      quit: false,
+    }
+  }
+
+  /// Handles a key pressed while the viewport is `size` characters big.
+  fn handle_key(&mut self, key: Key, size: (usize, usize)) {
+    match key {
+      Key::Left | Key::Char('h') => self.viewport.pan(-1, 0, size),
+      Key::Right | Key::Char('l') => self.viewport.pan(1, 0, size),
+      Key::Up | Key::Char('k') => self.viewport.pan(0, -1, size),
+      Key::Down | Key::Char('j') => self.viewport.pan(0, 1, size),
+      Key::Char('+') | Key::Char('=') => {
+        if let Some(zoom) = self.viewport.zoom.zoom_in() {
+          self.viewport.zoom = zoom;
+        }
+      }
+      Key::Char('-') => {
+        if let Some(zoom) = self.viewport.zoom.zoom_out() {
+          let zoomed_out = Viewport {
+            zoom,
+            ..self.viewport
+          };
+          let (_, (width, height)) = zoomed_out.region(size);
+          if width * height <= MAX_REGION_CELLS {
+            self.viewport = zoomed_out;
+          }
+        }
+      }
+      Key::Char('c') => self.viewport.center = self.home,
+      Key::Char(' ') => self.paused = !self.paused,
+      Key::Char('n') => {
+        self.paused = true;
+        self.steps += 1;
+      }
+      Key::Char('f') => {
+        self.delay /= 2;
+        if self.delay < Duration::from_millis(MIN_DELAY_MILLIS) {
+          self.delay = Duration::from_millis(0);
+        }
+      }
+      Key::Char('s') => {
+        self.delay = if self.delay < Duration::from_millis(MIN_DELAY_MILLIS) {
+          Duration::from_millis(MIN_DELAY_MILLIS)
+        } else {
+          (self.delay * 2).min(Duration::from_millis(MAX_DELAY_MILLIS))
+        };
+      }
+      Key::Char('q') | Key::Esc | Key::Ctrl('c') => self.quit = true,
+      _ => {}
+    }
+  }
+
+  /// Describes the speed of the simulation for the status bar.
+  fn speed(&self) -> String {
+    if self.paused {
+      "paused".to_owned()
+    } else if self.delay < Duration::from_millis(MIN_DELAY_MILLIS) {
+      "max speed".to_owned()
+    } else {
+      let delay = self.delay;
+      let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+      format!("{} ms/step", millis)
+    }
+  }
+}
+
+/// Measures how many generations are computed per second.
+struct Rate {
+  generation: u64,
+  since: Instant,
+  per_second: f64,
+}
+
+impl Rate {
+  fn new(generation: u64) -> Self {
+    Rate {
+      generation,
+      since: Instant::now(),
+      per_second: 0.0,
+    }
+  }
+
+  fn update(&mut self, generation: u64) {
+    let elapsed = self.since.elapsed();
+    if elapsed >= Duration::from_millis(RATE_INTERVAL_MILLIS) {
+      let seconds =
+        elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+      self.per_second = (generation - self.generation) as f64 / seconds;
+      self.generation = generation;
+      self.since = Instant::now();
+    }
+  }
+}
+
+/// The terminal in raw mode on the alternate screen. The terminal is
+/// restored when it's dropped, even if the engine fails.
+struct Terminal {
+  screen: AlternateScreen<RawTerminal<Stdout>>,
+}
+
+impl Terminal {
+  fn new() -> Result<Self, Error> {
+    let mut screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+    write!(screen, "{}{}", cursor::Hide, clear::All)?;
+    Ok(Terminal { screen })
+  }
+
+  /// Returns the size of the viewport, the terminal without the status bar.
+  fn view_size(&self) -> Result<(usize, usize), Error> {
+    let (columns, rows) = termion::terminal_size()?;
+    Ok((usize::from(columns), usize::from(rows).saturating_sub(1)))
+  }
+
+  fn draw(
+    &mut self,
+    session: &Session,
+    engine: &dyn Engine,
+    rule: &Rule,
+    rate: f64,
+  ) -> Result<(), Error> {
+    let (columns, rows) = self.view_size()?;
+    let mut frame = String::new();
+
+    if columns > 0 && rows > 0 {
+      let lines =
+        session
+          .viewport
+          .draw(engine, (columns, rows), rule.states())?;
+      for (row, line) in lines.iter().enumerate() {
+        write!(frame, "{}{}", cursor::Goto(1, row as u16 + 1), line)?;
+      }
+    }
+
+    let (x, y) = session.viewport.center;
+    let status = format!(
+      " generation #{} | population {} | {:.1} gen/s | {} | {} | zoom {} | \
+       ({}, {}) | space pause, n step, f/s speed, +/- zoom, arrows pan, \
+       q quit",
+      engine.generation(),
+      engine.population()?,
+      rate,
+      rule,
+      session.speed(),
+      session.viewport.zoom,
+      x,
+      y,
+    );
+    write!(
+      frame,
+      "{}{}{:width$.width$}{}",
+      cursor::Goto(1, rows as u16 + 1),
+      style::Invert,
+      status,
+      style::Reset,
+      width = columns
+    )?;
+
+    self.screen.write_all(frame.as_bytes())?;
+    self.screen.flush()?;
+    Ok(())
+  }
+}
+
+impl Drop for Terminal {
+  fn drop(&mut self) {
+    let _ = write!(self.screen, "{}", cursor::Show);
+    let _ = self.screen.flush();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keys() {
+    let size = (80, 24);
+    let mut session = Session::new((100, 25));
+    assert_eq!(session.speed(), "max speed");
+
+    session.handle_key(Key::Right, size);
+    session.handle_key(Key::Char('k'), size);
+    assert_eq!(session.viewport.center, (120, 19));
+    session.handle_key(Key::Char('c'), size);
+    assert_eq!(session.viewport.center, (100, 25));
+
+    session.handle_key(Key::Char('+'), size);
+    assert_eq!(session.viewport.zoom, Zoom::Cells);
+    for _ in 0..20 {
+      session.handle_key(Key::Char('-'), size);
+    }
+    // 80 x 24 characters of 64 x 128 cells are too many to read
+    assert_eq!(session.viewport.zoom, Zoom::Density(5));
+
+    session.handle_key(Key::Char(' '), size);
+    assert_eq!(session.speed(), "paused");
+    session.handle_key(Key::Char(' '), size);
+    session.handle_key(Key::Char('n'), size);
+    session.handle_key(Key::Char('n'), size);
+    assert!(session.paused);
+    assert_eq!(session.steps, 2);
+
+    session.handle_key(Key::Char('s'), size);
+    session.handle_key(Key::Char('s'), size);
+    assert_eq!(session.delay, Duration::from_millis(2 * MIN_DELAY_MILLIS));
+    for _ in 0..20 {
+      session.handle_key(Key::Char('s'), size);
+    }
+    assert_eq!(session.delay, Duration::from_millis(MAX_DELAY_MILLIS));
+    session.paused = false;
+    assert_eq!(session.speed(), "2000 ms/step");
+    for _ in 0..20 {
+      session.handle_key(Key::Char('f'), size);
+    }
+    assert_eq!(session.speed(), "max speed");
+
+    assert!(!session.quit);
+    session.handle_key(Key::Ctrl('c'), size);
+    assert!(session.quit);
+  }
+}
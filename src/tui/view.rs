@@ -0,0 +1,272 @@
+use failure::Error;
+use std::fmt;
+
+use engine::Engine;
+use rule::{State, ALIVE};
+use utils::render::TerminalCell;
+
+/// Characters of zoomed out views from empty to full.
+const DENSITY_CHARS: [char; 10] =
+  [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// Characters of two cells, one above the other: none, the top one, the
+/// bottom one or both of them alive.
+const HALF_BLOCKS: [char; 4] = [' ', '▀', '▄', '█'];
+
+/// Bits of the dots of a braille pattern by their position in the 2 x 4
+/// block of cells.
+const BRAILLE_DOTS: [[u32; 2]; 4] =
+  [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Density views show at most `2^MAX_DENSITY_LOG2` cells per column.
+const MAX_DENSITY_LOG2: u8 = 8;
+
+/// How many cells every character of the terminal shows. Characters are
+/// about twice as tall as they are wide, so zoomed out characters show
+/// twice as many rows as columns of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zoom {
+  /// A character per cell, dying cells are colored.
+  Cells,
+  /// Two cells per character drawn with half blocks.
+  HalfBlocks,
+  /// 2 x 4 cells per character drawn with braille patterns.
+  Braille,
+  /// `2^k` x `2^(k + 1)` cells per character, which are as dense as the
+  /// alive cells among them.
+  Density(u8),
+}
+
+impl Zoom {
+  /// Returns the width and the height of the block of cells a character
+  /// shows.
+  pub fn block_size(self) -> (usize, usize) {
+    match self {
+      Zoom::Cells => (1, 1),
+      Zoom::HalfBlocks => (1, 2),
+      Zoom::Braille => (2, 4),
+      Zoom::Density(log2) => (1 << log2, 2 << log2),
+    }
+  }
+
+  pub fn zoom_in(self) -> Option<Zoom> {
+    match self {
+      Zoom::Cells => None,
+      Zoom::HalfBlocks => Some(Zoom::Cells),
+      Zoom::Braille => Some(Zoom::HalfBlocks),
+      Zoom::Density(2) => Some(Zoom::Braille),
+      Zoom::Density(log2) => Some(Zoom::Density(log2 - 1)),
+    }
+  }
+
+  pub fn zoom_out(self) -> Option<Zoom> {
+    match self {
+      Zoom::Cells => Some(Zoom::HalfBlocks),
+      Zoom::HalfBlocks => Some(Zoom::Braille),
+      Zoom::Braille => Some(Zoom::Density(2)),
+      Zoom::Density(MAX_DENSITY_LOG2) => None,
+      Zoom::Density(log2) => Some(Zoom::Density(log2 + 1)),
+    }
+  }
+
+  /// Draws the cells of a `width` x `height` region (given row by row), whose
+  /// size is a multiple of the [`block_size`], into lines of characters.
+  /// Only [`Cells`] show the states of dying cells, other zooms only show
+  /// alive cells.
+  ///
+  /// [`block_size`]: #method.block_size
+  /// [`Cells`]: #variant.Cells
+  pub fn draw(
+    self,
+    cells: &[State],
+    width: usize,
+    height: usize,
+    states: u8,
+  ) -> Vec<String> {
+    let (block_width, block_height) = self.block_size();
+    assert_eq!(cells.len(), width * height);
+    assert!(width % block_width == 0 && height % block_height == 0);
+
+    let alive = |x: usize, y: usize| cells[x + y * width] == ALIVE;
+    (0..height / block_height)
+      .map(|row| {
+        let (top, columns) = (row * block_height, width / block_width);
+        (0..columns)
+          .map(|column| {
+            let left = column * block_width;
+            match self {
+              Zoom::Cells => TerminalCell {
+                state: cells[left + top * width],
+                states,
+              }
+              .to_string(),
+              Zoom::HalfBlocks => {
+                let index = alive(left, top) as usize
+                  | (alive(left, top + 1) as usize) << 1;
+                HALF_BLOCKS[index].to_string()
+              }
+              Zoom::Braille => {
+                let mut dots = 0;
+                for (y, row_dots) in BRAILLE_DOTS.iter().enumerate() {
+                  for (x, &dot) in row_dots.iter().enumerate() {
+                    if alive(left + x, top + y) {
+                      dots |= dot;
+                    }
+                  }
+                }
+                braille(dots).to_string()
+              }
+              Zoom::Density(_) => {
+                let count = (top..top + block_height)
+                  .flat_map(|y| (left..left + block_width).map(move |x| (x, y)))
+                  .filter(|&(x, y)| alive(x, y))
+                  .count();
+                density(count, block_width * block_height).to_string()
+              }
+            }
+          })
+          .collect()
+      })
+      .collect()
+  }
+}
+
+impl fmt::Display for Zoom {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (width, height) = self.block_size();
+    write!(f, "{}x{}", width, height)
+  }
+}
+
+fn braille(dots: u32) -> char {
+  if dots == 0 {
+    ' '
+  } else {
+    ::std::char::from_u32(0x2800 + dots).unwrap()
+  }
+}
+
+/// Returns the character of `alive` cells out of `total`, any alive cell is
+/// visible.
+fn density(alive: usize, total: usize) -> char {
+  let levels = DENSITY_CHARS.len() - 1;
+  DENSITY_CHARS[(alive * levels + total - 1) / total]
+}
+
+/// The part of the world which is shown in the terminal, the region around
+/// `center` which fills `columns` x `rows` characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+  pub center: (i64, i64),
+  pub zoom: Zoom,
+}
+
+impl Viewport {
+  /// Returns the position and the size of the region which fills `columns` x
+  /// `rows` characters.
+  pub fn region(
+    &self,
+    (columns, rows): (usize, usize),
+  ) -> ((i64, i64), (usize, usize)) {
+    let (block_width, block_height) = self.zoom.block_size();
+    let (width, height) = (columns * block_width, rows * block_height);
+    let (x, y) = self.center;
+    (
+      (x - width as i64 / 2, y - height as i64 / 2),
+      (width, height),
+    )
+  }
+
+  /// Moves the viewport by `dx` and `dy` quarters of its size, but at least
+  /// a character.
+  pub fn pan(&mut self, dx: i64, dy: i64, size: (usize, usize)) {
+    let (_, (width, height)) = self.region(size);
+    let (block_width, block_height) = self.zoom.block_size();
+    let step = |length: usize, block: usize| (length / 4).max(block) as i64;
+    self.center.0 += dx * step(width, block_width);
+    self.center.1 += dy * step(height, block_height);
+  }
+
+  /// Reads the region from `engine` and draws it.
+  pub fn draw(
+    &self,
+    engine: &dyn Engine,
+    size: (usize, usize),
+    states: u8,
+  ) -> Result<Vec<String>, Error> {
+    let ((x, y), (width, height)) = self.region(size);
+    let cells = engine.read_region(x, y, width, height)?;
+    Ok(self.zoom.draw(&cells, width, height, states))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rule::DEAD;
+
+  fn parse(rows: &[&str]) -> Vec<State> {
+    rows
+      .iter()
+      .flat_map(|row| row.chars())
+      .map(|c| if c == 'x' { ALIVE } else { DEAD })
+      .collect()
+  }
+
+  #[test]
+  fn zooms() {
+    let mut zoom = Zoom::Cells;
+    let mut sizes = vec![zoom.block_size()];
+    while let Some(next) = zoom.zoom_out() {
+      assert_eq!(next.zoom_in(), Some(zoom));
+      zoom = next;
+      sizes.push(zoom.block_size());
+    }
+    assert_eq!(&sizes[..5], &[(1, 1), (1, 2), (2, 4), (4, 8), (8, 16)]);
+    assert_eq!(zoom, Zoom::Density(MAX_DENSITY_LOG2));
+    assert_eq!(Zoom::Cells.zoom_in(), None);
+    assert_eq!(Zoom::Braille.to_string(), "2x4");
+  }
+
+  #[test]
+  fn drawing() {
+    let cells = parse(&[
+      "x...xx.x", //
+      ".x..xx.x", "x.x.xx..", "....xxx.",
+    ]);
+
+    assert_eq!(Zoom::Cells.draw(&cells[..8], 8, 1, 2), vec!["x   xx x"]);
+    assert_eq!(
+      Zoom::HalfBlocks.draw(&cells, 8, 4, 2),
+      vec!["▀▄  ██ █", "▀ ▀ ██▄ "]
+    );
+    assert_eq!(Zoom::Braille.draw(&cells, 8, 4, 2), vec!["⠕⠄⣿⡘"]);
+
+    let mut cells = vec![DEAD; 4 * 8];
+    assert_eq!(Zoom::Density(2).draw(&cells, 4, 8, 2), vec![" "]);
+    cells[5] = ALIVE;
+    assert_eq!(Zoom::Density(2).draw(&cells, 4, 8, 2), vec!["."]);
+    let cells = vec![ALIVE; 4 * 8];
+    assert_eq!(Zoom::Density(2).draw(&cells, 4, 8, 2), vec!["@"]);
+  }
+
+  #[test]
+  fn viewport() {
+    let mut viewport = Viewport {
+      center: (100, 25),
+      zoom: Zoom::Cells,
+    };
+    assert_eq!(viewport.region((80, 24)), ((60, 13), (80, 24)));
+
+    viewport.pan(1, -1, (80, 24));
+    assert_eq!(viewport.center, (120, 19));
+
+    viewport.zoom = Zoom::Braille;
+    assert_eq!(viewport.region((80, 24)), ((40, -29), (160, 96)));
+    viewport.pan(-1, 0, (80, 24));
+    assert_eq!(viewport.center, (80, 19));
+    // small viewports move by at least a character
+    viewport.pan(0, 1, (1, 1));
+    assert_eq!(viewport.center, (80, 23));
+  }
+}
@@ -0,0 +1,128 @@
+use failure::{err_msg, Error};
+
+use super::log;
+use engine::Engine;
+use rule::{Rule, State};
+
+pub mod world;
+use self::world::TiledWorld;
+
+/// Size of the random soup the simulation starts with.
+pub const SOUP_WIDTH: usize = 200;
+pub const SOUP_HEIGHT: usize = 50;
+
+/// The unbounded backend: a world of tiles which are allocated as the
+/// pattern grows.
+pub struct UnboundedEngine {
+  rule: Rule,
+  world: TiledWorld,
+  generation: u64,
+}
+
+impl UnboundedEngine {
+  pub fn new(rule: Rule) -> Result<Self, Error> {
+    if let Some(grid) = rule.grid() {
+      return Err(err_msg(format!(
+        "an unbounded world can't have a bounded grid, but {} has :{}",
+        rule, grid
+      )));
+    }
+
+    Ok(UnboundedEngine {
+      rule,
+      world: TiledWorld::new(),
+      generation: 0,
+    })
+  }
+}
+
+impl Engine for UnboundedEngine {
+  fn load(
+    &mut self,
+    width: usize,
+    height: usize,
+    cells: &[State],
+  ) -> Result<(), Error> {
+    self.world = TiledWorld::new();
+    for y in 0..height {
+      for x in 0..width {
+        self.world.set(x as i64, y as i64, cells[x + y * width]);
+      }
+    }
+
+    self.generation = 0;
+    Ok(())
+  }
+
+  fn step(&mut self, generations: u64) -> Result<(), Error> {
+    for _ in 0..generations {
+      self.world.next_generation(&self.rule);
+      self.generation += 1;
+    }
+
+    if log_enabled!(log::Level::Debug) {
+      debug!(
+        target: "unbounded",
+        "generation #{}: {} tiles, bounds {:?}",
+        self.generation,
+        self.world.tiles().len(),
+        self.world.bounds()
+      );
+    }
+    Ok(())
+  }
+
+  fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  fn read_region(
+    &self,
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+  ) -> Result<Vec<State>, Error> {
+    let mut cells = Vec::with_capacity(width * height);
+    for y in y..y + height as i64 {
+      for x in x..x + width as i64 {
+        cells.push(self.world.get(x, y));
+      }
+    }
+    Ok(cells)
+  }
+
+  fn population(&self) -> Result<u64, Error> {
+    Ok(self.world.population() as u64)
+  }
+
+  fn hash(&self) -> Result<u64, Error> {
+    Ok(self.world.hash())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rule::{ALIVE, DEAD};
+
+  #[test]
+  fn blinker_oscillates() {
+    assert!(UnboundedEngine::new("B3/S23:T10,10".parse().unwrap()).is_err());
+
+    let mut engine = UnboundedEngine::new(Rule::default()).unwrap();
+    let blinker = [DEAD, ALIVE, DEAD, DEAD, ALIVE, DEAD, DEAD, ALIVE, DEAD];
+    engine.load(3, 3, &blinker).unwrap();
+    engine.step(3).unwrap();
+    assert_eq!(engine.generation(), 3);
+    assert_eq!(engine.population().unwrap(), 3);
+    assert_eq!(
+      engine.read_region(-1, 0, 5, 3).unwrap(),
+      vec![
+        DEAD, DEAD, DEAD, DEAD, DEAD, //
+        DEAD, ALIVE, ALIVE, ALIVE, DEAD, //
+        DEAD, DEAD, DEAD, DEAD, DEAD,
+      ]
+    );
+  }
+}
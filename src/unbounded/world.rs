@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+
+use rule::{Rule, State, DEAD};
+use threaded::world::{
+  compute_next_generation, Cells, Sector, World, WORD_BITS,
+};
+use utils::hash::hash_world;
+
+/// Width and height of a tile in cells. A tile is a single word wide, which
+/// `Neighbors::row` relies on to build rows from three neighboring tiles.
+pub const TILE_SIZE: usize = WORD_BITS;
+
+/// Coordinates of a tile: the tile `(tx, ty)` contains cells from
+/// `(tx * TILE_SIZE, ty * TILE_SIZE)` to
+/// `((tx + 1) * TILE_SIZE - 1, (ty + 1) * TILE_SIZE - 1)`.
+pub type TileCoord = (i64, i64);
+
+/// A square block of cells, stored like a bounded world.
+#[derive(Clone)]
+struct Tile {
+  cells: World,
+  /// Bounds of the cells which aren't dead, see [`World::bounds`]. They're
+  /// updated whenever the cells change, so the bounds of the world and the
+  /// neighbors a tile reaches don't need to look at the cells.
+  ///
+  /// [`World::bounds`]: ../../threaded/world/struct.World.html#method.bounds
+  bounds: Option<(usize, usize, usize, usize)>,
+}
+
+impl Tile {
+  fn new(cells: World) -> Self {
+    let bounds = cells.bounds();
+    Tile { cells, bounds }
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bounds.is_none()
+  }
+
+  fn set(&mut self, x: usize, y: usize, cell: State) {
+    let previous = self.cells.get(x, y);
+    if cell == previous {
+      return;
+    }
+
+    self.cells.set(x, y, cell);
+    self.bounds = match self.bounds {
+      _ if cell == DEAD => self.cells.bounds(),
+      None => Some((x, y, x, y)),
+      Some((x0, y0, x1, y1)) => {
+        Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)))
+      }
+    };
+  }
+
+  /// Returns `true` if any cell may be within `range` cells from the edge
+  /// (or the corner) of the tile in the direction `(dx, dy)`, i.e. if the
+  /// cell can affect the neighboring tile in that direction. It's decided
+  /// from the bounds of the tile, so it may also be `true` for a corner the
+  /// bounds overlap without any cell in it.
+  fn reaches(&self, dx: i64, dy: i64, range: usize) -> bool {
+    let near = |d: i64, low: usize, high: usize| match d {
+      -1 => low < range,
+      1 => high >= TILE_SIZE - range,
+      _ => true,
+    };
+
+    self.bounds.map_or(false, |(x0, y0, x1, y1)| {
+      near(dx, x0, x1) && near(dy, y0, y1)
+    })
+  }
+}
+
+/// A tile and its eight neighbors, which are borrowed from the world, so a
+/// tile is computed without copying its halo. Cells are addressed relative to
+/// the tile, its neighbors are at negative coordinates or beyond the tile.
+struct Neighbors<'a> {
+  tiles: [[Option<&'a World>; 3]; 3],
+}
+
+impl<'a> Cells for Neighbors<'a> {
+  fn cell(&self, x: isize, y: isize) -> State {
+    let ((tx, ty), (x, y)) = locate(x as i64, y as i64);
+    let tile = self.tiles[(ty + 1) as usize][(tx + 1) as usize];
+    tile.map_or(DEAD, |tile| tile.get(x, y))
+  }
+
+  fn row<'b>(
+    &'b self,
+    y: isize,
+    words: &'b mut [u64; 3],
+  ) -> (&'b [u64], isize) {
+    let ((_, ty), (_, y)) = locate(0, y as i64);
+    for (word, tile) in words.iter_mut().zip(&self.tiles[(ty + 1) as usize]) {
+      // a tile is a single word wide
+      *word = tile.map_or(0, |tile| tile.row(y as isize).unwrap()[0]);
+    }
+    (words, -(TILE_SIZE as isize))
+  }
+
+  fn has_dying_cells(&self) -> bool {
+    self
+      .tiles
+      .iter()
+      .flat_map(|row| row.iter())
+      .any(|tile| tile.map_or(false, |tile| tile.has_dying_cells()))
+  }
+}
+
+/// An unbounded world made of tiles, which are allocated when activity
+/// reaches them and freed when all their cells are dead. Coordinates are
+/// signed, so patterns can grow and travel in any direction.
+#[derive(Clone)]
+pub struct TiledWorld {
+  tiles: HashMap<TileCoord, Tile>,
+  /// Cells of freed tiles, which are reused for the next generations.
+  spare: Vec<World>,
+}
+
+impl TiledWorld {
+  pub fn new() -> Self {
+    TiledWorld {
+      tiles: HashMap::new(),
+      spare: Vec::new(),
+    }
+  }
+
+  pub fn get(&self, x: i64, y: i64) -> State {
+    let (coord, (x, y)) = locate(x, y);
+    self
+      .tiles
+      .get(&coord)
+      .map_or(DEAD, |tile| tile.cells.get(x, y))
+  }
+
+  pub fn set(&mut self, x: i64, y: i64, cell: State) {
+    let (coord, (x, y)) = locate(x, y);
+    if cell == DEAD && !self.tiles.contains_key(&coord) {
+      return;
+    }
+    self
+      .tiles
+      .entry(coord)
+      .or_insert_with(|| Tile::new(World::new(TILE_SIZE, TILE_SIZE)))
+      .set(x, y, cell);
+  }
+
+  /// Returns the coordinates of the allocated tiles.
+  pub fn tiles(&self) -> Vec<TileCoord> {
+    let mut tiles: Vec<TileCoord> = self.tiles.keys().cloned().collect();
+    tiles.sort();
+    tiles
+  }
+
+  /// Returns the number of alive cells.
+  pub fn population(&self) -> usize {
+    self
+      .tiles
+      .values()
+      .map(|tile| tile.cells.population())
+      .sum()
+  }
+
+  /// Returns the smallest rectangle `(x0, y0, x1, y1)` (inclusive) which
+  /// contains all cells which aren't dead, or `None` if the world is empty.
+  pub fn bounds(&self) -> Option<(i64, i64, i64, i64)> {
+    let size = TILE_SIZE as i64;
+    self
+      .tiles
+      .iter()
+      .filter_map(|(&(tx, ty), tile)| {
+        tile.bounds.map(|(x0, y0, x1, y1)| {
+          let (x, y) = (tx * size, ty * size);
+          (x + x0 as i64, y + y0 as i64, x + x1 as i64, y + y1 as i64)
+        })
+      })
+      .fold(None, |bounds, (x0, y0, x1, y1)| {
+        Some(match bounds {
+          None => (x0, y0, x1, y1),
+          Some((bx0, by0, bx1, by1)) => {
+            (bx0.min(x0), by0.min(y0), bx1.max(x1), by1.max(y1))
+          }
+        })
+      })
+  }
+
+  /// Computes a stable hash of the cells within [`bounds`], see
+  /// [`WorldHasher`]. The hash doesn't depend on the position of the pattern,
+  /// so a spaceship has the same hash after every full period.
+  ///
+  /// [`bounds`]: #method.bounds
+  /// [`WorldHasher`]: ../../utils/hash/struct.WorldHasher.html
+  pub fn hash(&self) -> u64 {
+    let (x0, y0, x1, y1) = self.bounds().unwrap_or((0, 0, -1, -1));
+    let width = (x1 - x0 + 1) as usize;
+    let height = (y1 - y0 + 1) as usize;
+    hash_world(width, height, |x, y| self.get(x0 + x as i64, y0 + y as i64))
+  }
+
+  /// Computes the next generation. Every allocated tile is computed, as well
+  /// as every free tile which an alive cell of a neighboring tile can reach
+  /// with the range of the rule. Tiles which become empty are freed.
+  ///
+  /// Tiles are computed into the cells of tiles freed in earlier
+  /// generations, since all tiles of this generation are needed until every
+  /// tile is computed. Then the cells of this generation are kept for the
+  /// next one, so tiles are only allocated when the world grows.
+  ///
+  /// Rules with a bounded grid aren't supported, the world is always an
+  /// infinite plane.
+  pub fn next_generation(&mut self, rule: &Rule) {
+    let range = rule.range();
+    debug_assert!(range <= TILE_SIZE, "the range is larger than a tile");
+
+    let mut candidates: HashSet<TileCoord> = HashSet::new();
+    for (&(tx, ty), tile) in &self.tiles {
+      candidates.insert((tx, ty));
+      for dy in -1..=1 {
+        for dx in -1..=1 {
+          if (dx, dy) != (0, 0) && tile.reaches(dx, dy, range) {
+            candidates.insert((tx + dx, ty + dy));
+          }
+        }
+      }
+    }
+
+    let mut computed = Vec::with_capacity(candidates.len());
+    for coord in candidates {
+      let mut cells = self
+        .spare
+        .pop()
+        .unwrap_or_else(|| World::new(TILE_SIZE, TILE_SIZE));
+      if rule.states() > 2 {
+        cells.allocate_dying_cells();
+      }
+      self.next_tile(coord, rule, &mut cells);
+      computed.push((coord, Tile::new(cells)));
+    }
+
+    let spare = &mut self.spare;
+    spare.extend(self.tiles.drain().map(|(_, tile)| tile.cells));
+    for (coord, tile) in computed {
+      if tile.is_empty() {
+        spare.push(tile.cells);
+      } else {
+        self.tiles.insert(coord, tile);
+      }
+    }
+  }
+
+  /// Computes the next generation of a tile from the tile and its neighbors
+  /// and writes it into `next`, which is as big as a tile.
+  fn next_tile(&self, (tx, ty): TileCoord, rule: &Rule, next: &mut World) {
+    let mut tiles = [[None; 3]; 3];
+    for (dy, row) in (-1..=1).zip(tiles.iter_mut()) {
+      for (dx, tile) in (-1..=1).zip(row.iter_mut()) {
+        *tile = self.tiles.get(&(tx + dx, ty + dy)).map(|tile| &tile.cells);
+      }
+    }
+
+    let sectors = [Sector::new(0, 0, TILE_SIZE, TILE_SIZE)];
+    let mut destination = next.split_mut(&sectors).pop().unwrap();
+    compute_next_generation(
+      &Neighbors { tiles },
+      &sectors[0],
+      rule,
+      &mut destination,
+      0,
+      0,
+    );
+  }
+}
+
+/// Returns the coordinates of the tiles which contain the cells of the
+/// rectangle `(x0, y0, x1, y1)` (inclusive).
+pub fn tiles_covering(x0: i64, y0: i64, x1: i64, y1: i64) -> Vec<TileCoord> {
+  let ((tx0, ty0), _) = locate(x0, y0);
+  let ((tx1, ty1), _) = locate(x1, y1);
+  (ty0..=ty1)
+    .flat_map(|ty| (tx0..=tx1).map(move |tx| (tx, ty)))
+    .collect()
+}
+
+/// Returns the coordinates of the tile which contains the cell `(x, y)` and
+/// the coordinates of the cell in that tile.
+pub fn locate(x: i64, y: i64) -> (TileCoord, (usize, usize)) {
+  let size = TILE_SIZE as i64;
+  // division rounding towards negative infinity
+  let floor_div = |value: i64| {
+    if value < 0 {
+      (value + 1) / size - 1
+    } else {
+      value / size
+    }
+  };
+
+  let (tx, ty) = (floor_div(x), floor_div(y));
+  (
+    (tx, ty),
+    ((x - tx * size) as usize, (y - ty * size) as usize),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rule::ALIVE;
+
+  #[test]
+  fn signed_coordinates() {
+    let mut world = TiledWorld::new();
+    world.set(-1, -1, ALIVE);
+    world.set(64, -65, ALIVE);
+    world.set(-200, 3, DEAD);
+
+    assert_eq!(world.get(-1, -1), ALIVE);
+    assert_eq!(world.get(64, -65), ALIVE);
+    assert_eq!(world.get(0, 0), DEAD);
+    assert_eq!(world.tiles(), vec![(-1, -1), (1, -2)]);
+    assert_eq!(world.bounds(), Some((-1, -65, 64, -1)));
+    assert_eq!(world.population(), 2);
+
+    assert_eq!(tiles_covering(-1, 0, 64, 10), vec![(-1, 0), (0, 0), (1, 0)]);
+
+    // bounds shrink when a cell on them dies
+    world.set(-1, -1, DEAD);
+    assert_eq!(world.bounds(), Some((64, -65, 64, -65)));
+  }
+
+  #[test]
+  fn glider_travels_across_tiles() {
+    let rule = Rule::default();
+
+    // a glider moving up and to the left
+    let mut world = TiledWorld::new();
+    for &(x, y) in &[(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)] {
+      world.set(x, y, ALIVE);
+    }
+    let hash = world.hash();
+
+    for _ in 0..400 {
+      world.next_generation(&rule);
+      assert_eq!(world.population(), 5);
+      assert!(world.tiles().len() <= 4);
+    }
+
+    // the glider moves by one cell diagonally every 4 generations
+    assert_eq!(world.hash(), hash);
+    assert_eq!(world.bounds(), Some((-100, -100, -98, -98)));
+    assert_eq!(world.tiles(), vec![(-2, -2)]);
+  }
+
+  #[test]
+  fn larger_than_life_reaches_neighboring_tiles() {
+    let rule = "R5,C0,M1,S34..58,B1..1".parse().unwrap();
+
+    let mut world = TiledWorld::new();
+    world.set(TILE_SIZE as i64 - 1, 0, ALIVE);
+    world.next_generation(&rule);
+
+    // a single cell gives birth to cells in its whole neighborhood
+    assert_eq!(world.bounds(), Some((58, -5, 68, 5)));
+    assert_eq!(world.tiles(), vec![(0, -1), (0, 0), (1, -1), (1, 0)]);
+  }
+}
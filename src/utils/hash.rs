@@ -0,0 +1,165 @@
+use rule::{State, ALIVE};
+
+/// Offset basis of the 64-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// Prime of the 64-bit FNV-1a hash.
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A hasher which computes a stable fingerprint of a world.
+///
+/// The hash doesn't depend on how a world is stored in memory: cells are fed
+/// row by row, packed into bytes (8 cells per byte, least significant bit
+/// first, the last byte of every row padded with zeros), and the resulting
+/// byte stream is hashed with 64-bit [FNV-1a] together with the dimensions of
+/// the world. This means that the same world has the same hash in every
+/// engine, on every machine and in every run, so hashes can be logged and
+/// compared between engines.
+///
+/// Only alive cells are packed into bits. Dying cells of "Generations" rules
+/// are hashed separately (the index of every such cell and its state) and
+/// mixed into the final hash, so worlds without dying cells hash the same way
+/// regardless of the number of states of the rule.
+///
+/// [FNV-1a]: http://www.isthe.com/chongo/tech/comp/fnv/index.html
+pub struct WorldHasher {
+  state: u64,
+  byte: u8,
+  bits: u8,
+  index: u64,
+  dying: Option<u64>,
+}
+
+impl WorldHasher {
+  pub fn new(width: usize, height: usize) -> Self {
+    let mut hasher = WorldHasher {
+      state: FNV_OFFSET_BASIS,
+      byte: 0,
+      bits: 0,
+      index: 0,
+      dying: None,
+    };
+
+    hasher.write_u64(width as u64);
+    hasher.write_u64(height as u64);
+    hasher
+  }
+
+  pub fn write_cell(&mut self, cell: State) {
+    if cell == ALIVE {
+      self.byte |= 1 << self.bits;
+    } else if cell > ALIVE {
+      let mut dying = self.dying.unwrap_or(FNV_OFFSET_BASIS);
+      for i in 0..8 {
+        dying = fnv_step(dying, (self.index >> (i * 8)) as u8);
+      }
+      self.dying = Some(fnv_step(dying, cell));
+    }
+    self.index += 1;
+
+    self.bits += 1;
+    if self.bits == 8 {
+      self.flush_byte();
+    }
+  }
+
+  /// Pads the current row to a whole byte. Must be called after the last cell
+  /// of every row.
+  pub fn end_row(&mut self) {
+    if self.bits > 0 {
+      self.flush_byte();
+    }
+  }
+
+  pub fn finish(mut self) -> u64 {
+    self.end_row();
+    if let Some(dying) = self.dying {
+      self.write_u64(dying);
+    }
+    self.state
+  }
+
+  fn flush_byte(&mut self) {
+    let byte = self.byte;
+    self.write_byte(byte);
+    self.byte = 0;
+    self.bits = 0;
+  }
+
+  fn write_u64(&mut self, value: u64) {
+    for i in 0..8 {
+      self.write_byte((value >> (i * 8)) as u8);
+    }
+  }
+
+  fn write_byte(&mut self, byte: u8) {
+    self.state = fnv_step(self.state, byte);
+  }
+}
+
+fn fnv_step(state: u64, byte: u8) -> u64 {
+  (state ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+}
+
+/// Hashes a world of the given size, reading cells with the `cell` function.
+pub fn hash_world<F>(width: usize, height: usize, cell: F) -> u64
+where
+  F: Fn(usize, usize) -> State,
+{
+  let mut hasher = WorldHasher::new(width, height);
+
+  for y in 0..height {
+    for x in 0..width {
+      hasher.write_cell(cell(x, y));
+    }
+    hasher.end_row();
+  }
+
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rule::DEAD;
+
+  fn alive(cell: bool) -> State {
+    if cell {
+      ALIVE
+    } else {
+      DEAD
+    }
+  }
+
+  #[test]
+  fn empty_worlds_of_different_sizes() {
+    let a = hash_world(4, 2, |_, _| DEAD);
+    let b = hash_world(2, 4, |_, _| DEAD);
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn single_cell_changes_hash() {
+    let a = hash_world(10, 10, |_, _| DEAD);
+    let b = hash_world(10, 10, |x, y| alive(x == 9 && y == 9));
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn dying_cells_change_hash() {
+    let a = hash_world(10, 10, |x, _| alive(x == 1));
+    let b = hash_world(10, 10, |x, _| if x == 1 { 2 } else { DEAD });
+    let c = hash_world(10, 10, |x, _| if x == 1 { 3 } else { DEAD });
+    let d = hash_world(10, 10, |x, _| if x == 2 { 2 } else { DEAD });
+    let empty = hash_world(10, 10, |_, _| DEAD);
+    for &(x, y) in &[(a, b), (b, c), (b, d), (b, empty)] {
+      assert_ne!(x, y);
+    }
+  }
+
+  #[test]
+  fn stable_value() {
+    // a vertical line in the middle column: every row is packed into 0b010
+    let hash = hash_world(3, 3, |x, _| alive(x == 1));
+    assert_eq!(hash, 0x29fe_631b_d7d1_7be5);
+  }
+}
@@ -1 +1,5 @@
+pub mod hash;
+pub mod render;
 pub mod result;
+pub mod summed_area;
+pub mod time;
@@ -0,0 +1,38 @@
+use std::fmt;
+
+use rule::{State, ALIVE, DEAD};
+
+/// Colors of dying states in the 256-color ANSI palette, fading from yellow
+/// to red as cells get closer to death.
+const DYING_COLORS: [u8; 6] = [226, 220, 214, 208, 202, 196];
+
+/// Returns the character which represents a cell in plain text.
+pub fn symbol(state: State) -> char {
+  match state {
+    DEAD => '.',
+    ALIVE => 'x',
+    _ => 'o',
+  }
+}
+
+/// A cell drawn in a terminal. Dead cells are blank, dying cells of
+/// "Generations" rules are colored by how many generations they have left.
+pub struct TerminalCell {
+  pub state: State,
+  pub states: u8,
+}
+
+impl fmt::Display for TerminalCell {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.state {
+      DEAD => write!(f, " "),
+      ALIVE => write!(f, "x"),
+      state => {
+        let dying_states = usize::from(self.states.max(3) - 2);
+        let index = usize::from(state - 2) * DYING_COLORS.len() / dying_states;
+        let color = DYING_COLORS[index.min(DYING_COLORS.len() - 1)];
+        write!(f, "\x1b[38;5;{}mo\x1b[0m", color)
+      }
+    }
+  }
+}
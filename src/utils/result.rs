@@ -16,6 +16,9 @@ pub trait DescribeErr {
   /// Consider the following function:
   ///
   /// ```
+  /// # use std::fs::File;
+  /// # use std::io;
+  /// # use std::path::Path;
   /// fn copy_file(src: &Path, dest: &Path) -> io::Result<u64> {
   ///   let mut src_file = File::open(src)?;
   ///   let mut dest_file = File::create(dest)?;
@@ -33,7 +36,11 @@ pub trait DescribeErr {
   /// So, let's add the [`describe_err`] function to solve this problem:
   ///
   /// ```
-  /// use utils::result::DescribeErr;
+  /// # extern crate game_of_life_cluster;
+  /// use game_of_life_cluster::utils::result::DescribeErr;
+  /// # use std::fs::File;
+  /// # use std::io;
+  /// # use std::path::Path;
   ///
   /// fn copy_file(src: &Path, dest: &Path) -> io::Result<u64> {
   ///   let mut src_file = File::open(src)
@@ -48,7 +55,7 @@ pub trait DescribeErr {
   /// Now, if something bad happens, this function will return an error with
   /// message like this:
   ///
-  /// ```
+  /// ```text
   /// cannot open source file: No such file or directory
   /// ```
   ///
@@ -56,7 +63,11 @@ pub trait DescribeErr {
   /// because it can use any type that implements [`Display`]:
   ///
   /// ```
-  /// use utils::result::DescribeErr;
+  /// # extern crate game_of_life_cluster;
+  /// use game_of_life_cluster::utils::result::DescribeErr;
+  /// # use std::fs::File;
+  /// # use std::io;
+  /// # use std::path::Path;
   ///
   /// fn copy_file(src: &Path, dest: &Path) -> io::Result<u64> {
   ///   let mut src_file = File::open(src)
@@ -70,7 +81,7 @@ pub trait DescribeErr {
   ///
   /// This version will give you an error like this:
   ///
-  /// ```
+  /// ```text
   /// path/to/file/a: Permission denied
   /// ```
   ///
@@ -0,0 +1,82 @@
+/// A summed-area table of a rectangular region: for every cell it stores the
+/// number of alive cells above and to the left of it, so the number of alive
+/// cells in any rectangle is computed with four lookups regardless of its
+/// size. This keeps counting neighbors of Larger than Life rules fast for
+/// large ranges.
+pub struct SummedAreaTable {
+  width: usize,
+  height: usize,
+  sums: Vec<u32>,
+}
+
+impl SummedAreaTable {
+  /// Builds the table of a `width` x `height` region, reading cells with the
+  /// `alive` function.
+  pub fn new<F>(width: usize, height: usize, alive: F) -> Self
+  where
+    F: Fn(usize, usize) -> bool,
+  {
+    // the table has an extra zero row and column, so that rectangles
+    // touching the top or the left edge don't need special cases
+    let stride = width + 1;
+    let mut sums = vec![0; stride * (height + 1)];
+
+    for y in 0..height {
+      let mut row_sum = 0;
+      for x in 0..width {
+        row_sum += alive(x, y) as u32;
+        sums[(x + 1) + (y + 1) * stride] = sums[(x + 1) + y * stride] + row_sum;
+      }
+    }
+
+    SummedAreaTable {
+      width,
+      height,
+      sums,
+    }
+  }
+
+  /// Returns the number of alive cells in the rectangle from `(x0, y0)` to
+  /// `(x1, y1)`, both inclusive. The rectangle is clipped to the region, cells
+  /// outside of it are treated as dead.
+  pub fn sum(&self, x0: isize, y0: isize, x1: isize, y1: isize) -> u32 {
+    let clip = |value: isize, size: usize| value.max(0).min(size as isize);
+
+    let x0 = clip(x0, self.width) as usize;
+    let y0 = clip(y0, self.height) as usize;
+    let x1 = clip(x1 + 1, self.width) as usize;
+    let y1 = clip(y1 + 1, self.height) as usize;
+    if x0 >= x1 || y0 >= y1 {
+      return 0;
+    }
+
+    let stride = self.width + 1;
+    self.sums[x1 + y1 * stride] + self.sums[x0 + y0 * stride]
+      - self.sums[x0 + y1 * stride]
+      - self.sums[x1 + y0 * stride]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sums_match_brute_force() {
+    let alive = |x: usize, y: usize| (x * 7 + y * 13) % 5 < 2;
+    let table = SummedAreaTable::new(9, 6, alive);
+
+    for y0 in -2..8 {
+      for x0 in -2..11 {
+        for &(w, h) in &[(0, 0), (1, 1), (3, 2), (12, 9)] {
+          let (x1, y1) = (x0 + w, y0 + h);
+          let expected = (y0.max(0)..(y1 + 1).min(6))
+            .flat_map(|y| (x0.max(0)..(x1 + 1).min(9)).map(move |x| (x, y)))
+            .filter(|&(x, y)| alive(x as usize, y as usize))
+            .count();
+          assert_eq!(table.sum(x0, y0, x1, y1), expected as u32);
+        }
+      }
+    }
+  }
+}
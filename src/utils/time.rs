@@ -0,0 +1,18 @@
+use std::time::Instant;
+
+/// Runs `f` and logs how long it took at the debug level, with the target
+/// `time`.
+pub fn measure_time<F, R>(name: &str, f: F) -> R
+where
+  F: FnOnce() -> R,
+{
+  let start_time = Instant::now();
+  let ret = f();
+  debug!(
+    target: "time",
+    "{} - {} µs",
+    name,
+    start_time.elapsed().as_micros()
+  );
+  ret
+}
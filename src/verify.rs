@@ -0,0 +1,322 @@
+extern crate rand;
+use self::rand::prng::XorShiftRng;
+use self::rand::{Rng, SeedableRng};
+
+use failure::{err_msg, Error};
+use std::fmt;
+
+use engine::{Backend, Engine};
+use rule::{Rule, State};
+use utils::hash::hash_world;
+
+/// Maximum number of differing cells listed in a divergence report.
+const MAX_REPORTED_CELLS: usize = 20;
+
+/// The initial state which is replayed by every backend.
+struct InitialWorld {
+  width: usize,
+  height: usize,
+  cells: Vec<State>,
+}
+
+impl InitialWorld {
+  fn generate(width: usize, height: usize, seed: u64) -> Self {
+    let mut seed_bytes = [0; 16];
+    for i in 0..8 {
+      seed_bytes[i] = (seed >> (i * 8)) as u8;
+      seed_bytes[i + 8] = (seed >> (i * 8)) as u8;
+    }
+
+    let mut rng = XorShiftRng::from_seed(seed_bytes);
+    let cells = (0..width * height)
+      .map(|_| rng.gen_bool(0.5) as State)
+      .collect();
+
+    InitialWorld {
+      width,
+      height,
+      cells,
+    }
+  }
+
+  fn get(&self, x: usize, y: usize) -> State {
+    self.cells[x + y * self.width]
+  }
+}
+
+/// The outcome of [`run`], which is printed by its `Display` implementation.
+///
+/// [`run`]: fn.run.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+  pub backends: (Backend, Backend),
+  /// The hash of the initial world.
+  pub initial_hash: u64,
+  /// The number of generations after the initial world which were compared.
+  pub generations: u64,
+  /// The hash of the last compared generation of the first backend.
+  pub final_hash: u64,
+  pub divergence: Option<Divergence>,
+}
+
+/// The first generation in which the hashes of the backends differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+  pub generation: u64,
+  pub hashes: (u64, u64),
+  /// The cells of the initial area which differ, with their states in both
+  /// backends.
+  pub cells: Vec<(usize, usize, State, State)>,
+}
+
+/// Runs two backends with `rule` from the same seed and compares hashes of every
+/// generation. If the hashes diverge, both backends are replayed up to the
+/// first diverged generation and the cells which differ are reported.
+///
+/// Bounded and unbounded backends can't be compared, because cells which
+/// leave a bounded world disappear. Unbounded backends compare the hashes of
+/// their whole patterns (see [`Engine::hash`]), only the reported cells are
+/// limited to the area of the initial world.
+///
+/// [`Engine::hash`]: ../engine/trait.Engine.html#tymethod.hash
+pub fn run(
+  backends: (Backend, Backend),
+  seed: u64,
+  generations: u64,
+  width: usize,
+  height: usize,
+  rule: Rule,
+) -> Result<Report, Error> {
+  let (backend_a, backend_b) = backends;
+  if backend_a.is_unbounded(rule) != backend_b.is_unbounded(rule) {
+    return Err(err_msg(format!(
+      "{} and {} can't be compared, only one of them is unbounded",
+      backend_a, backend_b
+    )));
+  }
+
+  // rules with a bounded grid define the size of the world themselves
+  let (width, height) = match rule.grid() {
+    Some(grid) => {
+      info!(
+        target: "verify",
+        "using the {}x{} world of the rule's bounded grid",
+        grid.width,
+        grid.height
+      );
+      (grid.width, grid.height)
+    }
+    None => (width, height),
+  };
+
+  let initial_world = InitialWorld::generate(width, height, seed);
+  let initial_hash = hash_world(width, height, |x, y| initial_world.get(x, y));
+
+  info!(
+    target: "verify",
+    "replaying {} generations on {}",
+    generations,
+    backend_a
+  );
+  let mut expected_hashes = Vec::with_capacity(generations as usize + 1);
+  replay(backend_a, &initial_world, rule, |generation, hash, _| {
+    expected_hashes.push(hash);
+    Ok(generation < generations)
+  })?;
+
+  info!(
+    target: "verify",
+    "replaying {} generations on {}",
+    generations,
+    backend_b
+  );
+  let mut divergence = None;
+  replay(
+    backend_b,
+    &initial_world,
+    rule,
+    |generation, hash, engine| {
+      let expected_hash = expected_hashes[generation as usize];
+      debug!(
+        target: "verify",
+        "generation #{}: {} = {:016x}, {} = {:016x}",
+        generation,
+        backend_a,
+        expected_hash,
+        backend_b,
+        hash
+      );
+
+      if hash != expected_hash {
+        let cells = engine.read_region(0, 0, width, height)?;
+        divergence = Some((generation, expected_hash, hash, cells));
+        return Ok(false);
+      }
+
+      Ok(generation < generations)
+    },
+  )?;
+
+  let (generation, hash_a, hash_b, cells_b) = match divergence {
+    Some(divergence) => divergence,
+    None => {
+      return Ok(Report {
+        backends,
+        initial_hash,
+        generations,
+        final_hash: expected_hashes[generations as usize],
+        divergence: None,
+      });
+    }
+  };
+
+  info!(
+    target: "verify",
+    "replaying {} up to generation #{}",
+    backend_a,
+    generation
+  );
+  let mut cells_a = Vec::new();
+  replay(
+    backend_a,
+    &initial_world,
+    rule,
+    |current_generation, _, engine| {
+      if current_generation < generation {
+        return Ok(true);
+      }
+
+      cells_a = engine.read_region(0, 0, width, height)?;
+      Ok(false)
+    },
+  )?;
+
+  let cells = (0..height)
+    .flat_map(|y| (0..width).map(move |x| (x, y)))
+    .map(|(x, y)| (x, y, cells_a[x + y * width], cells_b[x + y * width]))
+    .filter(|&(_, _, a, b)| a != b)
+    .collect();
+
+  Ok(Report {
+    backends,
+    initial_hash,
+    generations: generation,
+    final_hash: hash_a,
+    divergence: Some(Divergence {
+      generation,
+      hashes: (hash_a, hash_b),
+      cells,
+    }),
+  })
+}
+
+impl Report {
+  /// Returns an error if the backends have diverged.
+  pub fn check(&self) -> Result<(), Error> {
+    let (backend_a, backend_b) = self.backends;
+    match self.divergence {
+      Some(ref divergence) => Err(err_msg(format!(
+        "{} and {} diverged at generation #{}",
+        backend_a, backend_b, divergence.generation
+      ))),
+      None => Ok(()),
+    }
+  }
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (backend_a, backend_b) = self.backends;
+    writeln!(f, "generation #0 hash = {:016x}", self.initial_hash)?;
+
+    let divergence = match self.divergence {
+      Some(ref divergence) => divergence,
+      None => {
+        return writeln!(
+          f,
+          "{} and {} agree on {} generations, final hash = {:016x}",
+          backend_a, backend_b, self.generations, self.final_hash
+        );
+      }
+    };
+
+    let (hash_a, hash_b) = divergence.hashes;
+    writeln!(
+      f,
+      "first divergence at generation #{}",
+      divergence.generation
+    )?;
+    writeln!(f, "  {} hash = {:016x}", backend_a, hash_a)?;
+    writeln!(f, "  {} hash = {:016x}", backend_b, hash_b)?;
+
+    writeln!(f, "{} cells differ:", divergence.cells.len())?;
+    for &(x, y, a, b) in divergence.cells.iter().take(MAX_REPORTED_CELLS) {
+      writeln!(
+        f,
+        "  ({}, {}): {} = {}, {} = {}",
+        x, y, backend_a, a, backend_b, b
+      )?;
+    }
+    if divergence.cells.len() > MAX_REPORTED_CELLS {
+      writeln!(f, "  ...")?;
+    }
+    Ok(())
+  }
+}
+
+/// Runs `backend` with `rule` from `initial_world` until `on_generation`
+/// returns `false`, which is checked before every step. `on_generation`
+/// receives the number of the generation (starting with 0 for the loaded
+/// world), the hash of the world and the engine, whose cells are only read
+/// when they're reported.
+fn replay<F>(
+  backend: Backend,
+  initial_world: &InitialWorld,
+  rule: Rule,
+  mut on_generation: F,
+) -> Result<(), Error>
+where
+  F: FnMut(u64, u64, &dyn Engine) -> Result<bool, Error>,
+{
+  let width = initial_world.width;
+  let height = initial_world.height;
+
+  let mut engine = backend.create(rule)?;
+  engine.load(width, height, &initial_world.cells)?;
+
+  // the loaded world is compared too
+  loop {
+    let hash = engine.hash()?;
+    if !on_generation(engine.generation(), hash, &*engine)? {
+      break;
+    }
+
+    engine.step(1)?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use unbounded::UnboundedEngine;
+
+  #[test]
+  fn generations_are_counted_from_the_loaded_world() {
+    let backends = (Backend::Unbounded, Backend::Hashlife);
+    let rule = Rule::default();
+    let initial_world = InitialWorld::generate(30, 20, 7);
+    let mut engine = UnboundedEngine::new(rule).unwrap();
+    engine.load(30, 20, &initial_world.cells).unwrap();
+
+    for &generations in &[0, 1, 5] {
+      engine.step(generations - engine.generation()).unwrap();
+      let report = run(backends, 7, generations, 30, 20, rule).unwrap();
+      assert_eq!(report.generations, generations);
+      assert_eq!(report.final_hash, engine.hash().unwrap());
+      assert_eq!(report.divergence, None);
+      assert!(report.check().is_ok());
+    }
+  }
+}
@@ -0,0 +1,107 @@
+/* Drives the C API like an embedding tool would. Prints the first failed
+   check and exits with 1 if something is wrong. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "game_of_life_cluster.h"
+
+#define CHECK(condition)                                                     \
+  do {                                                                       \
+    if (!(condition)) {                                                      \
+      const char *error = gol_last_error();                                  \
+      fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n",          \
+              __FILE__, __LINE__, #condition, error ? error : "none");       \
+      return 1;                                                              \
+    }                                                                        \
+  } while (0)
+
+static const char *GLIDER = "#N Glider\nx = 3, y = 3\nbob$2bo$3o!\n";
+
+/* A glider on a 16x16 torus is back where it started after 64 generations. */
+static int glider_on_torus(void) {
+  GolWorld *world = gol_world_new("threaded", "B3/S23:T16,16");
+  CHECK(world != NULL);
+  CHECK(gol_world_load_rle(world, GLIDER) == GOL_STATUS_OK);
+
+  uint8_t start[16 * 16], end[16 * 16];
+  CHECK(gol_world_read_region(world, 0, 0, 16, 16, start) == GOL_STATUS_OK);
+
+  uint64_t population = 0;
+  CHECK(gol_world_population(world, &population) == GOL_STATUS_OK);
+  CHECK(population == 5);
+
+  CHECK(gol_world_step(world, 64) == GOL_STATUS_OK);
+  CHECK(gol_world_generation(world) == 64);
+  CHECK(gol_world_read_region(world, 0, 0, 16, 16, end) == GOL_STATUS_OK);
+  CHECK(memcmp(start, end, sizeof(start)) == 0);
+
+  gol_world_free(world);
+  return 0;
+}
+
+/* A glider in an unbounded world moves by (1, 1) every 4 generations. */
+static int glider_on_plane(void) {
+  GolWorld *world = gol_world_new("hashlife", NULL);
+  CHECK(world != NULL);
+  CHECK(gol_world_load_rle(world, GLIDER) == GOL_STATUS_OK);
+  CHECK(gol_world_step(world, 400) == GOL_STATUS_OK);
+
+  uint8_t cells[3 * 3];
+  CHECK(gol_world_read_region(world, 100, 100, 3, 3, cells) == GOL_STATUS_OK);
+  const uint8_t glider[3 * 3] = {0, 1, 0, 0, 0, 1, 1, 1, 1};
+  CHECK(memcmp(cells, glider, sizeof(cells)) == 0);
+
+  /* brute force gets the same hash */
+  uint64_t hashlife_hash = 0, unbounded_hash = 0;
+  CHECK(gol_world_hash(world, &hashlife_hash) == GOL_STATUS_OK);
+  GolWorld *unbounded = gol_world_new("unbounded", NULL);
+  CHECK(unbounded != NULL);
+  CHECK(gol_world_load_rle(unbounded, GLIDER) == GOL_STATUS_OK);
+  CHECK(gol_world_step(unbounded, 400) == GOL_STATUS_OK);
+  CHECK(gol_world_hash(unbounded, &unbounded_hash) == GOL_STATUS_OK);
+  CHECK(hashlife_hash == unbounded_hash);
+
+  gol_world_free(unbounded);
+  gol_world_free(world);
+  return 0;
+}
+
+/* Rules can be replaced, which clears the world. */
+static int set_rule(void) {
+  GolWorld *world = gol_world_new(NULL, NULL);
+  CHECK(world != NULL);
+  CHECK(gol_world_set_rule(world, "B2/S/C3:T8,8") == GOL_STATUS_OK);
+  CHECK(gol_world_load_rle(world, "x = 2, y = 1\n2o!") == GOL_STATUS_OK);
+  CHECK(gol_world_step(world, 1) == GOL_STATUS_OK);
+
+  /* Brian's Brain: the pair becomes dying and gives birth above and below */
+  uint8_t cells[2 * 3];
+  CHECK(gol_world_read_region(world, 3, 2, 2, 3, cells) == GOL_STATUS_OK);
+  const uint8_t expected[2 * 3] = {1, 1, 2, 2, 1, 1};
+  CHECK(memcmp(cells, expected, sizeof(cells)) == 0);
+
+  gol_world_free(world);
+  return 0;
+}
+
+/* Errors are reported instead of crashing. */
+static int errors(void) {
+  CHECK(gol_world_new("quantum", NULL) == NULL);
+  CHECK(strstr(gol_last_error(), "quantum") != NULL);
+  CHECK(gol_world_new(NULL, "B3/S23/X") == NULL);
+
+  GolWorld *world = gol_world_new(NULL, NULL);
+  CHECK(world != NULL);
+  CHECK(gol_world_load_rle(world, "bo!") == GOL_STATUS_ERROR);
+  CHECK(gol_world_set_rule(world, "nonsense") == GOL_STATUS_ERROR);
+  CHECK(gol_world_population(world, NULL) == GOL_STATUS_ERROR);
+  CHECK(gol_world_step(NULL, 1) == GOL_STATUS_ERROR);
+  gol_world_free(world);
+  gol_world_free(NULL);
+  return 0;
+}
+
+int main(void) {
+  return glider_on_torus() || glider_on_plane() || set_rule() || errors();
+}
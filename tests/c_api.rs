@@ -0,0 +1,81 @@
+//! Checks the generated header, compiles the C test program against the
+//! shared library and the header and runs it.
+
+extern crate cbindgen;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The header of the C API, see `src/ffi.rs`.
+const HEADER: &str = "include/game_of_life_cluster.h";
+
+/// The directory of the shared library, which is built next to the
+/// directory of test executables.
+fn library_dir() -> PathBuf {
+  let executable = env::current_exe().unwrap();
+  executable.parent().unwrap().parent().unwrap().to_owned()
+}
+
+/// Generates the header with cbindgen. The committed header is only
+/// rewritten when `GOL_GENERATE_HEADER` is set, otherwise it must match.
+#[test]
+fn header_is_up_to_date() {
+  let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let config =
+    cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+  let mut header = Vec::new();
+  cbindgen::Builder::new()
+    .with_crate(crate_dir)
+    .with_config(config)
+    .generate()
+    .expect("can't generate the header of the C API")
+    .write(&mut header);
+
+  let path = crate_dir.join(HEADER);
+  if env::var_os("GOL_GENERATE_HEADER").is_some() {
+    fs::write(&path, &header).unwrap();
+  } else {
+    let committed = fs::read(&path).unwrap();
+    assert!(
+      committed == header,
+      "{} is out of date, run the tests with GOL_GENERATE_HEADER=1",
+      HEADER
+    );
+  }
+}
+
+// Windows toolchains have neither `cc` nor rpaths, and find DLLs on `PATH`
+#[cfg(unix)]
+#[test]
+fn c_program() {
+  let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let library_dir = library_dir();
+  let program = library_dir.join("c_api_world");
+
+  let mut rpath = OsString::from("-Wl,-rpath,");
+  rpath.push(&library_dir);
+  let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".into()))
+    .arg(crate_dir.join("tests/c/world.c"))
+    .arg("-std=c99")
+    .arg("-Wall")
+    .arg("-Werror")
+    .arg("-I")
+    .arg(crate_dir.join("include"))
+    .arg("-L")
+    .arg(&library_dir)
+    // the program finds the library without LD_LIBRARY_PATH or its
+    // counterparts on other platforms
+    .arg(rpath)
+    .arg("-lgame_of_life_cluster")
+    .arg("-o")
+    .arg(&program)
+    .status()
+    .expect("can't run the C compiler");
+  assert!(status.success(), "the C program doesn't compile");
+
+  let status = Command::new(&program).status().unwrap();
+  assert!(status.success(), "the C program failed");
+}